@@ -0,0 +1,26 @@
+CREATE SEQUENCE ach_trace_seq MINVALUE 1 MAXVALUE 9999999 CYCLE;
+
+CREATE TABLE ach_files (
+    id uuid PRIMARY KEY,
+    file_id_modifier char(1) NOT NULL,
+    entry_count integer NOT NULL,
+    total_credit decimal(19,2) NOT NULL,
+    entry_hash bigint NOT NULL,
+    content text NOT NULL,
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE ach_file_entries (
+    trace_number varchar(15) PRIMARY KEY,
+    ach_file_id uuid NOT NULL REFERENCES ach_files(id),
+    transaction_id uuid NOT NULL UNIQUE REFERENCES transactions(id),
+    bank_account_id uuid NOT NULL,
+    amount decimal(19,2) NOT NULL,
+    status varchar(20) NOT NULL DEFAULT 'sent',
+    return_code char(3),
+    returned_at timestamp,
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_ach_files_created_at ON ach_files(created_at);
+CREATE INDEX idx_ach_file_entries_ach_file_id ON ach_file_entries(ach_file_id);
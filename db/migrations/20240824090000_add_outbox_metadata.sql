@@ -0,0 +1 @@
+ALTER TABLE outbox ADD COLUMN metadata JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,19 @@
+-- Rows created before tenants were enforced belong to tenant 0, which no
+-- token can act as, so they stay hidden until assigned to a tenant.
+ALTER TABLE house_accounts ADD COLUMN tenant_id integer NOT NULL DEFAULT 0;
+ALTER TABLE house_accounts ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE house_accounts DROP CONSTRAINT unique_account_type_currency_status;
+ALTER TABLE house_accounts
+ADD CONSTRAINT unique_tenant_account_type_currency_status UNIQUE (tenant_id, account_type, currency, status);
+
+ALTER TABLE transactions ADD COLUMN tenant_id integer NOT NULL DEFAULT 0;
+ALTER TABLE transactions ALTER COLUMN tenant_id DROP DEFAULT;
+CREATE INDEX idx_transactions_tenant_id ON transactions(tenant_id);
+
+ALTER TABLE ach_files ADD COLUMN tenant_id integer NOT NULL DEFAULT 0;
+ALTER TABLE ach_files ALTER COLUMN tenant_id DROP DEFAULT;
+CREATE INDEX idx_ach_files_tenant_id ON ach_files(tenant_id);
+
+-- Views keep the tenant in their payload, these back the ownership lookups.
+CREATE INDEX idx_bank_account_views_tenant_id ON bank_account_views(((payload->>'tenant_id')::integer));
+CREATE INDEX idx_ledger_views_tenant_id ON ledger_views(((payload->>'tenant_id')::integer));
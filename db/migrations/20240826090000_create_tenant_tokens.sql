@@ -0,0 +1,16 @@
+CREATE TABLE tenant_tokens (
+    jti uuid PRIMARY KEY,  -- `jti` claim of the token
+    tenant_id integer NOT NULL REFERENCES tenants(id),
+    kind varchar(20) NOT NULL,  -- service or access
+    scope varchar(255) NOT NULL,
+    expires_at timestamp NOT NULL,
+    -- The token stops working at this instant, it is still in the future while
+    -- a rotated token is in its grace period.
+    revoked_at timestamp,
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_tenant_tokens_tenant_id ON tenant_tokens(tenant_id);
+
+-- SHA-256 of the secret exchanged for access tokens at /v1/oauth/token.
+ALTER TABLE tenants ADD COLUMN client_secret_hash varchar(64);
@@ -0,0 +1,9 @@
+CREATE TABLE signing_keys (
+    kid varchar(64) PRIMARY KEY,  -- `kid` header of the tokens signed with the key
+    algorithm varchar(10) NOT NULL,  -- RS256 or EdDSA
+    private_key text NOT NULL,  -- PKCS#8 PEM
+    public_jwk jsonb NOT NULL,  -- published at /.well-known/jwks.json
+    status varchar(20) NOT NULL DEFAULT 'active',  -- active or retired
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    retired_at timestamp
+);
@@ -0,0 +1,11 @@
+CREATE TABLE tenant_audit_log (
+    id bigserial PRIMARY KEY,
+    tenant_id integer NOT NULL REFERENCES tenants(id),  -- tenant the change applies to
+    action varchar(50) NOT NULL,  -- created, suspended, scope_updated, ...
+    actor_tenant_id integer NOT NULL,  -- tenant of the token that made the change
+    actor varchar(255) NOT NULL,  -- `sub` claim of that token
+    detail jsonb NOT NULL DEFAULT '{}',
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_tenant_audit_log_tenant_id ON tenant_audit_log(tenant_id, id);
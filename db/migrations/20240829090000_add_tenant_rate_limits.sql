@@ -0,0 +1,3 @@
+-- Per route class overrides of the `rate_limit` settings, e.g.
+-- {"money": {"rate": 5, "burst": 20, "daily_quota": 10000}}
+ALTER TABLE tenants ADD COLUMN rate_limits jsonb NOT NULL DEFAULT '{}';
@@ -0,0 +1,20 @@
+-- Custom assets registered by tenants (loyalty points, stablecoins, vouchers),
+-- usable wherever an ISO 4217 currency is accepted.
+CREATE TABLE assets (
+    code varchar(12) PRIMARY KEY,  -- 4 to 12 characters, never clashes with ISO 4217
+    tenant_id integer NOT NULL REFERENCES tenants(id),
+    name varchar(255) NOT NULL,
+    minor_units integer NOT NULL CHECK (minor_units BETWEEN 0 AND 18),
+    created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_assets_tenant_id ON assets(tenant_id);
+
+ALTER TABLE house_accounts ALTER COLUMN currency TYPE varchar(12);
+ALTER TABLE transactions ALTER COLUMN currency TYPE varchar(12);
+ALTER TABLE journal_lines ALTER COLUMN currency TYPE varchar(12);
+
+-- Keep the scale of the amounts, up to 18 decimals for custom assets.
+ALTER TABLE transactions ALTER COLUMN amount TYPE numeric;
+ALTER TABLE journal_lines ALTER COLUMN debit_amount TYPE numeric;
+ALTER TABLE journal_lines ALTER COLUMN credit_amount TYPE numeric;
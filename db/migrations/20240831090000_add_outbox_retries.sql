@@ -0,0 +1,13 @@
+ALTER TABLE outbox
+    ADD COLUMN status varchar(20) NOT NULL DEFAULT 'pending',
+    ADD COLUMN attempts integer NOT NULL DEFAULT 0,
+    ADD COLUMN next_attempt_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN last_error text,
+    -- Set when a dead-lettered withdrawal gave its hold back, a retry must
+    -- place it again.
+    ADD COLUMN hold_released boolean NOT NULL DEFAULT false;
+
+UPDATE outbox SET status = 'processed' WHERE processed;
+
+CREATE INDEX idx_outbox_pending ON outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_outbox_transaction_id ON outbox(transaction_id);
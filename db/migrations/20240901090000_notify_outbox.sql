@@ -0,0 +1,15 @@
+-- Wakes the outbox processors as soon as a command is queued or a
+-- dead-lettered one is retried.
+CREATE OR REPLACE FUNCTION notify_outbox()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('outbox', NEW.id::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_outbox_pending
+AFTER INSERT OR UPDATE OF status ON outbox
+FOR EACH ROW
+WHEN (NEW.status = 'pending')
+EXECUTE FUNCTION notify_outbox();
@@ -0,0 +1,5 @@
+-- Instance holding a pending command and until when, an expired claim can be
+-- taken over by another instance.
+ALTER TABLE outbox
+    ADD COLUMN claimed_by varchar(64),
+    ADD COLUMN claimed_until timestamp;
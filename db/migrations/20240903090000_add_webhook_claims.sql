@@ -0,0 +1,5 @@
+-- Instance delivering a pending webhook and until when, an expired claim can
+-- be taken over by another instance.
+ALTER TABLE webhook_deliveries
+    ADD COLUMN claimed_by varchar(64),
+    ADD COLUMN claimed_until timestamp;
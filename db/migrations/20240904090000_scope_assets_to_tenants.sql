@@ -0,0 +1,11 @@
+-- Asset codes are unique per tenant, two tenants can register the same code.
+-- Amounts carry the code qualified with its tenant, `<tenant_id>:<code>`.
+ALTER TABLE assets DROP CONSTRAINT assets_pkey;
+ALTER TABLE assets ADD PRIMARY KEY (tenant_id, code);
+
+-- Covered by the primary key.
+DROP INDEX idx_assets_tenant_id;
+
+ALTER TABLE house_accounts ALTER COLUMN currency TYPE varchar(24);
+ALTER TABLE transactions ALTER COLUMN currency TYPE varchar(24);
+ALTER TABLE journal_lines ALTER COLUMN currency TYPE varchar(24);
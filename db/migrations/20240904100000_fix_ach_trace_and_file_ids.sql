@@ -0,0 +1,7 @@
+-- Trace numbers key the entries, so the sequence stops at its maximum rather
+-- than handing out numbers already used.
+ALTER SEQUENCE ach_trace_seq NO CYCLE;
+
+-- Files created on the same day need distinct file ID modifiers.
+CREATE UNIQUE INDEX ach_files_created_on_file_id_modifier_key
+    ON ach_files ((created_at::date), file_id_modifier);
@@ -1,14 +1,21 @@
-use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use chrono::Duration;
 use postgres_es::default_postgress_pool;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use tracing::debug;
+use uuid::Uuid;
 
-use crate::{configs::settings::SETTINGS, repository::adapter::Adapter};
+use crate::{
+    configs::settings::SETTINGS,
+    domain::tenant::{TenantAuditEntry, TOKEN_KIND_SERVICE},
+    repository::adapter::{Adapter, DatabaseClient},
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+use super::keys::KeyRing;
+use super::token::{generate_client_secret, hash_secret, issue_token};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub iss: String, // Issuer
     pub sub: String, // Subject (often user_id)
@@ -19,6 +26,16 @@ pub struct Claims {
     #[serde(rename = "scope")]
     pub scopes: Vec<String>,
     pub tenant_id: i32,
+    // Id of the token in `tenant_tokens`, missing on tokens issued before
+    // revocation was supported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
+}
+
+pub struct TenantCredentials {
+    pub tenant_id: i32,
+    pub jwt: String,
+    pub client_secret: String,
 }
 
 pub fn generate_secret_key(length: usize) -> String {
@@ -35,45 +52,85 @@ pub fn generate_secret_key(length: usize) -> String {
         .collect()
 }
 
-pub async fn generate_jwt(service_id: &str, secret_key: &str) -> Result<String, sqlx::Error> {
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::days(365))
-        .expect("valid timestamp")
-        .timestamp();
-
+pub async fn generate_jwt(
+    service_id: &str,
+    scopes: Vec<String>,
+) -> Result<TenantCredentials, anyhow::Error> {
     let pool: PgPool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
     let database = Adapter::new(pool.clone());
+    let keys = KeyRing::new(
+        database.get_signing_keys().await?,
+        std::env::var("JWT_SECRET").ok(),
+    )?;
+    create_tenant(&database, &keys, service_id, scopes, None).await
+}
+
+/// Create an active tenant along with its first credentials, `audit` is
+/// recorded for the tenant as it is created.
+pub async fn create_tenant<C: DatabaseClient + Send + Sync>(
+    database: &Adapter<C>,
+    keys: &KeyRing,
+    name: &str,
+    scopes: Vec<String>,
+    audit: Option<TenantAuditEntry>,
+) -> Result<TenantCredentials, anyhow::Error> {
     let tenant_id = database
-        .create_tenant_profile(
-            service_id,
-            "bank-account:read bank-account:write ledger:read",
-        )
+        .create_tenant_profile(name, &scopes.join(" "), audit)
         .await?;
     debug!("Tenant ID: {}", tenant_id);
 
-    let claims = Claims {
-        sub: service_id.to_owned(),
-        exp: expiration as usize,
-        iat: Utc::now().timestamp() as usize,
-        iss: "bankie".to_owned(),
-        aud: "service".to_owned(),
-        scopes: vec![
-            "bank-account:read".to_owned(),
-            "bank-account:write".to_owned(),
-            "ledger:read".to_owned(),
-        ],
+    let credentials = issue_credentials(database, keys, tenant_id, name, scopes).await?;
+    database
+        .update_tenant_profile(tenant_id, &credentials.jwt)
+        .await?;
+
+    Ok(credentials)
+}
+
+/// A new service token and client secret for the tenant. The previous client
+/// secret stops working, its tokens stay valid until they are revoked.
+pub async fn issue_credentials<C: DatabaseClient + Send + Sync>(
+    database: &Adapter<C>,
+    keys: &KeyRing,
+    tenant_id: i32,
+    subject: &str,
+    scopes: Vec<String>,
+) -> Result<TenantCredentials, anyhow::Error> {
+    let ttl = Duration::days(SETTINGS.auth.service_token_ttl_days);
+    let (jwt, _) = issue_token(
+        database,
+        keys,
         tenant_id,
-    };
+        subject,
+        scopes,
+        TOKEN_KIND_SERVICE,
+        ttl,
+    )
+    .await?;
 
-    let header = Header::default();
-    let encoding_key = EncodingKey::from_secret(secret_key.as_bytes());
-    let jwt_token = encode(&header, &claims, &encoding_key).unwrap();
+    let client_secret = generate_client_secret();
+    database
+        .set_tenant_client_secret_hash(tenant_id, &hash_secret(&client_secret))
+        .await?;
 
+    Ok(TenantCredentials {
+        tenant_id,
+        jwt,
+        client_secret,
+    })
+}
+
+// Replaces the client secret of an existing tenant, the previous one stops
+// working at once.
+pub async fn generate_client_credentials(tenant_id: i32) -> Result<String, sqlx::Error> {
+    let pool: PgPool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
+    let database = Adapter::new(pool);
+    let client_secret = generate_client_secret();
     database
-        .update_tenant_profile(tenant_id, &jwt_token)
+        .set_tenant_client_secret_hash(tenant_id, &hash_secret(&client_secret))
         .await?;
 
-    Ok(jwt_token)
+    Ok(client_secret)
 }
 
 #[cfg(test)]
@@ -0,0 +1,334 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData,
+    Validation,
+};
+use postgres_es::default_postgress_pool;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configs::settings::SETTINGS;
+use crate::domain::signing_key::{SigningKey, SIGNING_KEY_ACTIVE};
+use crate::repository::adapter::{Adapter, DatabaseClient};
+use crate::state::ApplicationState;
+
+use super::jwt::Claims;
+
+pub const RS256: &str = "RS256";
+pub const EDDSA: &str = "EdDSA";
+
+// Keys are reloaded at most this often for tokens with an unknown `kid`.
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+struct LoadedKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    jwk: Jwk,
+}
+
+/// Keys tokens are signed and verified with. Tokens without `kid` are HS256
+/// tokens signed with `JWT_SECRET`, from before asymmetric keys were added.
+pub struct KeyRing {
+    keys: Vec<LoadedKey>,
+    secret: Option<String>,
+}
+
+impl KeyRing {
+    /// `keys` are the active keys, newest first.
+    pub fn new(keys: Vec<SigningKey>, secret: Option<String>) -> Result<Self, anyhow::Error> {
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let jwk: Jwk = serde_json::from_value(key.public_jwk)?;
+                let (algorithm, encoding) = match key.algorithm.as_str() {
+                    RS256 => (
+                        Algorithm::RS256,
+                        EncodingKey::from_rsa_pem(key.private_key.as_bytes())?,
+                    ),
+                    EDDSA => (
+                        Algorithm::EdDSA,
+                        EncodingKey::from_ed_pem(key.private_key.as_bytes())?,
+                    ),
+                    other => bail!("Unsupported algorithm {} for key {}", other, key.kid),
+                };
+                Ok(LoadedKey {
+                    kid: key.kid,
+                    algorithm,
+                    encoding,
+                    decoding: DecodingKey::from_jwk(&jwk)?,
+                    jwk,
+                })
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+
+        Ok(Self { keys, secret })
+    }
+
+    pub fn contains(&self, kid: &str) -> bool {
+        self.keys.iter().any(|key| key.kid == kid)
+    }
+
+    /// Sign with the newest key, or with `JWT_SECRET` while there is none.
+    pub fn encode(&self, claims: &Claims) -> Result<String, anyhow::Error> {
+        if let Some(key) = self.keys.first() {
+            let mut header = Header::new(key.algorithm);
+            header.kid = Some(key.kid.clone());
+            return Ok(encode(&header, claims, &key.encoding)?);
+        }
+        match &self.secret {
+            Some(secret) => Ok(encode(
+                &Header::default(),
+                claims,
+                &EncodingKey::from_secret(secret.as_bytes()),
+            )?),
+            None => Err(anyhow!("No signing key configured")),
+        }
+    }
+
+    pub fn decode(&self, token: &str) -> Result<TokenData<Claims>, anyhow::Error> {
+        let header = decode_header(token)?;
+        // The algorithm comes from the key, never from the token header.
+        let (algorithm, decoding) = match &header.kid {
+            Some(kid) => {
+                let key = self
+                    .keys
+                    .iter()
+                    .find(|key| key.kid == *kid)
+                    .ok_or_else(|| anyhow!("Unknown key {}", kid))?;
+                (key.algorithm, key.decoding.clone())
+            }
+            None => {
+                let secret = self
+                    .secret
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No JWT secret configured"))?;
+                (
+                    Algorithm::HS256,
+                    DecodingKey::from_secret(secret.as_bytes()),
+                )
+            }
+        };
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&["service"]);
+        Ok(decode(token, &decoding, &validation)?)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+/// The key ring loaded by the server, reloaded from `signing_keys` every
+/// `auth.key_refresh_secs` so keys rotated from the CLI are picked up.
+#[derive(Default)]
+pub struct KeyCache {
+    ring: RwLock<Option<(Instant, Arc<KeyRing>)>>,
+}
+
+/// The current key ring. `force` reloads it, unless it was loaded moments ago.
+pub async fn key_ring<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    force: bool,
+) -> Result<Arc<KeyRing>, anyhow::Error> {
+    let max_age = match force {
+        true => MIN_RELOAD_INTERVAL,
+        false => Duration::from_secs(SETTINGS.auth.key_refresh_secs),
+    };
+    if let Some((loaded_at, ring)) = state.keys.ring.read().unwrap().as_ref() {
+        if loaded_at.elapsed() < max_age {
+            return Ok(ring.clone());
+        }
+    }
+
+    let keys = state.database.get_signing_keys().await?;
+    let ring = Arc::new(KeyRing::new(keys, std::env::var("JWT_SECRET").ok())?);
+    *state.keys.ring.write().unwrap() = Some((Instant::now(), ring.clone()));
+    Ok(ring)
+}
+
+/// A new key pair with a random `kid`, its public half as a JWK.
+pub fn generate_signing_key(algorithm: &str) -> Result<SigningKey, anyhow::Error> {
+    let kid = Uuid::new_v4().simple().to_string();
+    let (private_key, key_algorithm, parameters) = match algorithm {
+        RS256 => {
+            let private = RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?;
+            let public = RsaPublicKey::from(&private);
+            let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(public.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(public.e().to_bytes_be()),
+            });
+            let pem = private.to_pkcs8_pem(LineEnding::LF)?.to_string();
+            (pem, KeyAlgorithm::RS256, parameters)
+        }
+        EDDSA => {
+            let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                .map_err(|_| anyhow!("Failed to generate Ed25519 key"))?;
+            let pair = Ed25519KeyPair::from_pkcs8(document.as_ref())
+                .map_err(|_| anyhow!("Failed to parse Ed25519 key"))?;
+            let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+            });
+            let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", document.as_ref()));
+            (pem, KeyAlgorithm::EdDSA, parameters)
+        }
+        other => bail!(
+            "Unsupported algorithm: {}, use {} or {}",
+            other,
+            RS256,
+            EDDSA
+        ),
+    };
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.clone()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    };
+
+    Ok(SigningKey {
+        kid,
+        algorithm: algorithm.to_string(),
+        private_key,
+        public_jwk: serde_json::to_value(jwk)?,
+        status: SIGNING_KEY_ACTIVE.to_string(),
+        created_at: Utc::now().naive_utc(),
+    })
+}
+
+// Adds a key that signs all new tokens. Older keys keep verifying the tokens
+// they signed until they are retired.
+pub async fn rotate_signing_key(algorithm: &str) -> Result<String, anyhow::Error> {
+    let pool: PgPool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
+    let key = generate_signing_key(algorithm)?;
+    let kid = key.kid.clone();
+    Adapter::new(pool).create_signing_key(key).await?;
+    Ok(kid)
+}
+
+// Tokens signed with a retired key are rejected and it leaves the JWKS.
+pub async fn retire_signing_key(kid: &str) -> Result<(), anyhow::Error> {
+    let pool: PgPool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
+    Adapter::new(pool).retire_signing_key(kid).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> Claims {
+        Claims {
+            iss: "bankie".to_owned(),
+            sub: "test_service".to_owned(),
+            aud: "service".to_owned(),
+            exp: (Utc::now().timestamp() + 60) as usize,
+            iat: Utc::now().timestamp() as usize,
+            scopes: vec!["ledger:read".to_owned()],
+            tenant_id: 1,
+            jti: None,
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify_with_newest_key() {
+        let old = generate_signing_key(EDDSA).unwrap();
+        let new = generate_signing_key(EDDSA).unwrap();
+        let old_token = KeyRing::new(vec![old.clone()], None)
+            .unwrap()
+            .encode(&claims())
+            .unwrap();
+        let ring = KeyRing::new(vec![new.clone(), old], None).unwrap();
+
+        let token = ring.encode(&claims()).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::EdDSA);
+        assert_eq!(header.kid, Some(new.kid));
+        assert_eq!(ring.decode(&token).unwrap().claims.tenant_id, 1);
+        // Tokens of the previous key verify until it is retired.
+        assert!(ring.decode(&old_token).is_ok());
+        assert_eq!(ring.jwks().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_retired_key_is_rejected() {
+        let old = generate_signing_key(EDDSA).unwrap();
+        let token = KeyRing::new(vec![old], None)
+            .unwrap()
+            .encode(&claims())
+            .unwrap();
+        let ring = KeyRing::new(vec![generate_signing_key(EDDSA).unwrap()], None).unwrap();
+
+        let err = ring.decode(&token).unwrap_err();
+
+        assert!(err.to_string().starts_with("Unknown key"), "{}", err);
+    }
+
+    #[test]
+    fn test_rsa_key_verifies_from_jwks() {
+        let key = generate_signing_key(RS256).unwrap();
+        let ring = KeyRing::new(vec![key], None).unwrap();
+        let token = ring.encode(&claims()).unwrap();
+
+        // What another service does with /.well-known/jwks.json.
+        let jwks = ring.jwks();
+        let kid = decode_header(&token).unwrap().kid.unwrap();
+        let jwk = jwks.find(&kid).unwrap();
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&["service"]);
+        let decoded =
+            decode::<Claims>(&token, &DecodingKey::from_jwk(jwk).unwrap(), &validation).unwrap();
+        assert_eq!(decoded.claims.scopes, vec!["ledger:read"]);
+        assert!(serde_json::to_string(&jwks).unwrap().contains("\"n\""));
+        assert!(!serde_json::to_string(&jwks).unwrap().contains("PRIVATE"));
+    }
+
+    #[test]
+    fn test_secret_tokens_without_kid() {
+        let secret = KeyRing::new(vec![], Some("secret".to_string())).unwrap();
+        let token = secret.encode(&claims()).unwrap();
+        assert_eq!(decode_header(&token).unwrap().alg, Algorithm::HS256);
+
+        let ring = KeyRing::new(
+            vec![generate_signing_key(EDDSA).unwrap()],
+            Some("secret".to_string()),
+        )
+        .unwrap();
+        assert!(ring.decode(&token).is_ok());
+        assert!(KeyRing::new(vec![], None).unwrap().decode(&token).is_err());
+        assert!(KeyRing::new(vec![], None)
+            .unwrap()
+            .encode(&claims())
+            .is_err());
+    }
+
+    #[test]
+    fn test_unsupported_algorithm() {
+        assert!(generate_signing_key("HS512").is_err());
+    }
+}
@@ -1,12 +1,14 @@
 use std::sync::Arc;
 
 use axum::{body::Body, extract::Request, http::StatusCode, middleware::Next, response::Response};
-use jsonwebtoken::{decode, DecodingKey, TokenData, Validation};
-use tracing::debug;
+use jsonwebtoken::{decode_header, TokenData};
+use tracing::{debug, error};
 
 use crate::{repository::adapter::DatabaseClient, state::ApplicationState};
 
 use super::jwt::Claims;
+use super::keys::key_ring;
+use super::token::is_token_active;
 
 pub async fn authorize<C: DatabaseClient + Send + Sync + 'static>(
     mut req: Request,
@@ -19,15 +21,13 @@ pub async fn authorize<C: DatabaseClient + Send + Sync + 'static>(
     };
     let mut header = auth_header.split_whitespace();
     let (_bearer, token) = (header.next(), header.next());
-    let token_data = match decode_jwt(token.unwrap().to_string()) {
-        Ok(data) => data,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
+    let token = token.unwrap().to_string();
 
     let state = match req.extensions().get::<Arc<ApplicationState<C>>>() {
-        Some(state) => state,
+        Some(state) => state.clone(),
         None => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
+    let token_data = decode_jwt(&state, &token).await?;
     match state
         .database
         .get_tenant_profile(token_data.claims.tenant_id)
@@ -35,30 +35,58 @@ pub async fn authorize<C: DatabaseClient + Send + Sync + 'static>(
     {
         Ok(tenant) => {
             debug!("Tenant: {:?}", tenant);
+            // Scopes removed from the tenant profile are no longer granted by
+            // tokens issued before.
+            let mut claims = token_data.claims;
+            if let Some(scope) = &tenant.scope {
+                claims
+                    .scopes
+                    .retain(|s| scope.split_whitespace().any(|granted| granted == s));
+            }
+            let active = match claims.jti {
+                Some(jti) => is_token_active(&state, jti)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                // Tokens issued before `jti` existed cannot be revoked one by
+                // one, they are only accepted while they are the tenant's
+                // current token.
+                None => token == tenant.jwt,
+            };
+            if !active {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
             req.extensions_mut().insert(tenant.id);
+            req.extensions_mut().insert(tenant.rate_limits);
+            req.extensions_mut().insert(claims);
             Ok(next.run(req).await)
         }
         Err(_) => Err(StatusCode::UNAUTHORIZED),
     }
 }
 
-pub fn decode_jwt(jwt_token: String) -> Result<TokenData<Claims>, StatusCode> {
-    if let Ok(secret_key) = std::env::var("JWT_SECRET") {
-        let mut validation = Validation::default();
-        validation.set_audience(&["service"]);
-        let result: Result<TokenData<Claims>, StatusCode> = decode(
-            &jwt_token,
-            &DecodingKey::from_secret(secret_key.as_ref()),
-            &validation,
-        )
-        .map_err(|e| {
-            debug!("Error: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        });
-        result
-    } else {
-        Err(StatusCode::INTERNAL_SERVER_ERROR)
+/// Verify the token with the signing key named by its `kid`, or with
+/// `JWT_SECRET` when it has none.
+pub async fn decode_jwt<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    jwt_token: &str,
+) -> Result<TokenData<Claims>, StatusCode> {
+    let kid = decode_header(jwt_token)
+        .map_err(|_| StatusCode::UNAUTHORIZED)?
+        .kid;
+    let mut keys = key_ring(state, false).await.map_err(|e| {
+        error!("Failed to load signing keys: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if kid.as_ref().is_some_and(|kid| !keys.contains(kid)) {
+        // Signed with a key rotated in after the keys were loaded.
+        keys = key_ring(state, true)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     }
+    keys.decode(jwt_token).map_err(|e| {
+        debug!("Error: {:?}", e);
+        StatusCode::UNAUTHORIZED
+    })
 }
 
 #[cfg(test)]
@@ -69,7 +97,7 @@ mod tests {
     };
 
     use super::*;
-    use axum::{middleware, Router};
+    use axum::{middleware, Extension, Router};
     use chrono::{Duration, Utc};
     use jsonwebtoken::{encode, EncodingKey, Header};
     use std::env;
@@ -101,6 +129,7 @@ mod tests {
                 "ledger:read".to_owned(),
             ],
             tenant_id: 1,
+            jti: None,
         };
 
         let header = Header::default();
@@ -114,10 +143,14 @@ mod tests {
             jwt: token.clone(),
             scope: Some("bank-account:read bank-account:write ledger:read".to_string()),
             status: "active".to_string(),
+            rate_limits: Default::default(),
         };
         mock_db_client
             .expect_get_tenant_profile()
             .returning(move |_| Ok(tenant.clone()));
+        mock_db_client
+            .expect_get_signing_keys()
+            .returning(|| Ok(vec![]));
         let state = Arc::new(ApplicationState::<MockDatabaseClient>::new(Adapter::new(
             mock_db_client,
         )));
@@ -145,6 +178,69 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_authorize_limits_scopes_to_tenant() {
+        env::set_var("JWT_SECRET", "your_secret_key");
+        let claims = Claims {
+            sub: "test_service".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            iat: Utc::now().timestamp() as usize,
+            iss: "bankie".to_owned(),
+            aud: "service".to_owned(),
+            scopes: vec!["bank-account:write".to_owned(), "ledger:read".to_owned()],
+            tenant_id: 1,
+            jti: None,
+        };
+        let encoding_key = EncodingKey::from_secret("your_secret_key".as_bytes());
+        let token = encode(&Header::default(), &claims, &encoding_key).unwrap();
+
+        let mut mock_db_client = MockDatabaseClient::new();
+        let jwt = token.clone();
+        mock_db_client
+            .expect_get_tenant_profile()
+            .returning(move |_| {
+                Ok(Tenant {
+                    id: 1,
+                    name: "test_service".to_string(),
+                    jwt: jwt.clone(),
+                    scope: Some("bank-account:read ledger:read".to_string()),
+                    status: "active".to_string(),
+                    rate_limits: Default::default(),
+                })
+            });
+        mock_db_client
+            .expect_get_signing_keys()
+            .returning(|| Ok(vec![]));
+        let state = Arc::new(ApplicationState::<MockDatabaseClient>::new(Adapter::new(
+            mock_db_client,
+        )));
+        let mut req = Request::builder()
+            .header(
+                axum::http::header::AUTHORIZATION,
+                format!("Bearer {}", token),
+            )
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(state.clone());
+
+        let app = Router::new()
+            .route(
+                "/",
+                axum::routing::get(|Extension(claims): Extension<Claims>| async move {
+                    claims.scopes.join(" ")
+                }),
+            )
+            .layer(middleware::from_fn(authorize::<MockDatabaseClient>));
+
+        let response = app.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "ledger:read");
+    }
+
     #[tokio::test]
     async fn test_authorize_missing_header() {
         // Create a mock request without the Authorization header
@@ -165,6 +261,7 @@ mod tests {
             jwt: "correct_token".to_string(),
             scope: Some("bank-account:read bank-account:write ledger:read".to_string()),
             status: "active".to_string(),
+            rate_limits: Default::default(),
         };
         mock_db_client
             .expect_get_tenant_profile()
@@ -211,6 +308,7 @@ mod tests {
                 "ledger:read".to_owned(),
             ],
             tenant_id: 1,
+            jti: None,
         };
 
         let header = Header::default();
@@ -218,8 +316,14 @@ mod tests {
         let encoding_key = EncodingKey::from_secret("your_secret_key".as_bytes());
         let jwt_token = encode(&header, &claims, &encoding_key).unwrap();
 
+        let mut mock_db_client = MockDatabaseClient::new();
+        mock_db_client
+            .expect_get_signing_keys()
+            .returning(|| Ok(vec![]));
+        let state = ApplicationState::new(Adapter::new(mock_db_client));
+
         // Decode the JWT token
-        let result = decode_jwt(jwt_token);
+        let result = decode_jwt(&state, &jwt_token).await;
 
         // Validate the result
         assert!(result.is_ok(), "JWT decoding failed");
@@ -1,2 +1,5 @@
 pub mod jwt;
+pub mod keys;
 pub mod middleware;
+pub mod scope;
+pub mod token;
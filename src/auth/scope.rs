@@ -0,0 +1,142 @@
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::MethodRouter;
+
+use crate::common::error::AppError;
+
+use super::jwt::Claims;
+
+pub const BANK_ACCOUNT_READ: &str = "bank-account:read";
+pub const BANK_ACCOUNT_WRITE: &str = "bank-account:write";
+pub const LEDGER_READ: &str = "ledger:read";
+pub const HOUSE_ACCOUNT_ADMIN: &str = "house-account:admin";
+pub const ASSET_ADMIN: &str = "asset:admin";
+pub const ACH_ADMIN: &str = "ach:admin";
+pub const WEBHOOK_ADMIN: &str = "webhook:admin";
+pub const OUTBOX_ADMIN: &str = "outbox:admin";
+pub const TOKEN_ADMIN: &str = "token:admin";
+// Manages every tenant, only for platform operators.
+pub const TENANT_ADMIN: &str = "tenant:admin";
+// Scrapes the platform-wide metrics, for monitoring.
+pub const METRICS_READ: &str = "metrics:read";
+
+pub const SCOPES: [&str; 11] = [
+    BANK_ACCOUNT_READ,
+    BANK_ACCOUNT_WRITE,
+    LEDGER_READ,
+    HOUSE_ACCOUNT_ADMIN,
+    ASSET_ADMIN,
+    ACH_ADMIN,
+    WEBHOOK_ADMIN,
+    OUTBOX_ADMIN,
+    TOKEN_ADMIN,
+    TENANT_ADMIN,
+    METRICS_READ,
+];
+
+// Granted by `--mode jwt` when no scope is given.
+pub const DEFAULT_SCOPES: [&str; 5] = [
+    BANK_ACCOUNT_READ,
+    BANK_ACCOUNT_WRITE,
+    LEDGER_READ,
+    WEBHOOK_ADMIN,
+    TOKEN_ADMIN,
+];
+
+/// Scopes to issue a token with, the defaults when `scopes` is empty.
+pub fn parse_scopes(scopes: &[String]) -> Result<Vec<String>, String> {
+    if scopes.is_empty() {
+        return Ok(DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect());
+    }
+    match scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+        Some(unknown) => Err(format!("Unknown scope: {}", unknown)),
+        None => Ok(scopes.to_vec()),
+    }
+}
+
+// Rejects the request with 403 unless the token carries `scope`. It runs as a
+// route layer, after `authorize` stored the claims in the extensions.
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let granted = req
+        .extensions()
+        .get::<Claims>()
+        .is_some_and(|claims| claims.scopes.iter().any(|s| s == scope));
+    if !granted {
+        return AppError::Forbidden(format!("Missing scope: {}", scope)).into_response();
+    }
+    next.run(req).await
+}
+
+/// Require `scope` for every method of `route`.
+pub fn scoped<S: Clone + Send + Sync + 'static>(
+    scope: &'static str,
+    route: MethodRouter<S>,
+) -> MethodRouter<S> {
+    route.route_layer(middleware::from_fn_with_state(scope, require_scope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn claims(scopes: &[&str]) -> Claims {
+        Claims {
+            iss: "bankie".to_owned(),
+            sub: "test_service".to_owned(),
+            aud: "service".to_owned(),
+            exp: 0,
+            iat: 0,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            tenant_id: 1,
+            jti: None,
+        }
+    }
+
+    async fn call(claims: Claims) -> (StatusCode, String) {
+        let app = Router::new().route("/", scoped(LEDGER_READ, get(|| async { "test" })));
+        let mut req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(claims);
+        let response = app.oneshot(req).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_require_scope() {
+        let (status, _) = call(claims(&[BANK_ACCOUNT_READ, LEDGER_READ])).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_require_scope_missing() {
+        let (status, body) = call(claims(&[BANK_ACCOUNT_READ])).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("Missing scope: ledger:read"), "{}", body);
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        assert_eq!(parse_scopes(&[]).unwrap(), DEFAULT_SCOPES.to_vec());
+        assert_eq!(
+            parse_scopes(&[HOUSE_ACCOUNT_ADMIN.to_string()]).unwrap(),
+            vec![HOUSE_ACCOUNT_ADMIN]
+        );
+        assert_eq!(
+            parse_scopes(&["root".to_string()]).unwrap_err(),
+            "Unknown scope: root"
+        );
+    }
+}
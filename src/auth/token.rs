@@ -0,0 +1,149 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::configs::settings::SETTINGS;
+use crate::domain::tenant::TenantToken;
+use crate::repository::adapter::{Adapter, DatabaseClient};
+use crate::repository::redis::{get_cached, set_cached};
+use crate::state::ApplicationState;
+
+use super::jwt::Claims;
+use super::keys::KeyRing;
+
+const CACHE_KEY_PREFIX: &str = "token_revoked_at:";
+// Cached for tokens that have no revocation scheduled.
+const NOT_REVOKED: &str = "none";
+const CACHE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Sign a token for the tenant and record its `jti`, so it can be revoked.
+pub async fn issue_token<C: DatabaseClient + Send + Sync>(
+    database: &Adapter<C>,
+    keys: &KeyRing,
+    tenant_id: i32,
+    subject: &str,
+    scopes: Vec<String>,
+    kind: &str,
+    ttl: Duration,
+) -> Result<(String, TenantToken), anyhow::Error> {
+    let now = Utc::now();
+    let expires_at = now + ttl;
+    let claims = Claims {
+        iss: "bankie".to_owned(),
+        sub: subject.to_owned(),
+        aud: "service".to_owned(),
+        exp: expires_at.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        scopes,
+        tenant_id,
+        jti: Some(Uuid::new_v4()),
+    };
+    let token = keys.encode(&claims)?;
+
+    let record = TenantToken {
+        jti: claims.jti.unwrap(),
+        tenant_id,
+        kind: kind.to_owned(),
+        scope: claims.scopes.join(" "),
+        expires_at: expires_at.naive_utc(),
+        revoked_at: None,
+    };
+    database.create_tenant_token(record.clone()).await?;
+
+    Ok((token, record))
+}
+
+/// Whether the token `jti` is known and not revoked. The revocation instant is
+/// cached in Redis for `auth.revocation_cache_secs`.
+pub async fn is_token_active<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    jti: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    if let Some(cache) = &state.cache {
+        if let Some(cached) = get_cached(cache, &cache_key(jti)).await {
+            if let Some(revoked_at) = parse_cached(&cached) {
+                return Ok(revoked_at.is_none_or(|revoked_at| revoked_at > now));
+            }
+        }
+    }
+
+    let token = match state.database.get_tenant_token(jti).await {
+        Ok(token) => token,
+        Err(sqlx::Error::RowNotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    cache_token(state, &token).await;
+    Ok(token.is_active(now))
+}
+
+/// Refresh the cached revocation instant, called after a revocation so other
+/// instances stop accepting the token without waiting for the cache to expire.
+pub async fn cache_token<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    token: &TenantToken,
+) {
+    if let Some(cache) = &state.cache {
+        let value = match token.revoked_at {
+            Some(revoked_at) => revoked_at.format(CACHE_TIME_FORMAT).to_string(),
+            None => NOT_REVOKED.to_string(),
+        };
+        let ttl = SETTINGS.auth.revocation_cache_secs;
+        set_cached(cache, &cache_key(token.jti), &value, ttl).await;
+    }
+}
+
+fn cache_key(jti: Uuid) -> String {
+    format!("{}{}", CACHE_KEY_PREFIX, jti)
+}
+
+// `None` for an unreadable value, `Some(None)` for a token without revocation.
+fn parse_cached(value: &str) -> Option<Option<NaiveDateTime>> {
+    if value == NOT_REVOKED {
+        return Some(None);
+    }
+    NaiveDateTime::parse_from_str(value, CACHE_TIME_FORMAT)
+        .ok()
+        .map(Some)
+}
+
+/// Secret of the client-credentials grant, alphanumeric so it can be sent
+/// form-encoded as is.
+pub fn generate_client_secret() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect()
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cached() {
+        assert_eq!(parse_cached(NOT_REVOKED), Some(None));
+        assert_eq!(parse_cached("garbage"), None);
+
+        let revoked_at = Utc::now().naive_utc();
+        let cached = revoked_at.format(CACHE_TIME_FORMAT).to_string();
+        assert_eq!(parse_cached(&cached), Some(Some(revoked_at)));
+    }
+
+    #[test]
+    fn test_client_secret() {
+        let secret = generate_client_secret();
+        assert_eq!(secret.len(), 48);
+        assert!(secret.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(hash_secret(&secret), hash_secret(&secret));
+        assert_ne!(hash_secret(&secret), hash_secret("other"));
+        assert_eq!(hash_secret(&secret).len(), 64);
+    }
+}
@@ -1,41 +1,194 @@
 use async_trait::async_trait;
 use axum::body::Bytes;
-use axum::extract::{FromRequest, Request};
-use axum::http::StatusCode;
-use axum::response::{IntoResponse, Response};
-use std::collections::HashMap;
+use axum::extract::{ConnectInfo, FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap, Uri};
+use lazy_static::lazy_static;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::auth::jwt::Claims;
+use crate::common::validation::ValidationErrors;
+use crate::configs::settings::SETTINGS;
 use crate::event_sourcing::command::BankAccountCommand;
+use crate::event_sourcing::metadata::{Metadata, CLIENT_IP, REQUEST_ID, SUBJECT, TENANT_ID};
 
 // This is a custom Axum extension that builds metadata from the inbound request
 // and parses and deserializes the body as the command payload.
-pub struct CommandExtractor(pub HashMap<String, String>, pub BankAccountCommand);
+pub struct CommandExtractor(pub Metadata, pub BankAccountCommand);
+
+// The metadata of a request without a command body.
+pub struct RequestMetadata(pub Metadata);
 
 const USER_AGENT_HDR: &str = "User-Agent";
+const REQUEST_ID_HDR: &str = "X-Request-Id";
+const FORWARDED_FOR_HDR: &str = "X-Forwarded-For";
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+lazy_static! {
+    pub static ref TRUSTED_PROXIES: Vec<TrustedProxy> = SETTINGS
+        .http
+        .trusted_proxies
+        .iter()
+        .map(|proxy| proxy.parse().unwrap_or_else(|e| panic!("{}", e)))
+        .collect();
+}
+
+// An address or CIDR range of `http.trusted_proxies`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid trusted proxy: {}", s))?
+            .to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("Invalid trusted proxy: {}", s))?,
+            None => bits,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl TrustedProxy {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(ip) as u128, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let host_bits = bits - self.prefix;
+        network.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+    }
+}
+
+// The peer is the client unless it is a trusted proxy, then the forwarded
+// hops are walked from the nearest one and the first untrusted hop is the
+// client. Hops left of it are set by the client and never believed.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[TrustedProxy],
+) -> Option<IpAddr> {
+    let trusted = |ip: IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+    let mut client = peer?.to_canonical();
+    let hops: Vec<&str> = headers
+        .get_all(FORWARDED_FOR_HDR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in hops.into_iter().rev() {
+        if !trusted(client) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => client = ip.to_canonical(),
+            // A malformed hop ends the chain, the proxy before it is the client.
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+// Ids are stored with the events, so only short ids made of safe characters
+// are taken from the caller.
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+// Here we are including the current date/time, the uri that was called, the
+// user-agent, the caller and where the call came from in a HashMap that we
+// will submit as metadata with the command.
+pub fn request_metadata(uri: &Uri, headers: &HeaderMap, extensions: &Extensions) -> Metadata {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let mut metadata = Metadata::default();
+    metadata.insert("time".to_string(), chrono::Utc::now().to_rfc3339());
+    metadata.insert("uri".to_string(), uri.to_string());
+    if let Some(user_agent) = header(USER_AGENT_HDR) {
+        metadata.insert(USER_AGENT_HDR.to_string(), user_agent);
+    }
+    // Reuse the id of the caller so both sides can correlate the call.
+    let request_id = header(REQUEST_ID_HDR)
+        .filter(|id| valid_request_id(id))
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    metadata.insert(REQUEST_ID.to_string(), request_id);
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    if let Some(client_ip) = client_ip(peer, headers, &TRUSTED_PROXIES) {
+        metadata.insert(CLIENT_IP.to_string(), client_ip.to_string());
+    }
+    // Set by the `authorize` middleware.
+    if let Some(tenant_id) = extensions.get::<i32>() {
+        metadata.insert(TENANT_ID.to_string(), tenant_id.to_string());
+    }
+    if let Some(claims) = extensions.get::<Claims>() {
+        metadata.insert(SUBJECT.to_string(), claims.sub.clone());
+    }
+    metadata
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestMetadata
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestMetadata(request_metadata(
+            &parts.uri,
+            &parts.headers,
+            &parts.extensions,
+        )))
+    }
+}
 
 #[async_trait]
 impl<S> FromRequest<S> for CommandExtractor
 where
     S: Send + Sync,
 {
-    type Rejection = CommandExtractionError;
+    type Rejection = ValidationErrors;
 
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        // Here we are including the current date/time, the uri that was called and the user-agent
-        // in a HashMap that we will submit as metadata with the command.
-        let mut metadata = HashMap::default();
-        metadata.insert("time".to_string(), chrono::Utc::now().to_rfc3339());
-        metadata.insert("uri".to_string(), req.uri().to_string());
-        if let Some(user_agent) = req.headers().get(USER_AGENT_HDR) {
-            if let Ok(value) = user_agent.to_str() {
-                metadata.insert(USER_AGENT_HDR.to_string(), value.to_string());
-            }
-        }
+        let metadata = request_metadata(req.uri(), req.headers(), req.extensions());
 
         // Parse and deserialize the request body as the command payload.
         let body = Bytes::from_request(req, state).await?;
-        let mut command: BankAccountCommand = serde_json::from_slice(body.as_ref())?;
+        let mut command: BankAccountCommand =
+            serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_slice(&body))?;
+        command.validate()?;
 
         // Generate ledger_id instead of bringing in from external
         if let BankAccountCommand::ApproveAccount { id: _, ledger_id } = &mut command {
@@ -50,30 +203,6 @@ where
     }
 }
 
-pub struct CommandExtractionError;
-
-impl IntoResponse for CommandExtractionError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::BAD_REQUEST,
-            "command could not be read".to_string(),
-        )
-            .into_response()
-    }
-}
-
-impl From<axum::extract::rejection::BytesRejection> for CommandExtractionError {
-    fn from(_: axum::extract::rejection::BytesRejection) -> Self {
-        CommandExtractionError
-    }
-}
-
-impl From<serde_json::Error> for CommandExtractionError {
-    fn from(_: serde_json::Error) -> Self {
-        CommandExtractionError
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -85,7 +214,8 @@ mod tests {
     use axum::{
         body::Body,
         extract::FromRequest,
-        http::{header::USER_AGENT, Request},
+        http::{header::USER_AGENT, Request, StatusCode},
+        response::IntoResponse,
     };
     use rust_decimal_macros::dec;
 
@@ -102,7 +232,8 @@ mod tests {
                         "account_type": "Retail",
                         "kind": "Interest",
                         "currency": "TWD",
-                        "user_id": "b9aa777c-0868-48ac-9c49-eff869b437d7"
+                        "user_id": "b9aa777c-0868-48ac-9c49-eff869b437d7",
+                        "tenant_id": 2
                     }
                 }
                 "#,
@@ -132,6 +263,7 @@ mod tests {
                     kind,
                     user_id,
                     currency,
+                    tenant_id,
                 } = command
                 {
                     assert!(!id.is_nil());
@@ -140,6 +272,8 @@ mod tests {
                     assert_eq!(currency, Currency::TWD);
                     assert_eq!(user_id, "b9aa777c-0868-48ac-9c49-eff869b437d7".to_string());
                     assert_eq!(kind, BankAccountKind::Interest);
+                    // Stamped by the handler, never taken from the body.
+                    assert_eq!(tenant_id, 0);
                 } else {
                     panic!("Invalid command");
                 }
@@ -247,6 +381,107 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_request_metadata() {
+        let mut request = Request::builder()
+            .uri("/test-uri")
+            .header(REQUEST_ID_HDR, "request1")
+            .header(FORWARDED_FOR_HDR, "198.51.100.1, 203.0.113.7")
+            .body(Body::empty())
+            .unwrap();
+        // Sent through the local proxy trusted in `config.local.yaml`.
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        request.extensions_mut().insert(7_i32);
+        request.extensions_mut().insert(Claims {
+            iss: "bankie".to_string(),
+            sub: "service1".to_string(),
+            aud: "service".to_string(),
+            exp: 0,
+            iat: 0,
+            scopes: vec![],
+            tenant_id: 7,
+            jti: None,
+        });
+
+        let RequestMetadata(metadata) = RequestMetadata::from_request(request, &()).await.unwrap();
+        assert_eq!(metadata.get(REQUEST_ID).unwrap(), "request1");
+        assert_eq!(metadata.get(CLIENT_IP).unwrap(), "203.0.113.7");
+        assert_eq!(metadata.get(TENANT_ID).unwrap(), "7");
+        assert_eq!(metadata.get(SUBJECT).unwrap(), "service1");
+
+        // Without the header every request still gets an id.
+        let request = Request::builder().body(Body::empty()).unwrap();
+        let RequestMetadata(metadata) = RequestMetadata::from_request(request, &()).await.unwrap();
+        assert!(Uuid::parse_str(metadata.get(REQUEST_ID).unwrap()).is_ok());
+        assert!(!metadata.contains_key(CLIENT_IP));
+    }
+
+    #[tokio::test]
+    async fn test_request_metadata_replaces_invalid_request_id() {
+        for request_id in ["a".repeat(MAX_REQUEST_ID_LEN + 1), "id; drop".to_string()] {
+            let request = Request::builder()
+                .header(REQUEST_ID_HDR, request_id)
+                .body(Body::empty())
+                .unwrap();
+            let RequestMetadata(metadata) =
+                RequestMetadata::from_request(request, &()).await.unwrap();
+            assert!(Uuid::parse_str(metadata.get(REQUEST_ID).unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies: Vec<TrustedProxy> = ["10.0.0.0/8", "::1"]
+            .iter()
+            .map(|proxy| proxy.parse().unwrap())
+            .collect();
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED_FOR_HDR, value.parse().unwrap());
+            headers
+        };
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        // A client talking to the server directly cannot spoof its address.
+        assert_eq!(
+            client_ip(ip("198.51.100.1"), &headers("203.0.113.7"), &proxies),
+            ip("198.51.100.1")
+        );
+        // Behind trusted proxies the right-most untrusted hop is the client.
+        assert_eq!(
+            client_ip(
+                ip("10.0.0.2"),
+                &headers("192.0.2.1, 203.0.113.7, 10.0.0.1"),
+                &proxies
+            ),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(ip("::1"), &headers("garbage, 10.0.0.1"), &proxies),
+            ip("10.0.0.1")
+        );
+        assert_eq!(
+            client_ip(ip("::ffff:10.0.0.2"), &HeaderMap::new(), &proxies),
+            ip("10.0.0.2")
+        );
+        assert_eq!(client_ip(None, &headers("203.0.113.7"), &proxies), None);
+    }
+
+    #[test]
+    fn test_trusted_proxy() {
+        let proxy: TrustedProxy = "10.1.0.0/16".parse().unwrap();
+        assert!(proxy.contains("10.1.2.3".parse().unwrap()));
+        assert!(!proxy.contains("10.2.0.1".parse().unwrap()));
+        assert!(!proxy.contains("::1".parse().unwrap()));
+
+        let any: TrustedProxy = "::/0".parse().unwrap();
+        assert!(any.contains("2001:db8::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy".parse::<TrustedProxy>().is_err());
+    }
+
     #[tokio::test]
     async fn test_withdrawal_extractor() {
         // Create a mock request
@@ -284,13 +519,19 @@ mod tests {
                 assert_eq!(metadata.get(USER_AGENT_HDR).unwrap(), "test-agent");
 
                 // Check fields
-                if let BankAccountCommand::Withdrawal { id, amount } = command {
+                if let BankAccountCommand::Withdrawal {
+                    id,
+                    amount,
+                    destination,
+                } = command
+                {
                     assert_eq!(
                         id,
                         Uuid::parse_str("b9aa777c-0868-48ac-9c49-eff869b437d7").unwrap()
                     );
                     assert_eq!(amount.currency, Currency::USD);
                     assert_eq!(amount.amount, dec!(100));
+                    assert!(destination.is_none());
                 } else {
                     panic!("Invalid command");
                 }
@@ -317,4 +558,58 @@ mod tests {
         // Verify the result
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_command_extractor_field_errors() {
+        for (body, field, message) in [
+            (
+                r#"{"Deposit": {"id": "b9aa777c-0868-48ac-9c49-eff869b437d7", "amount": {"currency": "USD", "amount": "-1"}}}"#,
+                "Deposit.amount",
+                "must be greater than 0",
+            ),
+            (
+                r#"{"Withdrawal": {"id": "b9aa777c-0868-48ac-9c49-eff869b437d7", "amount": {"currency": "USD", "amount": "100.123"}}}"#,
+                "Withdrawal.amount",
+                "USD allows at most 2 decimals",
+            ),
+            (
+                r#"{"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": "USD", "user_id": " "}}"#,
+                "OpenAccount.user_id",
+                "is required",
+            ),
+        ] {
+            let request = Request::builder().body(Body::from(body)).unwrap();
+
+            let errors = match CommandExtractor::from_request(request, &()).await {
+                Ok(_) => panic!("Extraction should fail: {}", body),
+                Err(errors) => errors,
+            };
+
+            assert_eq!(errors.errors.len(), 1);
+            assert_eq!(errors.errors[0].field, field);
+            assert_eq!(errors.errors[0].message, message);
+            let response = errors.into_response();
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_extractor_missing_field() {
+        let request = Request::builder()
+            .body(Body::from(
+                r#"{"Deposit": {"id": "b9aa777c-0868-48ac-9c49-eff869b437d7"}}"#,
+            ))
+            .unwrap();
+
+        let errors = match CommandExtractor::from_request(request, &()).await {
+            Ok(_) => panic!("Extraction should fail"),
+            Err(errors) => errors,
+        };
+
+        assert_eq!(errors.errors[0].field, "Deposit");
+        assert!(errors.errors[0]
+            .message
+            .starts_with("missing field `amount`"));
+        assert_eq!(errors.into_response().status(), StatusCode::BAD_REQUEST);
+    }
 }
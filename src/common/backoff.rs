@@ -0,0 +1,49 @@
+use chrono::NaiveDateTime;
+
+// Longest wait between two attempts of the same work.
+const MAX_BACKOFF_SECS: i64 = 6 * 60 * 60;
+
+/// Time of the next attempt after `attempts` failed ones, doubling the wait
+/// from `base_secs` each time, or `None` once `max_attempts` is reached and the
+/// work should be dead-lettered.
+pub fn next_attempt_at(
+    attempts: i32,
+    max_attempts: i32,
+    base_secs: i64,
+    now: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    if attempts >= max_attempts {
+        return None;
+    }
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    let backoff = (base_secs * 2_i64.pow(exponent)).min(MAX_BACKOFF_SECS);
+    Some(now + chrono::Duration::seconds(backoff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_next_attempt_at() {
+        let now = NaiveDate::from_ymd_opt(2024, 8, 22)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        assert_eq!(
+            next_attempt_at(1, 5, 10, now),
+            Some(now + chrono::Duration::seconds(10))
+        );
+        assert_eq!(
+            next_attempt_at(4, 5, 10, now),
+            Some(now + chrono::Duration::seconds(80))
+        );
+        assert_eq!(
+            next_attempt_at(20, 30, 10, now),
+            Some(now + chrono::Duration::seconds(MAX_BACKOFF_SECS))
+        );
+        assert_eq!(next_attempt_at(5, 5, 10, now), None);
+    }
+}
@@ -0,0 +1,484 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::postgres::{PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Postgres, Type};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use crate::configs::settings::SETTINGS;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct CurrencyInfo {
+    pub code: &'static str,
+    // ISO 4217 numeric code, custom assets have none.
+    pub numeric: Option<u16>,
+    // Digits after the decimal point of the minor unit.
+    pub minor_units: u32,
+    // Tenant that registered the custom asset.
+    pub tenant_id: Option<i32>,
+}
+
+const fn info(code: &'static str, numeric: u16, minor_units: u32) -> CurrencyInfo {
+    CurrencyInfo {
+        code,
+        numeric: Some(numeric),
+        minor_units,
+        tenant_id: None,
+    }
+}
+
+// ISO 4217 currencies in circulation, sorted by code. Funds, precious metals
+// and other codes without minor units are left out.
+const ISO_4217: &[CurrencyInfo] = &[
+    info("AED", 784, 2),
+    info("AFN", 971, 2),
+    info("ALL", 8, 2),
+    info("AMD", 51, 2),
+    info("ANG", 532, 2),
+    info("AOA", 973, 2),
+    info("ARS", 32, 2),
+    info("AUD", 36, 2),
+    info("AWG", 533, 2),
+    info("AZN", 944, 2),
+    info("BAM", 977, 2),
+    info("BBD", 52, 2),
+    info("BDT", 50, 2),
+    info("BGN", 975, 2),
+    info("BHD", 48, 3),
+    info("BIF", 108, 0),
+    info("BMD", 60, 2),
+    info("BND", 96, 2),
+    info("BOB", 68, 2),
+    info("BRL", 986, 2),
+    info("BSD", 44, 2),
+    info("BTN", 64, 2),
+    info("BWP", 72, 2),
+    info("BYN", 933, 2),
+    info("BZD", 84, 2),
+    info("CAD", 124, 2),
+    info("CDF", 976, 2),
+    info("CHF", 756, 2),
+    info("CLP", 152, 0),
+    info("CNY", 156, 2),
+    info("COP", 170, 2),
+    info("CRC", 188, 2),
+    info("CUP", 192, 2),
+    info("CVE", 132, 2),
+    info("CZK", 203, 2),
+    info("DJF", 262, 0),
+    info("DKK", 208, 2),
+    info("DOP", 214, 2),
+    info("DZD", 12, 2),
+    info("EGP", 818, 2),
+    info("ERN", 232, 2),
+    info("ETB", 230, 2),
+    info("EUR", 978, 2),
+    info("FJD", 242, 2),
+    info("FKP", 238, 2),
+    info("GBP", 826, 2),
+    info("GEL", 981, 2),
+    info("GHS", 936, 2),
+    info("GIP", 292, 2),
+    info("GMD", 270, 2),
+    info("GNF", 324, 0),
+    info("GTQ", 320, 2),
+    info("GYD", 328, 2),
+    info("HKD", 344, 2),
+    info("HNL", 340, 2),
+    info("HTG", 332, 2),
+    info("HUF", 348, 2),
+    info("IDR", 360, 2),
+    info("ILS", 376, 2),
+    info("INR", 356, 2),
+    info("IQD", 368, 3),
+    info("IRR", 364, 2),
+    info("ISK", 352, 0),
+    info("JMD", 388, 2),
+    info("JOD", 400, 3),
+    info("JPY", 392, 0),
+    info("KES", 404, 2),
+    info("KGS", 417, 2),
+    info("KHR", 116, 2),
+    info("KMF", 174, 0),
+    info("KPW", 408, 2),
+    info("KRW", 410, 0),
+    info("KWD", 414, 3),
+    info("KYD", 136, 2),
+    info("KZT", 398, 2),
+    info("LAK", 418, 2),
+    info("LBP", 422, 2),
+    info("LKR", 144, 2),
+    info("LRD", 430, 2),
+    info("LSL", 426, 2),
+    info("LYD", 434, 3),
+    info("MAD", 504, 2),
+    info("MDL", 498, 2),
+    info("MGA", 969, 2),
+    info("MKD", 807, 2),
+    info("MMK", 104, 2),
+    info("MNT", 496, 2),
+    info("MOP", 446, 2),
+    info("MRU", 929, 2),
+    info("MUR", 480, 2),
+    info("MVR", 462, 2),
+    info("MWK", 454, 2),
+    info("MXN", 484, 2),
+    info("MYR", 458, 2),
+    info("MZN", 943, 2),
+    info("NAD", 516, 2),
+    info("NGN", 566, 2),
+    info("NIO", 558, 2),
+    info("NOK", 578, 2),
+    info("NPR", 524, 2),
+    info("NZD", 554, 2),
+    info("OMR", 512, 3),
+    info("PAB", 590, 2),
+    info("PEN", 604, 2),
+    info("PGK", 598, 2),
+    info("PHP", 608, 2),
+    info("PKR", 586, 2),
+    info("PLN", 985, 2),
+    info("PYG", 600, 0),
+    info("QAR", 634, 2),
+    info("RON", 946, 2),
+    info("RSD", 941, 2),
+    info("RUB", 643, 2),
+    info("RWF", 646, 0),
+    info("SAR", 682, 2),
+    info("SBD", 90, 2),
+    info("SCR", 690, 2),
+    info("SDG", 938, 2),
+    info("SEK", 752, 2),
+    info("SGD", 702, 2),
+    info("SHP", 654, 2),
+    info("SLE", 925, 2),
+    info("SOS", 706, 2),
+    info("SRD", 968, 2),
+    info("SSP", 728, 2),
+    info("STN", 930, 2),
+    info("SVC", 222, 2),
+    info("SYP", 760, 2),
+    info("SZL", 748, 2),
+    info("THB", 764, 2),
+    info("TJS", 972, 2),
+    info("TMT", 934, 2),
+    info("TND", 788, 3),
+    info("TOP", 776, 2),
+    info("TRY", 949, 2),
+    info("TTD", 780, 2),
+    info("TWD", 901, 2),
+    info("TZS", 834, 2),
+    info("UAH", 980, 2),
+    info("UGX", 800, 0),
+    info("USD", 840, 2),
+    info("UYU", 858, 2),
+    info("UZS", 860, 2),
+    info("VED", 926, 2),
+    info("VES", 928, 2),
+    info("VND", 704, 0),
+    info("VUV", 548, 0),
+    info("WST", 882, 2),
+    info("XAF", 950, 0),
+    info("XCD", 951, 2),
+    info("XOF", 952, 0),
+    info("XPF", 953, 0),
+    info("YER", 886, 2),
+    info("ZAR", 710, 2),
+    info("ZMW", 967, 2),
+    info("ZWG", 924, 2),
+];
+
+lazy_static! {
+    // Custom assets by qualified code, filled from the `assets` table. Entries
+    // are never removed so `Currency` can stay `Copy`.
+    static ref ASSETS: RwLock<HashMap<&'static str, &'static CurrencyInfo>> =
+        RwLock::new(HashMap::new());
+}
+
+// Index of `code` in the table, for the constants below.
+const fn position(code: &str) -> usize {
+    let code = code.as_bytes();
+    let mut i = 0;
+    while i < ISO_4217.len() {
+        let candidate = ISO_4217[i].code.as_bytes();
+        if candidate[0] == code[0] && candidate[1] == code[1] && candidate[2] == code[2] {
+            return i;
+        }
+        i += 1;
+    }
+    panic!("currency missing from the ISO 4217 table");
+}
+
+/// An ISO 4217 currency or a custom asset of a tenant, serialized as its code.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency(&'static CurrencyInfo);
+
+impl Currency {
+    pub const USD: Currency = Currency(&ISO_4217[position("USD")]);
+    #[allow(dead_code)]
+    pub const TWD: Currency = Currency(&ISO_4217[position("TWD")]);
+
+    pub fn code(&self) -> &'static str {
+        self.0.code
+    }
+
+    pub fn numeric_code(&self) -> Option<u16> {
+        self.0.numeric
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.0.minor_units
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.0.tenant_id.is_some()
+    }
+
+    /// Makes a custom asset known to this process under its code qualified
+    /// with the tenant, `<tenant_id>:<code>`, so each tenant has its own codes.
+    /// An asset already known keeps its precision since it cannot change once
+    /// registered.
+    pub fn register(code: &str, minor_units: u32, tenant_id: i32) -> Currency {
+        let code = format!("{}:{}", tenant_id, code);
+        let mut assets = ASSETS.write().unwrap();
+        if let Some(info) = assets.get(code.as_str()) {
+            return Currency(info);
+        }
+        let info: &'static CurrencyInfo = Box::leak(Box::new(CurrencyInfo {
+            code: Box::leak(code.into_boxed_str()),
+            numeric: None,
+            minor_units,
+            tenant_id: Some(tenant_id),
+        }));
+        assets.insert(info.code, info);
+        Currency(info)
+    }
+
+    /// Currencies of `currency.enabled` followed by the custom assets of the
+    /// tenant, codes missing from the table are ignored.
+    pub fn enabled(tenant_id: i32) -> Vec<Currency> {
+        let mut currencies: Vec<Currency> = SETTINGS
+            .currency
+            .enabled
+            .iter()
+            .filter_map(|code| Currency::from_str(code).ok())
+            .collect();
+        let mut assets: Vec<Currency> = ASSETS
+            .read()
+            .unwrap()
+            .values()
+            .filter(|info| info.tenant_id == Some(tenant_id))
+            .map(|info| Currency(info))
+            .collect();
+        assets.sort_by_key(|asset| asset.code());
+        currencies.append(&mut assets);
+        currencies
+    }
+
+    /// Whether the tenant can open accounts and move money in the currency:
+    /// ISO 4217 currencies listed in `currency.enabled` and its own assets.
+    pub fn is_enabled(&self, tenant_id: i32) -> bool {
+        match self.0.tenant_id {
+            Some(owner) => owner == tenant_id,
+            None => SETTINGS
+                .currency
+                .enabled
+                .iter()
+                .any(|code| code == self.code()),
+        }
+    }
+
+    pub fn ensure_enabled(&self, tenant_id: i32) -> Result<(), CurrencyParseError> {
+        match self.is_enabled(tenant_id) {
+            true => Ok(()),
+            false => Err(CurrencyParseError::Disabled(self.code().to_string())),
+        }
+    }
+
+    /// Fails for a custom asset of another tenant, ISO 4217 currencies belong
+    /// to every tenant.
+    pub fn ensure_owner(&self, tenant_id: i32) -> Result<(), CurrencyParseError> {
+        match self.0.tenant_id {
+            Some(owner) if owner != tenant_id => {
+                Err(CurrencyParseError::Disabled(self.code().to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::USD
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = CurrencyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(i) = ISO_4217.binary_search_by(|info| info.code.cmp(s)) {
+            return Ok(Currency(&ISO_4217[i]));
+        }
+        ASSETS
+            .read()
+            .unwrap()
+            .get(s)
+            .map(|info| Currency(info))
+            .ok_or_else(|| CurrencyParseError::Unknown(s.to_string()))
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::from_str(&code).map_err(serde::de::Error::custom)
+    }
+}
+
+// Currency columns are text, rows with a code that is neither in the table nor
+// a registered asset fail to decode.
+impl Type<Postgres> for Currency {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Currency {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        let code = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(Currency::from_str(code)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurrencyParseError {
+    Unknown(String),
+    Disabled(String),
+}
+
+impl fmt::Display for CurrencyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurrencyParseError::Unknown(code) => write!(f, "invalid currency: {}", code),
+            CurrencyParseError::Disabled(code) => write!(f, "currency not enabled: {}", code),
+        }
+    }
+}
+
+impl Error for CurrencyParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_is_sorted() {
+        assert!(ISO_4217.windows(2).all(|w| w[0].code < w[1].code));
+        assert!(ISO_4217.iter().all(|info| info.code.len() == 3));
+    }
+
+    #[test]
+    fn test_minor_units() {
+        for (code, precision) in [("JPY", 0), ("KRW", 0), ("EUR", 2), ("KWD", 3), ("BHD", 3)] {
+            assert_eq!(Currency::from_str(code).unwrap().precision(), precision);
+        }
+    }
+
+    #[test]
+    fn test_numeric_code() {
+        assert_eq!(Currency::USD.numeric_code(), Some(840));
+        assert_eq!(Currency::from_str("JPY").unwrap().numeric_code(), Some(392));
+        assert_eq!(Currency::from_str("ALL").unwrap().numeric_code(), Some(8));
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        assert_eq!(
+            Currency::from_str("usd").unwrap_err(),
+            CurrencyParseError::Unknown("usd".to_string())
+        );
+        assert!(Currency::from_str("XXX").is_err());
+        assert!(Currency::from_str("").is_err());
+        assert!(serde_json::from_str::<Currency>(r#""ABC""#).is_err());
+        assert_eq!(
+            serde_json::from_str::<Currency>(r#""TWD""#).unwrap(),
+            Currency::TWD
+        );
+        assert_eq!(serde_json::to_string(&Currency::USD).unwrap(), r#""USD""#);
+    }
+
+    #[test]
+    fn test_enabled_currencies() {
+        assert!(Currency::USD.ensure_enabled(1).is_ok());
+        assert!(Currency::enabled(1).contains(&Currency::TWD));
+        let kwd = Currency::from_str("KWD").unwrap();
+        assert_eq!(
+            kwd.ensure_enabled(1).unwrap_err().to_string(),
+            "currency not enabled: KWD"
+        );
+    }
+
+    #[test]
+    fn test_custom_asset() {
+        let points = Currency::register("TESTPTS", 0, 7);
+        assert_eq!(points.code(), "7:TESTPTS");
+        assert_eq!(Currency::register("TESTPTS", 4, 7).precision(), 0);
+        assert!(Currency::from_str("TESTPTS").is_err());
+
+        let parsed = Currency::from_str("7:TESTPTS").unwrap();
+        assert_eq!(parsed, points);
+        assert!(parsed.is_custom());
+        assert_eq!(parsed.numeric_code(), None);
+        assert_eq!(
+            serde_json::from_str::<Currency>(r#""7:TESTPTS""#).unwrap(),
+            points
+        );
+
+        assert!(points.ensure_enabled(7).is_ok());
+        assert!(points.ensure_enabled(8).is_err());
+        assert!(points.ensure_owner(7).is_ok());
+        assert!(points.ensure_owner(8).is_err());
+        assert!(Currency::USD.ensure_owner(8).is_ok());
+        assert!(Currency::enabled(7).contains(&points));
+        assert!(!Currency::enabled(8).contains(&points));
+    }
+
+    #[test]
+    fn test_asset_codes_are_per_tenant() {
+        let first = Currency::register("SHAREDPTS", 0, 7);
+        let second = Currency::register("SHAREDPTS", 2, 8);
+
+        assert_ne!(first, second);
+        assert_eq!(second.code(), "8:SHAREDPTS");
+        assert_eq!(second.precision(), 2);
+        assert_eq!(Currency::from_str("8:SHAREDPTS").unwrap(), second);
+        assert!(second.ensure_enabled(8).is_ok());
+        assert!(second.ensure_enabled(7).is_err());
+    }
+}
@@ -1,36 +1,163 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde::Serialize;
+use serde_json::{json, Value};
 use std::fmt;
 
-#[derive(Debug, Serialize)]
+use crate::event_sourcing::error::DomainError;
+
+// Media type of RFC 7807 problem details.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// Machine readable code of every error the API returns. The codes are part of
+// the API contract, never rename one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadRequest,
+    MalformedRequest,
+    ValidationFailed,
+    Forbidden,
+    NotFound,
+    Conflict,
+    RateLimited,
+    InternalError,
+    AccountNotFound,
+    AccountNotActive,
+    AccountFrozen,
+    DuplicateAccount,
+    HouseAccountNotFound,
+    InsufficientFunds,
+    CurrencyMismatch,
+    UnknownCurrency,
+    CurrencyNotEnabled,
+    AmountOverflow,
+    InvalidPayoutDestination,
+    ConcurrentModification,
+    ShuttingDown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "BAD_REQUEST",
+            ErrorCode::MalformedRequest => "MALFORMED_REQUEST",
+            ErrorCode::ValidationFailed => "VALIDATION_FAILED",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::InternalError => "INTERNAL_ERROR",
+            ErrorCode::AccountNotFound => "ACCOUNT_NOT_FOUND",
+            ErrorCode::AccountNotActive => "ACCOUNT_NOT_ACTIVE",
+            ErrorCode::AccountFrozen => "ACCOUNT_FROZEN",
+            ErrorCode::DuplicateAccount => "DUPLICATE_ACCOUNT",
+            ErrorCode::HouseAccountNotFound => "HOUSE_ACCOUNT_NOT_FOUND",
+            ErrorCode::InsufficientFunds => "INSUFFICIENT_FUNDS",
+            ErrorCode::CurrencyMismatch => "CURRENCY_MISMATCH",
+            ErrorCode::UnknownCurrency => "UNKNOWN_CURRENCY",
+            ErrorCode::CurrencyNotEnabled => "CURRENCY_NOT_ENABLED",
+            ErrorCode::AmountOverflow => "AMOUNT_OVERFLOW",
+            ErrorCode::InvalidPayoutDestination => "INVALID_PAYOUT_DESTINATION",
+            ErrorCode::ConcurrentModification => "CONCURRENT_MODIFICATION",
+            ErrorCode::ShuttingDown => "SHUTTING_DOWN",
+        }
+    }
+
+    // Client errors the request can be fixed for are 4xx, a rejected but
+    // well-formed command is 409 or 422, only failures of ours are 5xx.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest
+            | ErrorCode::MalformedRequest
+            | ErrorCode::UnknownCurrency
+            | ErrorCode::CurrencyNotEnabled => StatusCode::BAD_REQUEST,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound | ErrorCode::AccountNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict
+            | ErrorCode::AccountNotActive
+            | ErrorCode::AccountFrozen
+            | ErrorCode::DuplicateAccount
+            | ErrorCode::ConcurrentModification => StatusCode::CONFLICT,
+            ErrorCode::ValidationFailed
+            | ErrorCode::HouseAccountNotFound
+            | ErrorCode::InsufficientFunds
+            | ErrorCode::CurrencyMismatch
+            | ErrorCode::AmountOverflow
+            | ErrorCode::InvalidPayoutDestination => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// RFC 7807 problem details, `extensions` are merged into the body.
+pub fn problem(code: ErrorCode, detail: &str, extensions: Option<Value>) -> Response {
+    let status = code.status();
+    let mut body = json!({
+        "type": "about:blank",
+        "title": status.canonical_reason().unwrap_or_default(),
+        "status": status.as_u16(),
+        "detail": detail,
+        "code": code.as_str(),
+    });
+    if let (Value::Object(body), Some(Value::Object(extensions))) = (&mut body, extensions) {
+        body.extend(extensions);
+    }
+    (status, [(header::CONTENT_TYPE, PROBLEM_JSON)], Json(body)).into_response()
+}
+
+#[derive(Debug)]
 pub enum AppError {
     BadRequest(String),
+    Forbidden(String),
     NotFound(String),
+    Conflict(String),
+    TooManyRequests(String),
     InternalServerError(String),
+    Domain(DomainError),
 }
 
 impl AppError {
-    fn code(&self) -> u16 {
+    fn code(&self) -> ErrorCode {
         match self {
-            AppError::BadRequest(_) => 400,
-            AppError::NotFound(_) => 404,
-            AppError::InternalServerError(_) => 500,
+            AppError::BadRequest(_) => ErrorCode::BadRequest,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::TooManyRequests(_) => ErrorCode::RateLimited,
+            AppError::InternalServerError(_) => ErrorCode::InternalError,
+            AppError::Domain(err) => err.code(),
         }
     }
 
-    fn message(&self) -> &str {
+    fn message(&self) -> String {
         match self {
-            AppError::BadRequest(msg) => msg,
-            AppError::NotFound(msg) => msg,
-            AppError::InternalServerError(msg) => msg,
+            AppError::BadRequest(msg) => msg.clone(),
+            AppError::Forbidden(msg) => msg.clone(),
+            AppError::NotFound(msg) => msg.clone(),
+            AppError::Conflict(msg) => msg.clone(),
+            AppError::TooManyRequests(msg) => msg.clone(),
+            AppError::InternalServerError(msg) => msg.clone(),
+            AppError::Domain(err) => err.to_string(),
         }
     }
 }
 
+impl From<DomainError> for AppError {
+    fn from(err: DomainError) -> Self {
+        AppError::Domain(err)
+    }
+}
+
 // Step 2: Implement the std::fmt::Display and std::error::Error Traits
 impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -42,13 +169,7 @@ impl std::error::Error for AppError {}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let status_code =
-            StatusCode::from_u16(self.code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        let body = Json(serde_json::json!({
-            "code": self.code(),
-            "message": self.message(),
-        }));
-        (status_code, body).into_response()
+        problem(self.code(), &self.message(), None)
     }
 }
 
@@ -59,57 +180,109 @@ mod tests {
     use axum::response::IntoResponse;
     use serde_json::json;
 
+    async fn problem_of(error: AppError) -> (StatusCode, String, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body_bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            serde_json::from_slice(&body_bytes).unwrap(),
+        )
+    }
+
     #[tokio::test]
     async fn test_bad_request_error() {
         let error = AppError::BadRequest("Bad request".into());
-        assert_eq!(error.code(), 400);
+        assert_eq!(error.code(), ErrorCode::BadRequest);
         assert_eq!(error.message(), "Bad request");
 
-        let response = error.into_response();
-        let status = response.status();
-        let body = response.into_body();
+        let (status, content_type, body) = problem_of(error).await;
 
         assert_eq!(status, StatusCode::BAD_REQUEST);
-
-        let body_bytes = to_bytes(body, usize::MAX).await.unwrap();
-        let body_json: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
-        assert_eq!(body_json, json!({"code": 400, "message": "Bad request"}));
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(
+            body,
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "Bad request",
+                "code": "BAD_REQUEST",
+            })
+        );
     }
 
     #[tokio::test]
-    async fn test_not_found_error() {
-        let error = AppError::NotFound("Not found".into());
-        assert_eq!(error.code(), 404);
-        assert_eq!(error.message(), "Not found");
-
-        let response = error.into_response();
-        let status = response.status();
-        let body = response.into_body();
-
-        assert_eq!(status, StatusCode::NOT_FOUND);
-
-        let body_bytes = to_bytes(body, usize::MAX).await.unwrap();
-        let body_json: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
-        assert_eq!(body_json, json!({"code": 404, "message": "Not found"}));
+    async fn test_status_errors() {
+        let cases = [
+            (AppError::Forbidden("Forbidden".into()), 403, "FORBIDDEN"),
+            (AppError::NotFound("Not found".into()), 404, "NOT_FOUND"),
+            (AppError::Conflict("Conflict".into()), 409, "CONFLICT"),
+            (
+                AppError::TooManyRequests("Too many requests".into()),
+                429,
+                "RATE_LIMITED",
+            ),
+            (
+                AppError::InternalServerError("Internal server error".into()),
+                500,
+                "INTERNAL_ERROR",
+            ),
+        ];
+        for (error, status, code) in cases {
+            let message = error.message();
+            let (actual_status, _, body) = problem_of(error).await;
+            assert_eq!(actual_status.as_u16(), status);
+            assert_eq!(body["status"], status);
+            assert_eq!(body["code"], code);
+            assert_eq!(body["detail"], message);
+        }
     }
 
     #[tokio::test]
-    async fn test_internal_server_error() {
-        let error = AppError::InternalServerError("Internal server error".into());
-        assert_eq!(error.code(), 500);
-        assert_eq!(error.message(), "Internal server error");
-
-        let response = error.into_response();
-        let status = response.status();
-        let body = response.into_body();
-
-        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    async fn test_domain_errors() {
+        let cases = [
+            (DomainError::AccountNotFound, 404, "ACCOUNT_NOT_FOUND"),
+            (DomainError::AccountFrozen, 409, "ACCOUNT_FROZEN"),
+            (DomainError::DuplicateAccount, 409, "DUPLICATE_ACCOUNT"),
+            (DomainError::InsufficientFunds, 422, "INSUFFICIENT_FUNDS"),
+            (DomainError::ShuttingDown, 503, "SHUTTING_DOWN"),
+            (
+                DomainError::CurrencyNotEnabled("EUR".to_string()),
+                400,
+                "CURRENCY_NOT_ENABLED",
+            ),
+            (
+                DomainError::Internal("boom".to_string()),
+                500,
+                "INTERNAL_ERROR",
+            ),
+        ];
+        for (error, status, code) in cases {
+            let detail = error.to_string();
+            let (actual_status, _, body) = problem_of(error.into()).await;
+            assert_eq!(actual_status.as_u16(), status);
+            assert_eq!(body["code"], code);
+            assert_eq!(body["detail"], detail);
+        }
+    }
 
-        let body_bytes = to_bytes(body, usize::MAX).await.unwrap();
-        let body_json: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
-        assert_eq!(
-            body_json,
-            json!({"code": 500, "message": "Internal server error"})
+    #[tokio::test]
+    async fn test_problem_extensions() {
+        let response = problem(
+            ErrorCode::ValidationFailed,
+            "Request is invalid",
+            Some(json!({ "errors": [] })),
         );
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body_bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(body["code"], "VALIDATION_FAILED");
+        assert_eq!(body["errors"], json!([]));
     }
 }
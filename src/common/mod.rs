@@ -1,4 +1,7 @@
 pub mod account;
+pub mod backoff;
+pub mod currency;
 pub mod error;
 pub mod money;
 pub mod snowflake;
+pub mod validation;
@@ -1,66 +1,10 @@
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt;
-use std::ops::{Add, Sub};
-use std::str::FromStr;
 
-#[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "UPPERCASE")]
-pub enum Currency {
-    #[default]
-    USD,
-    TWD,
-}
-
-impl Currency {
-    pub fn precision(&self) -> u32 {
-        match self {
-            Currency::USD => 2,
-            Currency::TWD => 0,
-        }
-    }
-}
-
-impl fmt::Display for Currency {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Currency::USD => write!(f, "USD"),
-            Currency::TWD => write!(f, "TWD"),
-        }
-    }
-}
-
-impl FromStr for Currency {
-    type Err = CurrencyParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "USD" => Ok(Currency::USD),
-            "TWD" => Ok(Currency::TWD),
-            _ => Err(CurrencyParseError),
-        }
-    }
-}
-
-impl From<String> for Currency {
-    fn from(s: String) -> Self {
-        Currency::from_str(&s).unwrap_or_default()
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct CurrencyParseError;
-
-impl fmt::Display for CurrencyParseError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "invalid currency")
-    }
-}
-
-impl Error for CurrencyParseError {}
+pub use crate::common::currency::{Currency, CurrencyParseError};
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct Money {
@@ -70,41 +14,68 @@ pub struct Money {
 
 /// Convert decimal into Money type with precision.
 /// let usd_amount = Money::new(dec!(100.00), Currency::USD);
-/// let twd_amount = Money::new(dec!(100), Currency::TWD);
+/// let twd_amount = Money::new(dec!(100.00), Currency::TWD);
 impl Money {
     pub fn new(amount: Decimal, currency: Currency) -> Self {
         Money { amount, currency }
     }
-}
 
-impl Add for Money {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self::Output {
-        if self.currency != other.currency {
-            panic!("Cannot add Money with different currencies");
-        }
+    /// Rounds the amount half-even to the minor unit of its currency.
+    pub fn round(&self) -> Money {
         Money {
-            amount: self.amount + other.amount,
+            amount: self.amount.round_dp_with_strategy(
+                self.currency.precision(),
+                RoundingStrategy::MidpointNearestEven,
+            ),
             currency: self.currency,
         }
     }
-}
 
-impl Sub for Money {
-    type Output = Self;
+    pub fn try_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(&other)?;
+        let amount = self
+            .amount
+            .checked_add(other.amount)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
 
-    fn sub(self, other: Self) -> Self::Output {
-        if self.currency != other.currency {
-            panic!("Cannot subtract Money with different currencies");
+    pub fn try_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(&other)?;
+        let amount = self
+            .amount
+            .checked_sub(other.amount)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    fn ensure_same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        match self.currency == other.currency {
+            true => Ok(()),
+            false => Err(MoneyError::CurrencyMismatch(self.currency, other.currency)),
         }
-        Money {
-            amount: self.amount - other.amount,
-            currency: self.currency,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoneyError {
+    CurrencyMismatch(Currency, Currency),
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(left, right) => {
+                write!(f, "currency mismatch: {} and {}", left, right)
+            }
+            MoneyError::Overflow => write!(f, "amount overflow"),
         }
     }
 }
 
+impl Error for MoneyError {}
+
 impl PartialOrd for Money {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         if self.currency != other.currency {
@@ -120,7 +91,7 @@ impl fmt::Display for Money {
         write!(
             f,
             "{:.precision$}",
-            self.amount,
+            self.round().amount,
             precision = precision as usize
         )
     }
@@ -130,6 +101,7 @@ impl fmt::Display for Money {
 mod money_tests {
     use super::*;
     use rust_decimal_macros::dec;
+    use std::str::FromStr;
 
     #[test]
     fn test_currency_default() {
@@ -148,13 +120,16 @@ mod money_tests {
     fn test_currency_from_str() {
         assert_eq!(Currency::from_str("USD").unwrap(), Currency::USD);
         assert_eq!(Currency::from_str("TWD").unwrap(), Currency::TWD);
-        assert!(Currency::from_str("EUR").is_err());
+        assert!(Currency::from_str("EUR").is_ok());
+        assert!(Currency::from_str("ABC").is_err());
     }
 
     #[test]
     fn test_currency_precision() {
         assert_eq!(Currency::USD.precision(), 2);
-        assert_eq!(Currency::TWD.precision(), 0);
+        assert_eq!(Currency::TWD.precision(), 2);
+        assert_eq!(Currency::from_str("JPY").unwrap().precision(), 0);
+        assert_eq!(Currency::from_str("KWD").unwrap().precision(), 3);
     }
 
     #[test]
@@ -168,40 +143,6 @@ mod money_tests {
         assert_eq!(twd_amount.currency, Currency::TWD);
     }
 
-    #[test]
-    fn test_money_addition() {
-        let usd1 = Money::new(dec!(50.00), Currency::USD);
-        let usd2 = Money::new(dec!(25.00), Currency::USD);
-        let result = usd1 + usd2;
-        assert_eq!(result.amount, dec!(75.00));
-        assert_eq!(result.currency, Currency::USD);
-    }
-
-    #[test]
-    #[should_panic(expected = "Cannot add Money with different currencies")]
-    fn test_money_addition_different_currencies() {
-        let usd = Money::new(dec!(50.00), Currency::USD);
-        let twd = Money::new(dec!(50), Currency::TWD);
-        let _ = usd + twd;
-    }
-
-    #[test]
-    fn test_money_subtraction() {
-        let usd1 = Money::new(dec!(50.00), Currency::USD);
-        let usd2 = Money::new(dec!(25.00), Currency::USD);
-        let result = usd1 - usd2;
-        assert_eq!(result.amount, dec!(25.00));
-        assert_eq!(result.currency, Currency::USD);
-    }
-
-    #[test]
-    #[should_panic(expected = "Cannot subtract Money with different currencies")]
-    fn test_money_subtraction_different_currencies() {
-        let usd = Money::new(dec!(50.00), Currency::USD);
-        let twd = Money::new(dec!(50), Currency::TWD);
-        let _ = usd - twd;
-    }
-
     #[test]
     fn test_money_partial_cmp() {
         let usd1 = Money::new(dec!(50.00), Currency::USD);
@@ -228,7 +169,46 @@ mod money_tests {
         let usd = Money::new(dec!(50.00), Currency::USD);
         let twd = Money::new(dec!(50.00), Currency::TWD);
         assert_eq!(format!("{}", usd), "50.00");
-        assert_eq!(format!("{}", twd), "50");
+        assert_eq!(format!("{}", twd), "50.00");
+    }
+
+    #[test]
+    fn test_money_display_minor_units() {
+        let jpy = Money::new(dec!(1500), Currency::from_str("JPY").unwrap());
+        let kwd = Money::new(dec!(1.5), Currency::from_str("KWD").unwrap());
+        assert_eq!(format!("{}", jpy), "1500");
+        assert_eq!(format!("{}", kwd), "1.500");
+    }
+
+    #[test]
+    fn test_money_round() {
+        let usd = Money::new(dec!(2.345), Currency::USD);
+        assert_eq!(usd.round().amount, dec!(2.34));
+        let negative = Money::new(dec!(-2.355), Currency::USD);
+        assert_eq!(negative.round().amount, dec!(-2.36));
+
+        let jpy = Money::new(dec!(2.5), Currency::from_str("JPY").unwrap());
+        assert_eq!(jpy.round().amount, dec!(2));
+        assert_eq!(
+            format!("{}", Money::new(dec!(0.125), Currency::USD)),
+            "0.12"
+        );
+    }
+
+    #[test]
+    fn test_money_try_add_sub() {
+        let usd = Money::new(dec!(50.00), Currency::USD);
+        let twd = Money::new(dec!(50), Currency::TWD);
+        assert_eq!(usd.try_add(usd).unwrap().amount, dec!(100.00));
+        assert_eq!(usd.try_sub(usd).unwrap().amount, dec!(0));
+        assert_eq!(
+            usd.try_add(twd).unwrap_err(),
+            MoneyError::CurrencyMismatch(Currency::USD, Currency::TWD)
+        );
+        assert!(usd.try_sub(twd).is_err());
+
+        let max = Money::new(Decimal::MAX, Currency::USD);
+        assert_eq!(max.try_add(usd).unwrap_err(), MoneyError::Overflow);
     }
 
     #[test]
@@ -240,6 +220,6 @@ mod money_tests {
     #[test]
     fn test_money_display_zero_twd() {
         let money = Money::new(dec!(0), Currency::TWD);
-        assert_eq!(format!("{}", money), "0");
+        assert_eq!(format!("{}", money), "0.00");
     }
 }
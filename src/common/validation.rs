@@ -0,0 +1,199 @@
+use axum::extract::rejection::BytesRejection;
+use axum::response::{IntoResponse, Response};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::common::error::{problem, ErrorCode};
+use crate::common::money::Money;
+
+// Largest amount a single command can move, 15 integer digits.
+pub const MAX_AMOUNT: Decimal = dec!(999_999_999_999_999);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    // Path of the field in the payload, e.g. `Deposit.amount`.
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found in a payload, returned at once so callers can fix them
+/// together.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationErrors {
+    // Set when the payload could not be parsed, `errors` has a single entry.
+    malformed: bool,
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+
+    // Error of a body that could not be read or parsed.
+    fn malformed(field: &str, message: String) -> ValidationErrors {
+        let mut errors = ValidationErrors {
+            malformed: true,
+            ..Default::default()
+        };
+        errors.add(field, message);
+        errors
+    }
+
+    /// Checks an amount that is moved: positive, in minor units of its
+    /// currency and at most `MAX_AMOUNT`.
+    pub fn check_amount(&mut self, field: &str, money: &Money) {
+        let precision = money.currency.precision();
+        if money.amount <= Decimal::ZERO {
+            self.add(field, "must be greater than 0");
+        } else if money.amount > MAX_AMOUNT {
+            self.add(field, format!("must be at most {}", MAX_AMOUNT));
+        }
+        if money.amount.normalize().scale() > precision {
+            self.add(
+                field,
+                format!("{} allows at most {} decimals", money.currency, precision),
+            );
+        }
+    }
+
+    pub fn check_text(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "is required");
+        } else if value.chars().count() > max_len {
+            self.add(field, format!("must be at most {} characters", max_len));
+        }
+    }
+}
+
+impl From<BytesRejection> for ValidationErrors {
+    fn from(err: BytesRejection) -> Self {
+        ValidationErrors::malformed("body", err.body_text())
+    }
+}
+
+// A body that is not valid JSON or does not match the payload, at the path
+// where deserializing stopped.
+impl From<serde_path_to_error::Error<serde_json::Error>> for ValidationErrors {
+    fn from(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let field = match err.path().to_string().as_str() {
+            "." => "body".to_string(),
+            path => path.to_string(),
+        };
+        ValidationErrors::malformed(&field, err.into_inner().to_string())
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        let (code, detail) = match self.malformed {
+            true => (
+                ErrorCode::MalformedRequest,
+                "Request body could not be read",
+            ),
+            false => (ErrorCode::ValidationFailed, "Request is invalid"),
+        };
+        problem(code, detail, Some(json!({ "errors": self.errors })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::money::Currency;
+    use std::str::FromStr;
+
+    fn amount_errors(money: Money) -> Vec<String> {
+        let mut errors = ValidationErrors::default();
+        errors.check_amount("amount", &money);
+        errors.errors.into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn test_check_amount() {
+        assert!(amount_errors(Money::new(dec!(100.10), Currency::USD)).is_empty());
+        // Trailing zeros are not decimals.
+        assert!(amount_errors(Money::new(dec!(100.1000), Currency::USD)).is_empty());
+        assert_eq!(
+            amount_errors(Money::new(dec!(0), Currency::USD)),
+            vec!["must be greater than 0"]
+        );
+        assert_eq!(
+            amount_errors(Money::new(dec!(-5), Currency::USD)),
+            vec!["must be greater than 0"]
+        );
+        assert_eq!(
+            amount_errors(Money::new(dec!(100.123), Currency::USD)),
+            vec!["USD allows at most 2 decimals"]
+        );
+        let jpy = Currency::from_str("JPY").unwrap();
+        assert_eq!(
+            amount_errors(Money::new(dec!(100.5), jpy)),
+            vec!["JPY allows at most 0 decimals"]
+        );
+        assert_eq!(
+            amount_errors(Money::new(dec!(1_000_000_000_000_000), Currency::USD)),
+            vec!["must be at most 999999999999999"]
+        );
+    }
+
+    #[test]
+    fn test_check_text() {
+        let mut errors = ValidationErrors::default();
+        errors.check_text("name", "  ", 10);
+        errors.check_text("other", "abcdefghijk", 10);
+        errors.check_text("fine", "abc", 10);
+        assert_eq!(
+            errors.errors,
+            vec![
+                FieldError {
+                    field: "name".to_string(),
+                    message: "is required".to_string()
+                },
+                FieldError {
+                    field: "other".to_string(),
+                    message: "must be at most 10 characters".to_string()
+                },
+            ]
+        );
+        assert!(errors.into_result().is_err());
+        assert!(ValidationErrors::default().into_result().is_ok());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let body = br#"{"Deposit": {"id": "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f", "amount": {"amount": "1", "currency": "ABC"}}}"#;
+        let err = serde_path_to_error::deserialize::<
+            _,
+            crate::event_sourcing::command::BankAccountCommand,
+        >(&mut serde_json::Deserializer::from_slice(body))
+        .unwrap_err();
+
+        let errors = ValidationErrors::from(err);
+
+        assert!(errors.malformed);
+        assert_eq!(errors.errors[0].field, "Deposit.amount.currency");
+        assert!(errors.errors[0]
+            .message
+            .starts_with("invalid currency: ABC"));
+
+        let err = serde_path_to_error::deserialize::<
+            _,
+            crate::event_sourcing::command::BankAccountCommand,
+        >(&mut serde_json::Deserializer::from_slice(b"{"))
+        .unwrap_err();
+        assert_eq!(ValidationErrors::from(err).errors[0].field, "body");
+    }
+}
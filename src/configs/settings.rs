@@ -3,10 +3,22 @@ use lazy_static::lazy_static;
 use serde::Deserialize;
 use tracing::info;
 
+use crate::domain::rate_limit::RateLimit;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub redis: RedisSettings,
+    pub ach: AchSettings,
+    pub webhook: WebhookSettings,
+    pub outbox: OutboxSettings,
+    pub stream: StreamSettings,
+    pub auth: AuthSettings,
+    pub rate_limit: RateLimitSettings,
+    pub currency: CurrencySettings,
+    pub shutdown: ShutdownSettings,
+    pub health: HealthSettings,
+    pub http: HttpSettings,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -25,6 +37,91 @@ pub struct RedisSettings {
     pub port: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct AchSettings {
+    pub immediate_destination: String,
+    pub immediate_destination_name: String,
+    pub immediate_origin: String,
+    pub immediate_origin_name: String,
+    pub company_name: String,
+    pub company_id: String,
+    pub odfi_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookSettings {
+    pub max_attempts: i32,
+    pub backoff_base_secs: i64,
+    pub timeout_secs: u64,
+    // How long an instance holds the deliveries it claimed, a batch timing
+    // out on every request must fit in it.
+    pub lease_secs: i64,
+}
+
+// Retries of outbox commands the ledger job failed to apply.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutboxSettings {
+    pub max_attempts: i32,
+    pub backoff_base_secs: i64,
+    // Sweep for due commands, notifications settle new ones right away.
+    pub poll_interval_secs: u64,
+    // How long an instance holds the commands it claimed, a batch must be
+    // applied within it or another instance may claim the same commands.
+    pub lease_secs: i64,
+}
+
+// Time a server gets after SIGTERM to finish open requests, queued commands
+// and running jobs before it exits anyway.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShutdownSettings {
+    pub deadline_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthSettings {
+    // `/readyz` fails while more commands wait for the command processor.
+    pub max_queued_commands: usize,
+    // A transaction processing for longer is reported as stuck.
+    pub stuck_transaction_secs: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpSettings {
+    // Addresses or CIDR ranges of the reverse proxies whose `X-Forwarded-For`
+    // is believed, the peer address is the client otherwise.
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamSettings {
+    pub history_size: usize,
+    pub channel_capacity: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthSettings {
+    pub service_token_ttl_days: i64,
+    pub access_token_ttl_secs: i64,
+    // How long a rotated token keeps working next to its replacement.
+    pub rotation_grace_secs: i64,
+    pub revocation_cache_secs: u64,
+    // How long the signing keys are cached before reloading them.
+    pub key_refresh_secs: u64,
+}
+
+// Limits of tenants without their own in `tenants.rate_limits`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitSettings {
+    pub read: RateLimit,
+    pub money: RateLimit,
+}
+
+// ISO 4217 codes accepted for new accounts and money movements.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CurrencySettings {
+    pub enabled: Vec<String>,
+}
+
 lazy_static! {
     pub static ref SETTINGS: Settings = Settings::new();
 }
@@ -93,6 +190,17 @@ mod tests {
         assert_eq!(settings.database.dbname, "bankie_main");
         assert_eq!(settings.redis.host, "localhost");
         assert_eq!(settings.redis.port, "6379");
+        assert_eq!(settings.ach.odfi_id, "09100001");
+        assert_eq!(settings.webhook.max_attempts, 8);
+        assert_eq!(settings.webhook.lease_secs, 1200);
+        assert_eq!(settings.outbox.max_attempts, 6);
+        assert_eq!(settings.outbox.poll_interval_secs, 30);
+        assert_eq!(settings.outbox.lease_secs, 300);
+        assert_eq!(settings.shutdown.deadline_secs, 30);
+        assert_eq!(settings.health.max_queued_commands, 1000);
+        assert_eq!(settings.http.trusted_proxies, vec!["127.0.0.1", "::1"]);
+        assert_eq!(settings.stream.history_size, 1000);
+        assert_eq!(settings.auth.access_token_ttl_secs, 900);
     }
 
     #[test]
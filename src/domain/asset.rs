@@ -0,0 +1,86 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+use crate::common::money::Currency;
+
+// Decimals of the smallest unit, 18 covers tokens like ERC-20 stablecoins.
+pub const ASSET_MAX_MINOR_UNITS: i32 = 18;
+
+// Custom asset of a tenant (loyalty points, stablecoins, vouchers), accepted
+// wherever an ISO 4217 currency is.
+#[derive(FromRow, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    pub code: String,
+    #[serde(skip)]
+    pub tenant_id: i32,
+    pub name: String,
+    pub minor_units: i32,
+    #[serde(skip_deserializing)]
+    pub created_at: NaiveDateTime,
+}
+
+impl Asset {
+    pub fn register(&self) -> Currency {
+        Currency::register(&self.code, self.minor_units as u32, self.tenant_id)
+    }
+
+    // Codes are 4 to 12 characters so they never clash with a 3-letter
+    // ISO 4217 code, present or future.
+    pub fn validate(&self) -> Result<(), String> {
+        let code = self.code.as_bytes();
+        if !(4..=12).contains(&code.len())
+            || !code[0].is_ascii_uppercase()
+            || !code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        {
+            return Err(
+                "code must be 4 to 12 uppercase letters or digits, starting with a letter"
+                    .to_string(),
+            );
+        }
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if !(0..=ASSET_MAX_MINOR_UNITS).contains(&self.minor_units) {
+            return Err(format!(
+                "minor_units must be between 0 and {}",
+                ASSET_MAX_MINOR_UNITS
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let asset = Asset {
+            code: "POINTS".to_string(),
+            name: "Reward points".to_string(),
+            minor_units: 0,
+            ..Default::default()
+        };
+        assert!(asset.validate().is_ok());
+
+        for code in ["USD", "points", "1POINT", "POINTS-1", "ABCDEFGHIJKLM"] {
+            let asset = Asset {
+                code: code.to_string(),
+                ..asset.clone()
+            };
+            assert!(asset.validate().is_err(), "{}", code);
+        }
+        let asset = Asset {
+            minor_units: 19,
+            ..asset
+        };
+        assert_eq!(
+            asset.validate().unwrap_err(),
+            "minor_units must be between 0 and 18"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct OutboxBacklog {
+    pub pending: i64,
+    pub dead: i64,
+    // Age of the oldest pending command, `None` when nothing is pending.
+    pub oldest_pending_secs: Option<i64>,
+}
+
+// Aggregates whose view has not caught up with their events, the view update
+// failed after the events were committed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ProjectionLag {
+    pub projection: String,
+    pub aggregates_behind: i64,
+    pub max_events_behind: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Diagnostics {
+    pub outbox: OutboxBacklog,
+    // Transactions processing for longer than `health.stuck_transaction_secs`.
+    pub stuck_transactions: i64,
+    pub projections: Vec<ProjectionLag>,
+}
+
+// Balances of every ledger in a currency, summed across tenants.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct LedgerTotal {
+    pub currency: String,
+    pub available: Decimal,
+    pub pending: Decimal,
+    pub current: Decimal,
+}
@@ -8,6 +8,10 @@ use crate::{
 
 use super::models::{BankAccountKind, BankAccountType};
 
+// Current schema version of every event, stored events of older versions are
+// upcasted on load, see `event_sourcing::upcaster`.
+pub const EVENT_VERSION: &str = "2.0.0";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum BankAccountEvent {
     AccountOpened {
@@ -45,10 +49,21 @@ impl DomainEvent for BankAccountEvent {
     }
 
     fn event_version(&self) -> String {
-        "1.0".to_string()
+        EVENT_VERSION.to_string()
     }
 }
 
+// Serialized with the same names version 1.0 used for the free-form string.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerTransactionType {
+    DebitHold,
+    DebitRelease,
+    DebitCancel,
+    CreditHold,
+    CreditRelease,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum LedgerEvent {
     LedgerInitiated {
@@ -58,7 +73,7 @@ pub enum LedgerEvent {
     LedgerUpdated {
         amount: Money,
         transaction_id: String,
-        transaction_type: String,
+        transaction_type: LedgerTransactionType,
         available_delta: Money,
         pending_delta: Money,
         base_event: BaseEvent,
@@ -75,6 +90,6 @@ impl DomainEvent for LedgerEvent {
     }
 
     fn event_version(&self) -> String {
-        "1.0".to_string()
+        EVENT_VERSION.to_string()
     }
 }
@@ -1,11 +1,12 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use rust_decimal::Decimal;
 use serde::Serialize;
 use serde_json::Value;
 use sqlx::prelude::FromRow;
+use std::str::FromStr;
 use uuid::Uuid;
 
-use crate::common::money::Money;
+use crate::common::money::{Currency, CurrencyParseError, Money};
 
 use super::models::LedgerAction;
 
@@ -25,6 +26,7 @@ pub struct Transaction {
     pub status: String,
     #[allow(dead_code)]
     pub journal_entry_id: Option<Uuid>,
+    pub tenant_id: i32,
 }
 
 #[derive(Debug, Serialize)]
@@ -54,18 +56,19 @@ impl Transaction {
 }
 
 impl Transaction {
-    pub fn into_transaction_with_money(self) -> TransactionWithMoney {
-        TransactionWithMoney {
+    pub fn into_transaction_with_money(self) -> Result<TransactionWithMoney, CurrencyParseError> {
+        let currency = Currency::from_str(&self.currency)?;
+        Ok(TransactionWithMoney {
             id: self.id,
             bank_account_id: self.bank_account_id,
             transaction_reference: self.transaction_reference,
             transaction_date: self.transaction_date,
-            amount: format!("{}", Money::new(self.amount, self.currency.clone().into())),
+            amount: format!("{}", Money::new(self.amount, currency)),
             currency: self.currency,
             description: self.description,
             metadata: self.metadata,
             status: self.status,
-        }
+        })
     }
 }
 
@@ -89,14 +92,39 @@ pub struct JournalLine {
     pub description: Option<String>,
 }
 
+pub const OUTBOX_CREDIT: &str = "LedgerCommand::Credit";
+// Settles the hold placed when the withdrawal was accepted.
+pub const OUTBOX_DEBIT: &str = "LedgerCommand::Debit";
+
+pub const OUTBOX_PENDING: &str = "pending";
+pub const OUTBOX_PROCESSED: &str = "processed";
+// Gave up after `outbox.max_attempts`, the transaction is failed.
+pub const OUTBOX_DEAD: &str = "dead";
+// Dead-lettered and acknowledged by an operator, never retried.
+pub const OUTBOX_SKIPPED: &str = "skipped";
+
 #[derive(FromRow, Debug)]
 pub struct Outbox {
-    #[allow(dead_code)]
     pub id: i32,
-    #[allow(dead_code)]
     pub transaction_id: Uuid,
     pub event_type: String,
     pub payload: Value,
-    #[allow(dead_code)]
-    pub processed: bool,
+    // Metadata of the API call that queued the command.
+    pub metadata: Value,
+    pub attempts: i32,
+}
+
+// An outbox row as listed to operators.
+#[derive(FromRow, Debug, Serialize)]
+pub struct OutboxEntry {
+    pub id: i32,
+    pub transaction_id: Uuid,
+    pub event_type: String,
+    pub payload: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub hold_released: bool,
+    pub created_at: Option<NaiveDateTime>,
 }
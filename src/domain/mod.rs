@@ -1,5 +1,11 @@
+pub mod asset;
+pub mod diagnostics;
 pub mod events;
 pub mod finance;
 pub mod models;
+pub mod payment;
+pub mod rate_limit;
+pub mod signing_key;
 pub mod tenant;
 pub mod user;
+pub mod webhook;
@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 use uuid::Uuid;
 
 use crate::common::money::{Currency, Money};
+use crate::common::validation::ValidationErrors;
+use crate::domain::events::LedgerTransactionType;
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum BankAccountStatus {
@@ -57,6 +60,8 @@ pub struct BankAccount {
     pub ledger_id: String,
     pub user_id: String,
     pub timestamp: String,
+    #[serde(default)]
+    pub tenant_id: i32,
 }
 
 #[derive(Serialize, Default, Deserialize, sqlx::FromRow)]
@@ -71,6 +76,20 @@ pub struct HouseAccount {
     #[serde(skip_deserializing)]
     pub ledger_id: String,
     pub currency: Currency,
+    #[serde(skip)]
+    pub tenant_id: i32,
+}
+
+impl HouseAccount {
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check_text("account_name", &self.account_name, 100);
+        errors.check_text("account_type", &self.account_type, 50);
+        if !["active", "inactive"].contains(&self.status.as_str()) {
+            errors.add("status", "must be active or inactive");
+        }
+        errors.into_result()
+    }
 }
 
 // The view for a BankAccount query
@@ -86,6 +105,11 @@ pub struct BankAccountView {
     pub currency: Currency,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub tenant_id: i32,
+    // Sequence of the last event applied, an event delivered again is ignored.
+    #[serde(default)]
+    pub sequence: usize,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -96,6 +120,12 @@ pub struct Ledger {
     pub pending: Money,
     pub amount: Money,
     pub timestamp: String,
+    #[serde(default)]
+    pub tenant_id: i32,
+    // Last step applied of every transaction, a command delivered again
+    // repeats it and changes nothing.
+    #[serde(default)]
+    pub transactions: HashMap<String, LedgerTransactionType>,
 }
 
 // The view for a Ledger query
@@ -108,4 +138,9 @@ pub struct LedgerView {
     pub current: Money,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub tenant_id: i32,
+    // Sequence of the last event applied, an event delivered again is ignored.
+    #[serde(default)]
+    pub sequence: usize,
 }
@@ -0,0 +1,117 @@
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::payment::nacha::is_valid_routing_number;
+
+pub const ACH_ENTRY_SENT: &str = "sent";
+pub const ACH_ENTRY_RETURNED: &str = "returned";
+
+// Unique index of the file ID modifiers used each day.
+pub const ACH_FILE_MODIFIER_KEY: &str = "ach_files_created_on_file_id_modifier_key";
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PayoutAccountKind {
+    #[default]
+    Checking,
+    Savings,
+}
+
+// External bank account that a withdrawal is paid out to, carried in the
+// transaction metadata until the withdrawal is batched into an ACH file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PayoutDestination {
+    pub routing_number: String,
+    pub account_number: String,
+    pub account_holder_name: String,
+    #[serde(default)]
+    pub account_kind: PayoutAccountKind,
+}
+
+impl PayoutDestination {
+    pub fn is_valid(&self) -> bool {
+        is_valid_routing_number(&self.routing_number)
+            && !self.account_number.is_empty()
+            && self.account_number.len() <= 17
+            && self
+                .account_number
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+            && !self.account_holder_name.trim().is_empty()
+    }
+}
+
+#[derive(FromRow, Debug, Serialize)]
+pub struct AchFile {
+    pub id: Uuid,
+    pub file_id_modifier: String,
+    pub entry_count: i32,
+    pub total_credit: Decimal,
+    pub entry_hash: i64,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    #[serde(skip)]
+    pub tenant_id: i32,
+}
+
+#[derive(FromRow, Debug, Clone, Serialize)]
+pub struct AchFileEntry {
+    pub trace_number: String,
+    pub ach_file_id: Uuid,
+    pub transaction_id: Uuid,
+    pub bank_account_id: Uuid,
+    pub amount: Decimal,
+    pub status: String,
+    pub return_code: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn destination() -> PayoutDestination {
+        PayoutDestination {
+            routing_number: "021000021".to_string(),
+            account_number: "123456789".to_string(),
+            account_holder_name: "Jane Doe".to_string(),
+            account_kind: PayoutAccountKind::Checking,
+        }
+    }
+
+    #[test]
+    fn test_payout_destination_valid() {
+        assert!(destination().is_valid());
+    }
+
+    #[test]
+    fn test_payout_destination_invalid_routing_number() {
+        let mut destination = destination();
+        destination.routing_number = "021000022".to_string();
+        assert!(!destination.is_valid());
+    }
+
+    #[test]
+    fn test_payout_destination_invalid_account_number() {
+        let mut destination = destination();
+        destination.account_number = "123456789012345678".to_string();
+        assert!(!destination.is_valid());
+
+        destination.account_number = "".to_string();
+        assert!(!destination.is_valid());
+    }
+
+    #[test]
+    fn test_payout_destination_deserialize_default_kind() {
+        let destination: PayoutDestination = serde_json::from_str(
+            r#"{
+                "routing_number": "021000021",
+                "account_number": "123456789",
+                "account_holder_name": "Jane Doe"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(destination.account_kind, PayoutAccountKind::Checking);
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+// Every route that does not move money.
+pub const ROUTE_CLASS_READ: &str = "read";
+// Deposits, withdrawals, house accounts and ACH files.
+pub const ROUTE_CLASS_MONEY: &str = "money";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    // Requests per second the token bucket refills with.
+    pub rate: f64,
+    // Requests that can be made at once after being idle.
+    pub burst: u32,
+    // Requests per UTC day, unlimited when 0.
+    pub daily_quota: u64,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err("rate must be greater than 0".to_string());
+        }
+        if self.burst == 0 {
+            return Err("burst must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Limits of a tenant that replace the `rate_limit` settings of a route class,
+/// stored in `tenants.rate_limits`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantRateLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<RateLimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub money: Option<RateLimit>,
+}
+
+impl TenantRateLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        for (class, limit) in [
+            (ROUTE_CLASS_READ, self.read),
+            (ROUTE_CLASS_MONEY, self.money),
+        ] {
+            if let Some(limit) = limit {
+                limit
+                    .validate()
+                    .map_err(|err| format!("{}: {}", class, err))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let limit = RateLimit {
+            rate: 5.0,
+            burst: 10,
+            daily_quota: 0,
+        };
+        let mut limits = TenantRateLimits {
+            read: None,
+            money: Some(limit),
+        };
+        assert!(limits.validate().is_ok());
+
+        limits.money = Some(RateLimit { rate: 0.0, ..limit });
+        assert_eq!(
+            limits.validate().unwrap_err(),
+            "money: rate must be greater than 0"
+        );
+        limits.money = Some(RateLimit { burst: 0, ..limit });
+        assert_eq!(
+            limits.validate().unwrap_err(),
+            "money: burst must be at least 1"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_rejects_unknown_class() {
+        let limits: Result<TenantRateLimits, _> =
+            serde_json::from_str(r#"{"write":{"rate":1,"burst":1,"daily_quota":0}}"#);
+        assert!(limits.is_err());
+
+        let limits: TenantRateLimits =
+            serde_json::from_str(r#"{"money":{"rate":1,"burst":2,"daily_quota":100}}"#).unwrap();
+        assert_eq!(limits.money.unwrap().daily_quota, 100);
+        assert_eq!(limits.read, None);
+    }
+}
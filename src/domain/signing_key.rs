@@ -0,0 +1,16 @@
+use chrono::NaiveDateTime;
+use serde_json::Value;
+
+pub const SIGNING_KEY_ACTIVE: &str = "active";
+
+// Asymmetric key tokens are signed with, the newest active key signs and all
+// active keys verify.
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: String,
+    pub private_key: String,
+    pub public_jwk: Value,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+}
@@ -1,3 +1,22 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::rate_limit::TenantRateLimits;
+
+pub const TENANT_ACTIVE: &str = "active";
+// Created, waiting for its first token.
+pub const TENANT_INACTIVE: &str = "inactive";
+pub const TENANT_SUSPENDED: &str = "suspended";
+// Deleted tenants are kept, their data still references them.
+pub const TENANT_DELETED: &str = "deleted";
+
+// Long-lived token issued by `--mode jwt` or a rotation.
+pub const TOKEN_KIND_SERVICE: &str = "service";
+// Short-lived token issued by the client-credentials grant.
+pub const TOKEN_KIND_ACCESS: &str = "access";
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Tenant {
@@ -6,4 +25,77 @@ pub struct Tenant {
     pub jwt: String,
     pub status: String,
     pub scope: Option<String>,
+    pub rate_limits: TenantRateLimits,
+}
+
+// Tenant as shown by the admin API, without its token.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantSummary {
+    pub id: i32,
+    pub name: String,
+    pub status: String,
+    pub scope: String,
+    pub rate_limits: Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantAuditEntry {
+    // Assigned by the database.
+    pub id: i64,
+    pub tenant_id: i32,
+    pub action: String,
+    pub actor_tenant_id: i32,
+    pub actor: String,
+    pub detail: Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantToken {
+    pub jti: Uuid,
+    #[serde(skip)]
+    pub tenant_id: i32,
+    pub kind: String,
+    pub scope: String,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl TenantToken {
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.expires_at > now && self.revoked_at.is_none_or(|revoked_at| revoked_at > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_token_is_active() {
+        let now = Utc::now().naive_utc();
+        let mut token = TenantToken {
+            jti: Uuid::new_v4(),
+            tenant_id: 1,
+            kind: TOKEN_KIND_SERVICE.to_string(),
+            scope: "ledger:read".to_string(),
+            expires_at: now + Duration::hours(1),
+            revoked_at: None,
+        };
+        assert!(token.is_active(now));
+
+        // Rotated, still in its grace period.
+        token.revoked_at = Some(now + Duration::minutes(5));
+        assert!(token.is_active(now));
+
+        token.revoked_at = Some(now);
+        assert!(!token.is_active(now));
+
+        token.revoked_at = None;
+        token.expires_at = now - Duration::seconds(1);
+        assert!(!token.is_active(now));
+    }
 }
@@ -0,0 +1,169 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::prelude::FromRow;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+pub const WEBHOOK_PENDING: &str = "pending";
+pub const WEBHOOK_DELIVERED: &str = "delivered";
+pub const WEBHOOK_DEAD: &str = "dead";
+
+pub const TRANSACTION_COMPLETED: &str = "transaction.completed";
+pub const TRANSACTION_FAILED: &str = "transaction.failed";
+
+// Event types a tenant can subscribe a webhook endpoint to.
+pub const WEBHOOK_EVENT_TYPES: [&str; 5] = [
+    "bank_account.opened",
+    "bank_account.kyc_approved",
+    "ledger.updated",
+    TRANSACTION_COMPLETED,
+    TRANSACTION_FAILED,
+];
+
+#[derive(FromRow, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    #[serde(skip_deserializing)]
+    pub id: Uuid,
+    #[serde(skip)]
+    pub tenant_id: i32,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub event_types: Vec<String>,
+    #[serde(skip_deserializing)]
+    pub status: String,
+}
+
+// Deliveries carry signed ledger data, so they only go out over TLS and to a
+// named host. Where the name resolves to is checked again on every delivery.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|_| "url is invalid".to_string())?;
+    if url.scheme() != "https" {
+        return Err("url must be https".to_string());
+    }
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.parse::<IpAddr>().is_ok() {
+        return Err("url must name a host, not an IP address".to_string());
+    }
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || host == "localhost" || host.ends_with(".localhost") {
+        return Err("url must name a public host".to_string());
+    }
+    Ok(())
+}
+
+impl WebhookEndpoint {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_url(&self.url)?;
+        if self.event_types.is_empty() {
+            return Err("event_types must not be empty".to_string());
+        }
+        if let Some(unknown) = self
+            .event_types
+            .iter()
+            .find(|t| !WEBHOOK_EVENT_TYPES.contains(&t.as_str()))
+        {
+            return Err(format!("unknown event type: {}", unknown));
+        }
+        Ok(())
+    }
+}
+
+#[derive(FromRow, Debug, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub endpoint_id: Uuid,
+    pub event_type: String,
+    pub payload: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+// A delivery that is due, joined with the endpoint it should be sent to.
+#[derive(FromRow, Debug)]
+pub struct PendingWebhookDelivery {
+    pub id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event_type: String,
+    pub payload: Value,
+    pub attempts: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint() -> WebhookEndpoint {
+        WebhookEndpoint {
+            url: "https://example.com/hooks".to_string(),
+            event_types: vec!["ledger.updated".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_webhook_endpoint() {
+        assert!(endpoint().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_webhook_endpoint_invalid_url() {
+        let mut endpoint = endpoint();
+        endpoint.url = "ftp://example.com".to_string();
+        assert!(endpoint.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_url_rejects_internal_targets() {
+        for url in [
+            "http://example.com/hooks",
+            "https://127.0.0.1/hooks",
+            "https://2130706433/hooks",
+            "https://10.0.0.8/hooks",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hooks",
+            "https://[fd00::1]/hooks",
+            "https://localhost/hooks",
+            "https://api.localhost./hooks",
+        ] {
+            assert!(validate_url(url).is_err(), "{}", url);
+        }
+        assert!(validate_url("https://hooks.example.com:8443/bankie").is_ok());
+    }
+
+    #[test]
+    fn test_validate_webhook_endpoint_unknown_event_type() {
+        let mut endpoint = endpoint();
+        endpoint.event_types.push("ledger.deleted".to_string());
+        assert_eq!(
+            endpoint.validate(),
+            Err("unknown event type: ledger.deleted".to_string())
+        );
+
+        endpoint.event_types.clear();
+        assert!(endpoint.validate().is_err());
+    }
+
+    #[test]
+    fn test_webhook_endpoint_deserialize_skips_server_fields() {
+        let endpoint: WebhookEndpoint = serde_json::from_str(
+            r#"{
+                "url": "https://example.com/hooks",
+                "event_types": ["ledger.updated"],
+                "secret": "injected",
+                "tenant_id": 99
+            }"#,
+        )
+        .unwrap();
+        assert!(endpoint.secret.is_empty());
+        assert_eq!(endpoint.tenant_id, 0);
+        assert!(endpoint.id.is_nil());
+    }
+}
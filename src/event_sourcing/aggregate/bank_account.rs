@@ -13,7 +13,7 @@ use crate::service::BankAccountServices;
 impl Aggregate for models::BankAccount {
     type Command = command::BankAccountCommand;
     type Event = events::BankAccountEvent;
-    type Error = error::BankAccountError;
+    type Error = error::DomainError;
     type Services = BankAccountServices;
 
     // This identifier should be unique to the system.
@@ -36,12 +36,20 @@ impl Aggregate for models::BankAccount {
                 kind,
                 user_id,
                 currency,
+                tenant_id,
             } => {
-                helper::validate_account_creation(services, id, user_id.clone(), currency, kind)
-                    .await?;
+                helper::validate_account_creation(
+                    services,
+                    id,
+                    tenant_id,
+                    user_id.clone(),
+                    currency,
+                    kind,
+                )
+                .await?;
 
                 Ok(vec![events::BankAccountEvent::AccountOpened {
-                    base_event: helper::create_base_event(id),
+                    base_event: helper::create_base_event(id, tenant_id),
                     account_type,
                     kind,
                     user_id,
@@ -49,25 +57,27 @@ impl Aggregate for models::BankAccount {
                 }])
             }
             BankAccountCommand::ApproveAccount { id, ledger_id } => {
-                let bank_account = services
-                    .services
-                    .get_bank_account(id)
-                    .await
-                    .map_err(|_| "account not found")?;
+                let bank_account = services.services.get_bank_account(id).await?;
 
-                helper::init_ledger(services, ledger_id, id, bank_account.currency).await?;
+                helper::init_ledger(
+                    services,
+                    ledger_id,
+                    id,
+                    bank_account.currency,
+                    self.tenant_id,
+                )
+                .await?;
 
                 Ok(vec![events::BankAccountEvent::AccountKycApproved {
                     ledger_id: ledger_id.to_string(),
-                    base_event: helper::create_base_event(id),
+                    base_event: helper::create_base_event(id, self.tenant_id),
                 }])
             }
             BankAccountCommand::Deposit { id: _, amount } => {
                 let house_account = services
                     .services
-                    .get_house_account(amount.currency)
-                    .await
-                    .map_err(|_| "house account not found")?;
+                    .get_house_account(self.tenant_id, amount.currency)
+                    .await?;
 
                 helper::create_transaction_with_journal(
                     self,
@@ -75,17 +85,30 @@ impl Aggregate for models::BankAccount {
                     amount,
                     house_account.ledger_id,
                     LedgerAction::Deposit,
+                    serde_json::Value::Null,
                 )
                 .await?;
 
                 Ok(vec![])
             }
-            BankAccountCommand::Withdrawal { id, amount } => {
+            BankAccountCommand::Withdrawal {
+                id,
+                amount,
+                destination,
+            } => {
+                // The payout destination is kept on the transaction until the
+                // withdrawal is batched into an ACH file.
+                let metadata = match destination {
+                    Some(destination) if !destination.is_valid() => {
+                        return Err(error::DomainError::InvalidPayoutDestination);
+                    }
+                    Some(destination) => serde_json::json!({ "destination": destination }),
+                    None => serde_json::Value::Null,
+                };
                 let house_account = services
                     .services
-                    .get_house_account(amount.currency)
-                    .await
-                    .map_err(|_| "house account not found")?;
+                    .get_house_account(self.tenant_id, amount.currency)
+                    .await?;
 
                 // Here we create transaction and journal. with outbox record
                 // support, later on have job to credit/debit ledger.
@@ -95,6 +118,7 @@ impl Aggregate for models::BankAccount {
                     amount,
                     house_account.ledger_id,
                     LedgerAction::Withdraw,
+                    metadata,
                 )
                 .await?;
 
@@ -125,6 +149,7 @@ impl Aggregate for models::BankAccount {
                 currency,
             } => {
                 self.id = base_event.get_aggregate_id();
+                self.tenant_id = base_event.get_tenant_id();
                 self.status = models::BankAccountStatus::Pending;
                 self.timestamp = base_event.get_created_at();
                 self.account_type = account_type;
@@ -169,6 +194,7 @@ mod aggregate_tests {
 
     use super::{
         command::{BankAccountCommand, LedgerCommand},
+        error::DomainError,
         event::{BaseEvent, Event},
         events::BankAccountEvent,
         finance::{JournalEntry, JournalLine, Transaction},
@@ -176,6 +202,7 @@ mod aggregate_tests {
             BankAccount, BankAccountKind, BankAccountType, BankAccountView, HouseAccount,
             LedgerAction,
         },
+        payment::{PayoutAccountKind, PayoutDestination},
     };
 
     // A test framework that will apply our events and command
@@ -188,9 +215,13 @@ mod aggregate_tests {
         static ref TRANSACTION_ID: Uuid = Uuid::new_v4();
     }
 
+    const TENANT_ID: i32 = 7;
+
+    // Events after `AccountOpened` carry the tenant the account was opened for.
     fn create_base_event(uuid: Uuid) -> BaseEvent {
         let mut base_event = BaseEvent::default();
         base_event.set_aggregate_id(uuid);
+        base_event.set_tenant_id(TENANT_ID);
         base_event.set_created_at(chrono::Utc::now());
         base_event
     }
@@ -225,7 +256,8 @@ mod aggregate_tests {
             account_type: BankAccountType::Retail,
             kind: BankAccountKind::Checking,
             user_id: "user".to_string(),
-            currency: Currency::USD
+            currency: Currency::USD,
+            tenant_id: TENANT_ID
         },
         vec![BankAccountEvent::AccountOpened {
             base_event: create_base_event(*ACCOUNT_ID),
@@ -294,11 +326,94 @@ mod aggregate_tests {
         ],
         BankAccountCommand::Withdrawal {
             id: *ACCOUNT_ID,
-            amount: Money::new(dec!(500.0), Currency::USD)
+            amount: Money::new(dec!(500.0), Currency::USD),
+            destination: None
+        },
+        vec![]
+    );
+
+    test_case!(
+        test_withdrawal_with_destination,
+        vec![
+            BankAccountEvent::AccountOpened {
+                base_event: create_base_event(*ACCOUNT_ID),
+                account_type: BankAccountType::Retail,
+                kind: BankAccountKind::Checking,
+                user_id: "user".to_string(),
+                currency: Currency::USD
+            },
+            BankAccountEvent::AccountKycApproved {
+                ledger_id: LEDGER_ID.to_string(),
+                base_event: create_base_event(*ACCOUNT_ID)
+            }
+        ],
+        BankAccountCommand::Withdrawal {
+            id: *ACCOUNT_ID,
+            amount: Money::new(dec!(500.0), Currency::USD),
+            destination: Some(PayoutDestination {
+                routing_number: "021000021".to_string(),
+                account_number: "123456789".to_string(),
+                account_holder_name: "Jane Doe".to_string(),
+                account_kind: PayoutAccountKind::Checking,
+            })
         },
         vec![]
     );
 
+    #[test]
+    fn test_withdrawal_with_invalid_destination() {
+        let services = BankAccountServices::new(Box::new(setup_mock_services()));
+        AccountTestFramework::with(services)
+            .given(vec![
+                BankAccountEvent::AccountOpened {
+                    base_event: create_base_event(*ACCOUNT_ID),
+                    account_type: BankAccountType::Retail,
+                    kind: BankAccountKind::Checking,
+                    user_id: "user".to_string(),
+                    currency: Currency::USD,
+                },
+                BankAccountEvent::AccountKycApproved {
+                    ledger_id: LEDGER_ID.to_string(),
+                    base_event: create_base_event(*ACCOUNT_ID),
+                },
+            ])
+            .when(BankAccountCommand::Withdrawal {
+                id: *ACCOUNT_ID,
+                amount: Money::new(dec!(500.0), Currency::USD),
+                destination: Some(PayoutDestination {
+                    routing_number: "123".to_string(),
+                    ..Default::default()
+                }),
+            })
+            .then_expect_error_message("invalid payout destination");
+    }
+
+    #[test]
+    fn test_withdrawal_with_insufficient_funds() {
+        let mock_services = setup_mock_services();
+        mock_services.set_validate_response(Err(DomainError::InsufficientFunds.into()));
+        AccountTestFramework::with(BankAccountServices::new(Box::new(mock_services)))
+            .given(vec![
+                BankAccountEvent::AccountOpened {
+                    base_event: create_base_event(*ACCOUNT_ID),
+                    account_type: BankAccountType::Retail,
+                    kind: BankAccountKind::Checking,
+                    user_id: "user".to_string(),
+                    currency: Currency::USD,
+                },
+                BankAccountEvent::AccountKycApproved {
+                    ledger_id: LEDGER_ID.to_string(),
+                    base_event: create_base_event(*ACCOUNT_ID),
+                },
+            ])
+            .when(BankAccountCommand::Withdrawal {
+                id: *ACCOUNT_ID,
+                amount: Money::new(dec!(500.0), Currency::USD),
+                destination: None,
+            })
+            .then_expect_error(DomainError::InsufficientFunds);
+    }
+
     pub struct MockBankAccountServices {
         write_ledger_response: Mutex<Option<Result<(), anyhow::Error>>>,
         write_transaction_response: Mutex<Option<Result<Uuid, anyhow::Error>>>,
@@ -364,6 +479,7 @@ mod aggregate_tests {
 
         async fn get_house_account(
             &self,
+            _tenant_id: i32,
             _currency: Currency,
         ) -> Result<HouseAccount, anyhow::Error> {
             Ok(HouseAccount::default())
@@ -372,6 +488,7 @@ mod aggregate_tests {
         async fn validate_account_creation(
             &self,
             _account_id: Uuid,
+            _tenant_id: i32,
             _user_id: String,
             _currency: Currency,
             _kind: BankAccountKind,
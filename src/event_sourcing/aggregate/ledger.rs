@@ -3,6 +3,7 @@ use command::LedgerCommand;
 use cqrs_es::Aggregate;
 use event::{BaseEvent, Event};
 use rust_decimal::Decimal;
+use tracing::error;
 
 use crate::common::money::Money;
 use crate::domain::*;
@@ -13,7 +14,7 @@ use crate::service::MockLedgerServices;
 impl Aggregate for models::Ledger {
     type Command = command::LedgerCommand;
     type Event = events::LedgerEvent;
-    type Error = error::LedgerError;
+    type Error = error::DomainError;
     type Services = MockLedgerServices;
 
     // This identifier should be unique to the system.
@@ -28,20 +29,27 @@ impl Aggregate for models::Ledger {
         command: Self::Command,
         _services: &Self::Services,
     ) -> Result<Vec<Self::Event>, Self::Error> {
-        match command {
+        // Outbox commands are applied at least once, e.g. again by another
+        // instance after a lease expired.
+        if self.is_applied(&command)? {
+            return Ok(vec![]);
+        }
+        let events = match command {
             LedgerCommand::Init {
                 id,
                 account_id,
                 amount,
+                tenant_id,
             } => {
                 let mut base_event = BaseEvent::default();
                 base_event.set_aggregate_id(id);
                 base_event.set_parent_id(account_id);
+                base_event.set_tenant_id(tenant_id);
                 base_event.set_created_at(chrono::Utc::now());
-                Ok(vec![events::LedgerEvent::LedgerInitiated {
+                vec![events::LedgerEvent::LedgerInitiated {
                     amount,
                     base_event: base_event.clone(),
-                }])
+                }]
             }
             LedgerCommand::DebitHold {
                 id,
@@ -52,15 +60,16 @@ impl Aggregate for models::Ledger {
                 let mut base_event = BaseEvent::default();
                 base_event.set_aggregate_id(id);
                 base_event.set_parent_id(account_id);
+                base_event.set_tenant_id(self.tenant_id);
                 base_event.set_created_at(chrono::Utc::now());
-                Ok(vec![events::LedgerEvent::LedgerUpdated {
+                vec![events::LedgerEvent::LedgerUpdated {
                     amount,
                     transaction_id: transaction_id.to_string(),
-                    transaction_type: "debit_hold".to_string(),
+                    transaction_type: events::LedgerTransactionType::DebitHold,
                     available_delta: Money::new(Decimal::ZERO - amount.amount, amount.currency),
                     pending_delta: Money::new(amount.amount, amount.currency),
                     base_event: base_event.clone(),
-                }])
+                }]
             }
             LedgerCommand::DebitRelease {
                 id,
@@ -71,15 +80,36 @@ impl Aggregate for models::Ledger {
                 let mut base_event = BaseEvent::default();
                 base_event.set_aggregate_id(id);
                 base_event.set_parent_id(account_id);
+                base_event.set_tenant_id(self.tenant_id);
                 base_event.set_created_at(chrono::Utc::now());
-                Ok(vec![events::LedgerEvent::LedgerUpdated {
+                vec![events::LedgerEvent::LedgerUpdated {
                     amount,
                     transaction_id: transaction_id.to_string(),
-                    transaction_type: "debit_release".to_string(),
+                    transaction_type: events::LedgerTransactionType::DebitRelease,
                     available_delta: Money::new(Decimal::ZERO, amount.currency),
                     pending_delta: Money::new(Decimal::ZERO - amount.amount, amount.currency),
                     base_event,
-                }])
+                }]
+            }
+            LedgerCommand::DebitCancel {
+                id,
+                account_id,
+                transaction_id,
+                amount,
+            } => {
+                let mut base_event = BaseEvent::default();
+                base_event.set_aggregate_id(id);
+                base_event.set_parent_id(account_id);
+                base_event.set_tenant_id(self.tenant_id);
+                base_event.set_created_at(chrono::Utc::now());
+                vec![events::LedgerEvent::LedgerUpdated {
+                    amount,
+                    transaction_id: transaction_id.to_string(),
+                    transaction_type: events::LedgerTransactionType::DebitCancel,
+                    available_delta: amount,
+                    pending_delta: Money::new(Decimal::ZERO - amount.amount, amount.currency),
+                    base_event,
+                }]
             }
             LedgerCommand::Credit {
                 id,
@@ -90,12 +120,13 @@ impl Aggregate for models::Ledger {
                 let mut base_event = BaseEvent::default();
                 base_event.set_aggregate_id(id);
                 base_event.set_parent_id(account_id);
+                base_event.set_tenant_id(self.tenant_id);
                 base_event.set_created_at(chrono::Utc::now());
-                Ok(vec![
+                vec![
                     events::LedgerEvent::LedgerUpdated {
                         amount,
                         transaction_id: transaction_id.to_string(),
-                        transaction_type: "credit_hold".to_string(),
+                        transaction_type: events::LedgerTransactionType::CreditHold,
                         available_delta: Money::new(Decimal::ZERO, amount.currency),
                         pending_delta: Money::new(amount.amount, amount.currency),
                         base_event: base_event.clone(),
@@ -103,14 +134,18 @@ impl Aggregate for models::Ledger {
                     events::LedgerEvent::LedgerUpdated {
                         amount,
                         transaction_id: transaction_id.to_string(),
-                        transaction_type: "credit_release".to_string(),
+                        transaction_type: events::LedgerTransactionType::CreditRelease,
                         available_delta: Money::new(amount.amount, amount.currency),
                         pending_delta: Money::new(Decimal::ZERO - amount.amount, amount.currency),
                         base_event,
                     },
-                ])
+                ]
             }
-        }
+        };
+        // Rejects a command in another currency than the ledger here, `apply`
+        // cannot fail.
+        self.ensure_balances(&events)?;
+        Ok(events)
     }
 
     fn apply(&mut self, event: Self::Event) {
@@ -118,6 +153,7 @@ impl Aggregate for models::Ledger {
             events::LedgerEvent::LedgerInitiated { base_event, amount } => {
                 self.id = base_event.get_aggregate_id();
                 self.account_id = base_event.get_parent_id();
+                self.tenant_id = base_event.get_tenant_id();
                 self.amount = amount;
                 self.available = amount;
                 self.pending = Money::new(Decimal::ZERO, amount.currency);
@@ -125,16 +161,27 @@ impl Aggregate for models::Ledger {
             }
             events::LedgerEvent::LedgerUpdated {
                 amount,
-                transaction_id: _,
-                transaction_type: _,
+                transaction_id,
+                transaction_type,
                 available_delta,
                 pending_delta,
                 base_event,
             } => {
                 self.id = base_event.get_aggregate_id();
                 self.amount = amount;
-                self.available = self.available + available_delta;
-                self.pending = self.pending + pending_delta;
+                self.transactions.insert(transaction_id, transaction_type);
+                match (
+                    self.available.try_add(available_delta),
+                    self.pending.try_add(pending_delta),
+                ) {
+                    (Ok(available), Ok(pending)) => {
+                        self.available = available;
+                        self.pending = pending;
+                    }
+                    (Err(err), _) | (_, Err(err)) => {
+                        error!("Ledger {} skipped an update: {}", self.id, err);
+                    }
+                }
                 self.account_id = base_event.get_parent_id();
                 self.timestamp = base_event.get_created_at();
             }
@@ -142,6 +189,55 @@ impl Aggregate for models::Ledger {
     }
 }
 
+impl models::Ledger {
+    // Whether `command` repeats the last step of its transaction. Settling a
+    // withdrawal whose hold was given back is refused, a retry places the hold
+    // again first.
+    fn is_applied(&self, command: &LedgerCommand) -> Result<bool, error::DomainError> {
+        use crate::domain::events::LedgerTransactionType::{
+            CreditRelease, DebitCancel, DebitHold, DebitRelease,
+        };
+
+        let (transaction_id, repeated): (_, &[events::LedgerTransactionType]) = match command {
+            LedgerCommand::Init { .. } => return Ok(false),
+            LedgerCommand::Credit { transaction_id, .. } => (transaction_id, &[CreditRelease]),
+            LedgerCommand::DebitHold { transaction_id, .. } => {
+                (transaction_id, &[DebitHold, DebitRelease])
+            }
+            LedgerCommand::DebitRelease { transaction_id, .. } => (transaction_id, &[DebitRelease]),
+            LedgerCommand::DebitCancel { transaction_id, .. } => {
+                (transaction_id, &[DebitCancel, DebitRelease])
+            }
+        };
+        let last = self.transactions.get(&transaction_id.to_string());
+        if matches!(command, LedgerCommand::DebitRelease { .. }) && last == Some(&DebitCancel) {
+            return Err(error::DomainError::Internal(format!(
+                "hold of transaction {} was given back",
+                transaction_id
+            )));
+        }
+        Ok(last.is_some_and(|last| repeated.contains(last)))
+    }
+
+    // Fails when a balance cannot be updated by `events`.
+    fn ensure_balances(&self, events: &[events::LedgerEvent]) -> Result<(), error::DomainError> {
+        let mut available = self.available;
+        let mut pending = self.pending;
+        for event in events {
+            if let events::LedgerEvent::LedgerUpdated {
+                available_delta,
+                pending_delta,
+                ..
+            } = event
+            {
+                available = available.try_add(*available_delta)?;
+                pending = pending.try_add(*pending_delta)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 // The aggregate tests are the most important part of a CQRS system.
 // The simplicity and flexibility of these tests are a good part of what
 // makes an event sourced system so friendly to changing business requirements.
@@ -162,7 +258,7 @@ mod aggregate_tests {
     use super::{
         command::LedgerCommand,
         event::{BaseEvent, Event},
-        events::LedgerEvent,
+        events::{LedgerEvent, LedgerTransactionType},
         models::Ledger,
     };
 
@@ -176,10 +272,13 @@ mod aggregate_tests {
         static ref TRANSACTION_ID: Uuid = Uuid::new_v4();
     }
 
+    const TENANT_ID: i32 = 7;
+
     fn create_ledger_base_event(uuid: Uuid, parent_id: Uuid) -> BaseEvent {
         let mut base_event = BaseEvent::default();
         base_event.set_aggregate_id(uuid);
         base_event.set_parent_id(parent_id);
+        base_event.set_tenant_id(TENANT_ID);
         base_event.set_created_at(chrono::Utc::now());
         base_event
     }
@@ -203,6 +302,7 @@ mod aggregate_tests {
             id: *LEDGER_ID,
             account_id: *ACCOUNT_ID,
             amount: Money::new(dec!(1000.0), Currency::USD),
+            tenant_id: TENANT_ID,
         },
         vec![LedgerEvent::LedgerInitiated {
             amount: Money::new(dec!(1000.0), Currency::USD),
@@ -226,7 +326,7 @@ mod aggregate_tests {
             LedgerEvent::LedgerUpdated {
                 amount: Money::new(dec!(1000.0), Currency::USD),
                 transaction_id: TRANSACTION_ID.to_string(),
-                transaction_type: "credit_hold".to_string(),
+                transaction_type: LedgerTransactionType::CreditHold,
                 available_delta: Money::new(Decimal::ZERO, Currency::USD),
                 pending_delta: Money::new(dec!(1000.0), Currency::USD),
                 base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
@@ -234,7 +334,7 @@ mod aggregate_tests {
             LedgerEvent::LedgerUpdated {
                 amount: Money::new(dec!(1000.0), Currency::USD),
                 transaction_id: TRANSACTION_ID.to_string(),
-                transaction_type: "credit_release".to_string(),
+                transaction_type: LedgerTransactionType::CreditRelease,
                 pending_delta: Money::new(Decimal::ZERO - dec!(1000.0), Currency::USD),
                 available_delta: Money::new(dec!(1000.0), Currency::USD),
                 base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
@@ -252,7 +352,7 @@ mod aggregate_tests {
             LedgerEvent::LedgerUpdated {
                 amount: Money::new(dec!(1000.0), Currency::USD),
                 transaction_id: TRANSACTION_ID.to_string(),
-                transaction_type: "credit_hold".to_string(),
+                transaction_type: LedgerTransactionType::CreditHold,
                 available_delta: Money::new(Decimal::ZERO, Currency::USD),
                 pending_delta: Money::new(dec!(1000.0), Currency::USD),
                 base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
@@ -260,7 +360,7 @@ mod aggregate_tests {
             LedgerEvent::LedgerUpdated {
                 amount: Money::new(dec!(1000.0), Currency::USD),
                 transaction_id: TRANSACTION_ID.to_string(),
-                transaction_type: "credit_release".to_string(),
+                transaction_type: LedgerTransactionType::CreditRelease,
                 pending_delta: Money::new(Decimal::ZERO - dec!(1000.0), Currency::USD),
                 available_delta: Money::new(dec!(1000.0), Currency::USD),
                 base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
@@ -275,10 +375,165 @@ mod aggregate_tests {
         vec![LedgerEvent::LedgerUpdated {
             amount: Money::new(dec!(200.0), Currency::USD),
             transaction_id: TRANSACTION_ID.to_string(),
-            transaction_type: "debit_hold".to_string(),
+            transaction_type: LedgerTransactionType::DebitHold,
             available_delta: Money::new(Decimal::ZERO - dec!(200.0), Currency::USD),
             pending_delta: Money::new(dec!(200.0), Currency::USD),
             base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
         }]
     );
+
+    ledger_test_case!(
+        test_ledger_debit_cancel,
+        vec![
+            LedgerEvent::LedgerInitiated {
+                amount: Money::new(dec!(1000.0), Currency::USD),
+                base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
+            },
+            LedgerEvent::LedgerUpdated {
+                amount: Money::new(dec!(200.0), Currency::USD),
+                transaction_id: TRANSACTION_ID.to_string(),
+                transaction_type: LedgerTransactionType::DebitHold,
+                available_delta: Money::new(Decimal::ZERO - dec!(200.0), Currency::USD),
+                pending_delta: Money::new(dec!(200.0), Currency::USD),
+                base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
+            }
+        ],
+        LedgerCommand::DebitCancel {
+            id: *LEDGER_ID,
+            account_id: *ACCOUNT_ID,
+            transaction_id: *TRANSACTION_ID,
+            amount: Money::new(dec!(200.0), Currency::USD),
+        },
+        vec![LedgerEvent::LedgerUpdated {
+            amount: Money::new(dec!(200.0), Currency::USD),
+            transaction_id: TRANSACTION_ID.to_string(),
+            transaction_type: LedgerTransactionType::DebitCancel,
+            available_delta: Money::new(dec!(200.0), Currency::USD),
+            pending_delta: Money::new(Decimal::ZERO - dec!(200.0), Currency::USD),
+            base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID)
+        }]
+    );
+
+    fn ledger_updated(transaction_type: LedgerTransactionType) -> LedgerEvent {
+        LedgerEvent::LedgerUpdated {
+            amount: Money::new(dec!(200.0), Currency::USD),
+            transaction_id: TRANSACTION_ID.to_string(),
+            transaction_type,
+            available_delta: Money::new(Decimal::ZERO, Currency::USD),
+            pending_delta: Money::new(Decimal::ZERO, Currency::USD),
+            base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID),
+        }
+    }
+
+    fn ledger_initiated() -> LedgerEvent {
+        LedgerEvent::LedgerInitiated {
+            amount: Money::new(dec!(1000.0), Currency::USD),
+            base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID),
+        }
+    }
+
+    #[test]
+    fn test_ledger_ignores_applied_commands() {
+        let amount = Money::new(dec!(200.0), Currency::USD);
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![
+                ledger_initiated(),
+                ledger_updated(LedgerTransactionType::CreditHold),
+                ledger_updated(LedgerTransactionType::CreditRelease),
+            ])
+            .when(LedgerCommand::Credit {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount,
+            })
+            .then_expect_events(vec![]);
+
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![
+                ledger_initiated(),
+                ledger_updated(LedgerTransactionType::DebitHold),
+                ledger_updated(LedgerTransactionType::DebitRelease),
+            ])
+            .when(LedgerCommand::DebitRelease {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount,
+            })
+            .then_expect_events(vec![]);
+
+        // The hold is only given back while the withdrawal is not settled.
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![
+                ledger_initiated(),
+                ledger_updated(LedgerTransactionType::DebitHold),
+                ledger_updated(LedgerTransactionType::DebitRelease),
+            ])
+            .when(LedgerCommand::DebitCancel {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount,
+            })
+            .then_expect_events(vec![]);
+    }
+
+    #[test]
+    fn test_ledger_rejects_release_of_cancelled_hold() {
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![
+                ledger_initiated(),
+                ledger_updated(LedgerTransactionType::DebitHold),
+                ledger_updated(LedgerTransactionType::DebitCancel),
+            ])
+            .when(LedgerCommand::DebitRelease {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount: Money::new(dec!(200.0), Currency::USD),
+            })
+            .then_expect_error_message(&format!(
+                "hold of transaction {} was given back",
+                *TRANSACTION_ID
+            ));
+
+        // A retry places the hold again.
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![
+                ledger_initiated(),
+                ledger_updated(LedgerTransactionType::DebitHold),
+                ledger_updated(LedgerTransactionType::DebitCancel),
+            ])
+            .when(LedgerCommand::DebitHold {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount: Money::new(dec!(200.0), Currency::USD),
+            })
+            .then_expect_events(vec![LedgerEvent::LedgerUpdated {
+                amount: Money::new(dec!(200.0), Currency::USD),
+                transaction_id: TRANSACTION_ID.to_string(),
+                transaction_type: LedgerTransactionType::DebitHold,
+                available_delta: Money::new(Decimal::ZERO - dec!(200.0), Currency::USD),
+                pending_delta: Money::new(dec!(200.0), Currency::USD),
+                base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID),
+            }]);
+    }
+
+    #[test]
+    fn test_ledger_rejects_other_currency() {
+        LedgerTestFramework::with(MockLedgerServices {})
+            .given(vec![LedgerEvent::LedgerInitiated {
+                amount: Money::new(dec!(1000.0), Currency::USD),
+                base_event: create_ledger_base_event(*LEDGER_ID, *ACCOUNT_ID),
+            }])
+            .when(LedgerCommand::Credit {
+                id: *LEDGER_ID,
+                account_id: *ACCOUNT_ID,
+                transaction_id: *TRANSACTION_ID,
+                amount: Money::new(dec!(1000), Currency::TWD),
+            })
+            .then_expect_error_message("currency mismatch: USD and TWD");
+    }
 }
@@ -2,8 +2,14 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    common::money::{Currency, Money},
-    domain::models::{BankAccountKind, BankAccountType},
+    common::{
+        money::{Currency, Money},
+        validation::ValidationErrors,
+    },
+    domain::{
+        models::{BankAccountKind, BankAccountType},
+        payment::PayoutDestination,
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,6 +23,8 @@ pub enum BankAccountCommand {
         kind: BankAccountKind,
         user_id: String,
         currency: Currency,
+        #[serde(skip_deserializing)]
+        tenant_id: i32,
     },
     ApproveAccount {
         id: Uuid,
@@ -30,15 +38,58 @@ pub enum BankAccountCommand {
     Withdrawal {
         id: Uuid,
         amount: Money,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        destination: Option<PayoutDestination>,
     },
 }
 
+impl BankAccountCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BankAccountCommand::OpenAccount { .. } => "OpenAccount",
+            BankAccountCommand::ApproveAccount { .. } => "ApproveAccount",
+            BankAccountCommand::Deposit { .. } => "Deposit",
+            BankAccountCommand::Withdrawal { .. } => "Withdrawal",
+        }
+    }
+
+    /// Checks the fields a caller sends, the aggregate still enforces the
+    /// rules that depend on the account.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        match self {
+            BankAccountCommand::OpenAccount { user_id, .. } => {
+                errors.check_text("OpenAccount.user_id", user_id, 255);
+            }
+            BankAccountCommand::ApproveAccount { .. } => {}
+            BankAccountCommand::Deposit { amount, .. } => {
+                errors.check_amount("Deposit.amount", amount);
+            }
+            BankAccountCommand::Withdrawal {
+                amount,
+                destination,
+                ..
+            } => {
+                errors.check_amount("Withdrawal.amount", amount);
+                if matches!(destination, Some(destination) if !destination.is_valid()) {
+                    errors.add(
+                        "Withdrawal.destination",
+                        "must have a valid routing number, account number and holder name",
+                    );
+                }
+            }
+        }
+        errors.into_result()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum LedgerCommand {
     Init {
         id: Uuid,
         account_id: Uuid,
         amount: Money,
+        tenant_id: i32,
     },
     Credit {
         id: Uuid,
@@ -58,4 +109,23 @@ pub enum LedgerCommand {
         transaction_id: Uuid,
         amount: Money,
     },
+    // Gives the amount of a `DebitHold` back when its withdrawal failed.
+    DebitCancel {
+        id: Uuid,
+        account_id: Uuid,
+        transaction_id: Uuid,
+        amount: Money,
+    },
+}
+
+impl LedgerCommand {
+    pub fn ledger_id(&self) -> Uuid {
+        match self {
+            LedgerCommand::Init { id, .. }
+            | LedgerCommand::Credit { id, .. }
+            | LedgerCommand::DebitHold { id, .. }
+            | LedgerCommand::DebitRelease { id, .. }
+            | LedgerCommand::DebitCancel { id, .. } => *id,
+        }
+    }
 }
@@ -1,42 +1,155 @@
-use anyhow::Error;
+use cqrs_es::AggregateError;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug)]
-pub struct BankAccountError(String);
+use crate::common::error::ErrorCode;
+use crate::common::money::{Currency, CurrencyParseError, MoneyError};
 
-impl Display for BankAccountError {
+// Everything a bank account or ledger command can be rejected with. The
+// variants carry a stable `ErrorCode` so callers can tell them apart without
+// parsing messages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainError {
+    AccountNotFound,
+    AccountNotActive,
+    AccountFrozen,
+    DuplicateAccount,
+    HouseAccountNotFound,
+    InsufficientFunds,
+    CurrencyMismatch(Currency, Currency),
+    UnknownCurrency(String),
+    CurrencyNotEnabled(String),
+    AmountOverflow,
+    InvalidPayoutDestination,
+    // The aggregate was changed by another command in the meantime, retrying
+    // the command is safe.
+    ConcurrentModification,
+    // The server stopped taking commands, retrying against another one is safe.
+    ShuttingDown,
+    Internal(String),
+}
+
+impl DomainError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DomainError::AccountNotFound => ErrorCode::AccountNotFound,
+            DomainError::AccountNotActive => ErrorCode::AccountNotActive,
+            DomainError::AccountFrozen => ErrorCode::AccountFrozen,
+            DomainError::DuplicateAccount => ErrorCode::DuplicateAccount,
+            DomainError::HouseAccountNotFound => ErrorCode::HouseAccountNotFound,
+            DomainError::InsufficientFunds => ErrorCode::InsufficientFunds,
+            DomainError::CurrencyMismatch(..) => ErrorCode::CurrencyMismatch,
+            DomainError::UnknownCurrency(_) => ErrorCode::UnknownCurrency,
+            DomainError::CurrencyNotEnabled(_) => ErrorCode::CurrencyNotEnabled,
+            DomainError::AmountOverflow => ErrorCode::AmountOverflow,
+            DomainError::InvalidPayoutDestination => ErrorCode::InvalidPayoutDestination,
+            DomainError::ConcurrentModification => ErrorCode::ConcurrentModification,
+            DomainError::ShuttingDown => ErrorCode::ShuttingDown,
+            DomainError::Internal(_) => ErrorCode::InternalError,
+        }
+    }
+}
+
+impl Display for DomainError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            DomainError::AccountNotFound => write!(f, "account not found"),
+            DomainError::AccountNotActive => write!(f, "account is not active"),
+            DomainError::AccountFrozen => write!(f, "account is frozen"),
+            DomainError::DuplicateAccount => write!(f, "account already exists"),
+            DomainError::HouseAccountNotFound => write!(f, "house account not found"),
+            DomainError::InsufficientFunds => write!(f, "insufficient funds"),
+            DomainError::CurrencyMismatch(left, right) => {
+                write!(f, "currency mismatch: {} and {}", left, right)
+            }
+            DomainError::UnknownCurrency(code) => write!(f, "invalid currency: {}", code),
+            DomainError::CurrencyNotEnabled(code) => write!(f, "currency not enabled: {}", code),
+            DomainError::AmountOverflow => write!(f, "amount overflow"),
+            DomainError::InvalidPayoutDestination => write!(f, "invalid payout destination"),
+            DomainError::ConcurrentModification => {
+                write!(f, "account was modified concurrently, retry the command")
+            }
+            DomainError::ShuttingDown => {
+                write!(f, "server is shutting down, retry the command")
+            }
+            DomainError::Internal(message) => write!(f, "{}", message),
+        }
     }
 }
 
-impl std::error::Error for BankAccountError {}
+impl std::error::Error for DomainError {}
 
-impl From<&str> for BankAccountError {
-    fn from(message: &str) -> Self {
-        BankAccountError(message.to_string())
+// Services report domain errors through `anyhow`, anything else is internal.
+impl From<anyhow::Error> for DomainError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<DomainError>() {
+            Ok(err) => err,
+            Err(err) => DomainError::Internal(err.to_string()),
+        }
     }
 }
 
-impl From<Error> for BankAccountError {
-    fn from(err: Error) -> Self {
-        BankAccountError(err.to_string())
+impl From<MoneyError> for DomainError {
+    fn from(err: MoneyError) -> Self {
+        match err {
+            MoneyError::CurrencyMismatch(left, right) => DomainError::CurrencyMismatch(left, right),
+            MoneyError::Overflow => DomainError::AmountOverflow,
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct LedgerError(String);
+impl From<CurrencyParseError> for DomainError {
+    fn from(err: CurrencyParseError) -> Self {
+        match err {
+            CurrencyParseError::Unknown(code) => DomainError::UnknownCurrency(code),
+            CurrencyParseError::Disabled(code) => DomainError::CurrencyNotEnabled(code),
+        }
+    }
+}
 
-impl Display for LedgerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+// Unwraps the error of a command executed through the framework.
+impl From<AggregateError<DomainError>> for DomainError {
+    fn from(err: AggregateError<DomainError>) -> Self {
+        match err {
+            AggregateError::UserError(err) => err,
+            AggregateError::AggregateConflict => DomainError::ConcurrentModification,
+            err => DomainError::Internal(err.to_string()),
+        }
     }
 }
 
-impl std::error::Error for LedgerError {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_from_anyhow() {
+        let err = anyhow::Error::from(DomainError::InsufficientFunds);
+        assert_eq!(DomainError::from(err), DomainError::InsufficientFunds);
+
+        let err = DomainError::from(anyhow!("connection reset"));
+        assert_eq!(err, DomainError::Internal("connection reset".to_string()));
+        assert_eq!(err.code(), ErrorCode::InternalError);
+    }
+
+    #[test]
+    fn test_from_aggregate_error() {
+        let err = AggregateError::UserError(DomainError::AccountFrozen);
+        assert_eq!(DomainError::from(err), DomainError::AccountFrozen);
+        assert_eq!(
+            DomainError::from(AggregateError::<DomainError>::AggregateConflict),
+            DomainError::ConcurrentModification
+        );
+    }
 
-impl From<&str> for LedgerError {
-    fn from(message: &str) -> Self {
-        LedgerError(message.to_string())
+    #[test]
+    fn test_from_money_error() {
+        let err = DomainError::from(MoneyError::CurrencyMismatch(Currency::USD, Currency::TWD));
+        assert_eq!(err.code(), ErrorCode::CurrencyMismatch);
+        assert_eq!(err.to_string(), "currency mismatch: USD and TWD");
+        assert_eq!(
+            DomainError::from(MoneyError::Overflow).code(),
+            ErrorCode::AmountOverflow
+        );
     }
 }
@@ -20,13 +20,22 @@ pub trait Event {
 
     // SetCreatedAt changes event's create time
     fn set_created_at(&mut self, created_at: DateTime<Utc>);
+
+    // GetTenantID returns the tenant owning event's aggregate
+    fn get_tenant_id(&self) -> i32;
+
+    // SetTenantID changes the tenant owning event's aggregate
+    fn set_tenant_id(&mut self, tenant_id: i32);
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BaseEvent {
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
     pub aggregate_id: String,
     pub parent_id: String,
+    // Events stored before tenants were enforced belong to tenant 0.
+    #[serde(default)]
+    pub tenant_id: i32,
 }
 
 impl Event for BaseEvent {
@@ -47,30 +56,32 @@ impl Event for BaseEvent {
     }
 
     fn get_created_at(&self) -> String {
-        self.created_at.clone()
+        self.created_at.to_rfc3339()
     }
 
     fn set_created_at(&mut self, created_at: DateTime<Utc>) {
-        self.created_at = created_at.to_rfc3339();
+        self.created_at = created_at;
+    }
+
+    fn get_tenant_id(&self) -> i32 {
+        self.tenant_id
+    }
+
+    fn set_tenant_id(&mut self, tenant_id: i32) {
+        self.tenant_id = tenant_id;
     }
 }
 
 impl PartialEq for BaseEvent {
     fn eq(&self, other: &Self) -> bool {
-        if self.aggregate_id != other.aggregate_id || self.parent_id != other.parent_id {
+        if self.aggregate_id != other.aggregate_id
+            || self.parent_id != other.parent_id
+            || self.tenant_id != other.tenant_id
+        {
             return false;
         }
 
-        // Parse the created_at strings into DateTime objects
-        let self_created_at = DateTime::parse_from_rfc3339(&self.created_at).ok();
-        let other_created_at = DateTime::parse_from_rfc3339(&other.created_at).ok();
-
-        match (self_created_at, other_created_at) {
-            (Some(self_dt), Some(other_dt)) => {
-                // Compare timestamps, allowing for a small difference (e.g., 1 second)
-                (self_dt - other_dt).abs().num_seconds() <= 1
-            }
-            _ => self.created_at == other.created_at, // Fall back to string comparison if parsing fails
-        }
+        // Compare timestamps, allowing for a small difference (e.g., 1 second)
+        (self.created_at - other.created_at).num_seconds().abs() <= 1
     }
 }
@@ -13,23 +13,25 @@ use crate::{common, event_sourcing::*};
 pub async fn validate_account_creation(
     services: &BankAccountServices,
     id: Uuid,
+    tenant_id: i32,
     user_id: String,
     currency: Currency,
     kind: BankAccountKind,
-) -> Result<(), error::BankAccountError> {
+) -> Result<(), error::DomainError> {
     let valid = services
         .services
-        .validate_account_creation(id, user_id, currency, kind)
+        .validate_account_creation(id, tenant_id, user_id, currency, kind)
         .await?;
     if !valid {
-        return Err("validation failed".into());
+        return Err(error::DomainError::DuplicateAccount);
     }
     Ok(())
 }
 
-pub fn create_base_event(id: Uuid) -> BaseEvent {
+pub fn create_base_event(id: Uuid, tenant_id: i32) -> BaseEvent {
     let mut base_event = BaseEvent::default();
     base_event.set_aggregate_id(id);
+    base_event.set_tenant_id(tenant_id);
     base_event.set_created_at(chrono::Utc::now());
     base_event
 }
@@ -39,17 +41,19 @@ pub async fn init_ledger(
     ledger_id: Uuid,
     account_id: Uuid,
     currency: Currency,
-) -> Result<(), error::BankAccountError> {
+    tenant_id: i32,
+) -> Result<(), error::DomainError> {
     let command = LedgerCommand::Init {
         id: ledger_id,
         account_id,
         amount: Money::new(Decimal::ZERO, currency),
+        tenant_id,
     };
     services
         .services
         .note_ledger(ledger_id.to_string(), command)
         .await
-        .map_err(|_| "ledger write failed".into())
+        .map_err(error::DomainError::from)
 }
 
 pub async fn create_transaction_with_journal(
@@ -58,7 +62,10 @@ pub async fn create_transaction_with_journal(
     amount: Money,
     house_account_ledger: String,
     action_type: LedgerAction,
-) -> Result<Uuid, error::BankAccountError> {
+    metadata: serde_json::Value,
+) -> Result<Uuid, error::DomainError> {
+    // Journal lines never carry an asset of another tenant.
+    amount.currency.ensure_owner(bank_account.tenant_id)?;
     // Validate ledger available is sufficient
     services
         .services
@@ -81,9 +88,10 @@ pub async fn create_transaction_with_journal(
         amount: amount.amount,
         currency: amount.currency.to_string(),
         description: None,
-        metadata: serde_json::Value::Null,
+        metadata,
         journal_entry_id: None,
         status: "processing".to_string(),
+        tenant_id: bank_account.tenant_id,
     };
 
     let journal_entry = JournalEntry {
@@ -129,5 +137,5 @@ pub async fn create_transaction_with_journal(
             journal_lines,
         )
         .await
-        .map_err(|_| "transaction update failed".into())
+        .map_err(error::DomainError::from)
 }
@@ -0,0 +1,313 @@
+use chrono::{DateTime, Utc};
+use cqrs_es::persist::{EventUpcaster, PersistedEventRepository, SerializedEvent};
+use cqrs_es::{Aggregate, EventEnvelope};
+use postgres_es::PostgresEventRepository;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::common::money::Money;
+use crate::domain::events::LedgerEvent;
+use crate::domain::models::Ledger;
+use crate::event_sourcing::replay::serialized_event;
+use crate::event_sourcing::upcaster::{ledger_upcasters, upcast};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+// A stored event as it was written, for audit purposes.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EventRecord {
+    pub sequence: usize,
+    pub event_type: String,
+    pub event_version: String,
+    pub payload: Value,
+    pub metadata: Value,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EventPage {
+    pub entries: Vec<EventRecord>,
+    // Pass as `after` to fetch the next page, `None` on the last page.
+    pub next_cursor: Option<usize>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LedgerBalance {
+    pub id: String,
+    pub account_id: String,
+    pub available: Money,
+    pub pending: Money,
+    pub current: Money,
+    // Sequence of the last event applied.
+    pub sequence: usize,
+    pub as_of: DateTime<Utc>,
+}
+
+/// Rebuild the balances of a ledger as they were at `as_of` by replaying its
+/// events. The latest snapshot is used as the starting point when it was
+/// taken before `as_of`. Returns `None` if the ledger did not exist yet.
+pub async fn ledger_balance_as_of(
+    events: &PostgresEventRepository,
+    id: &str,
+    as_of: DateTime<Utc>,
+) -> Result<Option<LedgerBalance>, anyhow::Error> {
+    let snapshot = events
+        .get_snapshot::<Ledger>(id)
+        .await?
+        .and_then(|snapshot| {
+            let ledger: Ledger = serde_json::from_value(snapshot.aggregate).ok()?;
+            let taken_at = DateTime::parse_from_rfc3339(&ledger.timestamp).ok()?;
+            (taken_at <= as_of).then_some((ledger, snapshot.current_sequence))
+        });
+
+    let (ledger, sequence, stored) = match snapshot {
+        Some((ledger, sequence)) => {
+            let stored = events.get_last_events::<Ledger>(id, sequence).await?;
+            (ledger, sequence, stored)
+        }
+        None => (Ledger::default(), 0, events.get_events::<Ledger>(id).await?),
+    };
+
+    let (ledger, sequence) = replay_until(ledger, sequence, stored, &ledger_upcasters(), as_of)?;
+    if sequence == 0 {
+        return Ok(None);
+    }
+    Ok(Some(LedgerBalance {
+        id: ledger.id,
+        account_id: ledger.account_id,
+        available: ledger.available,
+        pending: ledger.pending,
+        current: ledger.available.try_add(ledger.pending)?,
+        sequence,
+        as_of,
+    }))
+}
+
+// Reads the event history of one aggregate type straight from its events
+// table, the framework's repository can only load every event after a sequence.
+#[derive(Clone)]
+pub struct EventHistory {
+    pool: PgPool,
+    events_table: &'static str,
+}
+
+impl EventHistory {
+    pub fn new(pool: PgPool, events_table: &'static str) -> Self {
+        Self { pool, events_table }
+    }
+}
+
+/// A page of the events of one aggregate, ordered by sequence and starting
+/// after the `after` sequence. Only the page and the first event after it are
+/// loaded.
+pub async fn event_history(
+    history: &EventHistory,
+    upcasters: &[Box<dyn EventUpcaster>],
+    id: &str,
+    after: usize,
+    limit: usize,
+) -> Result<EventPage, anyhow::Error> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let rows = sqlx::query(&format!(
+        "SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+        FROM {}
+        WHERE aggregate_id = $1 AND sequence > $2
+        ORDER BY sequence
+        LIMIT $3 + 1",
+        history.events_table
+    ))
+    .bind(id)
+    .bind(after as i64)
+    .bind(limit as i64)
+    .fetch_all(&history.pool)
+    .await?;
+    let stored = rows
+        .iter()
+        .map(serialized_event)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(paginate(stored, upcasters, limit))
+}
+
+fn paginate(
+    stored: Vec<SerializedEvent>,
+    upcasters: &[Box<dyn EventUpcaster>],
+    limit: usize,
+) -> EventPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let has_more = stored.len() > limit;
+    let entries: Vec<EventRecord> = stored
+        .into_iter()
+        .take(limit)
+        .map(|event| {
+            // The stored payload is returned untouched, only the timestamp is
+            // read through the upcasters so every version yields one.
+            let timestamp = created_at(&upcast(upcasters, event.clone()).payload);
+            EventRecord {
+                sequence: event.sequence,
+                event_type: event.event_type,
+                event_version: event.event_version,
+                payload: event.payload,
+                metadata: event.metadata,
+                timestamp,
+            }
+        })
+        .collect();
+    let next_cursor = entries.last().filter(|_| has_more).map(|e| e.sequence);
+    EventPage {
+        entries,
+        next_cursor,
+    }
+}
+
+// Events are serialized as `{"<Variant>": {..., "base_event": {...}}}`.
+fn created_at(payload: &Value) -> Option<DateTime<Utc>> {
+    let event = payload.as_object()?.values().next()?;
+    serde_json::from_value(event.pointer("/base_event/created_at")?.clone()).ok()
+}
+
+// Apply the stored events in order until the first one created after `as_of`.
+fn replay_until(
+    mut ledger: Ledger,
+    mut sequence: usize,
+    stored: Vec<SerializedEvent>,
+    upcasters: &[Box<dyn EventUpcaster>],
+    as_of: DateTime<Utc>,
+) -> Result<(Ledger, usize), anyhow::Error> {
+    for event in stored {
+        let envelope = EventEnvelope::<Ledger>::try_from(upcast(upcasters, event))?;
+        let created_at = match &envelope.payload {
+            LedgerEvent::LedgerInitiated { base_event, .. } => base_event.created_at,
+            LedgerEvent::LedgerUpdated { base_event, .. } => base_event.created_at,
+        };
+        if created_at > as_of {
+            break;
+        }
+        sequence = envelope.sequence;
+        ledger.apply(envelope.payload);
+    }
+    Ok((ledger, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::money::Currency;
+    use crate::domain::events::{LedgerTransactionType, EVENT_VERSION};
+    use crate::event_sourcing::event::{BaseEvent, Event};
+    use chrono::TimeZone;
+    use cqrs_es::DomainEvent;
+    use rust_decimal::Decimal;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 8, 3, hour, 0, 0).unwrap()
+    }
+
+    fn serialized(sequence: usize, event: LedgerEvent) -> SerializedEvent {
+        SerializedEvent::new(
+            "ledger1".to_string(),
+            sequence,
+            "Ledger".to_string(),
+            event.event_type(),
+            EVENT_VERSION.to_string(),
+            serde_json::to_value(event).unwrap(),
+            serde_json::json!({}),
+        )
+    }
+
+    fn base_event(hour: u32) -> BaseEvent {
+        let mut base_event = BaseEvent {
+            aggregate_id: "ledger1".to_string(),
+            parent_id: "account1".to_string(),
+            ..Default::default()
+        };
+        base_event.set_created_at(at(hour));
+        base_event
+    }
+
+    fn history() -> Vec<SerializedEvent> {
+        let amount = Money::new(Decimal::new(10000, 2), Currency::USD);
+        let zero = Money::new(Decimal::ZERO, Currency::USD);
+        vec![
+            serialized(
+                1,
+                LedgerEvent::LedgerInitiated {
+                    amount: zero,
+                    base_event: base_event(9),
+                },
+            ),
+            serialized(
+                2,
+                LedgerEvent::LedgerUpdated {
+                    amount,
+                    transaction_id: "transaction1".to_string(),
+                    transaction_type: LedgerTransactionType::CreditHold,
+                    available_delta: zero,
+                    pending_delta: amount,
+                    base_event: base_event(10),
+                },
+            ),
+            serialized(
+                3,
+                LedgerEvent::LedgerUpdated {
+                    amount,
+                    transaction_id: "transaction1".to_string(),
+                    transaction_type: LedgerTransactionType::CreditRelease,
+                    available_delta: amount,
+                    pending_delta: Money::new(-amount.amount, Currency::USD),
+                    base_event: base_event(14),
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_replay_until_stops_at_as_of() {
+        let (ledger, sequence) =
+            replay_until(Ledger::default(), 0, history(), &ledger_upcasters(), at(12)).unwrap();
+        assert_eq!(sequence, 2);
+        assert_eq!(ledger.id, "ledger1");
+        assert_eq!(ledger.available.amount, Decimal::ZERO);
+        assert_eq!(ledger.pending.amount, Decimal::new(10000, 2));
+
+        let (ledger, sequence) =
+            replay_until(Ledger::default(), 0, history(), &ledger_upcasters(), at(14)).unwrap();
+        assert_eq!(sequence, 3);
+        assert_eq!(ledger.available.amount, Decimal::new(10000, 2));
+        assert_eq!(ledger.pending.amount, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_paginate_events() {
+        let page = paginate(history(), &ledger_upcasters(), 2);
+        let sequences: Vec<usize> = page.entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+        assert_eq!(page.next_cursor, Some(2));
+        assert_eq!(page.entries[0].event_type, "ledger.initiated");
+        assert_eq!(page.entries[1].timestamp, Some(at(10)));
+
+        let page = paginate(history().split_off(2), &ledger_upcasters(), 2);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_paginate_reads_timestamp_of_old_versions() {
+        let mut event = history().remove(0);
+        event.event_version = "1.0".to_string();
+        event.payload["LedgerInitiated"]["base_event"]["created_at"] =
+            Value::String("2024-08-03 09:00:00 UTC".to_string());
+
+        let page = paginate(vec![event], &ledger_upcasters(), DEFAULT_PAGE_SIZE);
+        assert_eq!(page.entries[0].event_version, "1.0");
+        assert_eq!(page.entries[0].timestamp, Some(at(9)));
+    }
+
+    #[test]
+    fn test_replay_until_before_first_event() {
+        let (_, sequence) =
+            replay_until(Ledger::default(), 0, history(), &ledger_upcasters(), at(8)).unwrap();
+        assert_eq!(sequence, 0);
+    }
+}
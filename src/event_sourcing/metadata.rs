@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+// Keys of the metadata stored with every event, next to `time`, `uri` and
+// `User-Agent` that come straight from the request.
+pub const TENANT_ID: &str = "tenant_id";
+pub const REQUEST_ID: &str = "request_id";
+pub const CLIENT_IP: &str = "client_ip";
+pub const SUBJECT: &str = "subject";
+
+pub type Metadata = HashMap<String, String>;
+
+tokio::task_local! {
+    static METADATA: Metadata;
+}
+
+/// Run `f` with `metadata` as the metadata of the API call being processed.
+/// Aggregate services have no access to the metadata passed to the framework,
+/// so the ledger commands they execute read it back with [`current`].
+pub async fn with_metadata<F: Future>(metadata: Metadata, f: F) -> F::Output {
+    METADATA.scope(metadata, f).await
+}
+
+/// The metadata of the API call being processed, empty outside [`with_metadata`].
+pub fn current() -> Metadata {
+    METADATA.try_with(Clone::clone).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_current_metadata() {
+        assert!(current().is_empty());
+
+        let metadata = Metadata::from([(REQUEST_ID.to_string(), "request1".to_string())]);
+        let inner = with_metadata(metadata.clone(), async { current() }).await;
+        assert_eq!(inner, metadata);
+        assert!(current().is_empty());
+    }
+}
@@ -3,4 +3,8 @@ pub mod command;
 pub mod error;
 pub mod event;
 mod helper;
+pub mod history;
+pub mod metadata;
 pub mod query;
+pub mod replay;
+pub mod upcaster;
@@ -1,14 +1,19 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use cqrs_es::persist::GenericQuery;
-use cqrs_es::{EventEnvelope, Query, View};
+use cqrs_es::persist::{GenericQuery, ViewRepository};
+use cqrs_es::{Aggregate, EventEnvelope, Query, View};
 use postgres_es::PostgresViewRepository;
 use rust_decimal::Decimal;
-use tracing::trace;
+use serde_json::json;
+use tracing::{error, trace};
 
 use crate::common::money::Money;
 use crate::domain::events::{BankAccountEvent, LedgerEvent};
 use crate::domain::models::{BankAccount, BankAccountStatus, BankAccountView, Ledger, LedgerView};
-use crate::event_sourcing::event::Event;
+use crate::event_sourcing::event::{BaseEvent, Event};
+use crate::repository::adapter::{Adapter, DatabaseClient};
+use crate::stream::{EventStream, ACCOUNT_STATUS, BALANCE_UPDATED};
 
 pub struct AccountLogging {}
 
@@ -35,6 +40,11 @@ pub type AccountQuery = GenericQuery<
 // design the events to carry the balance information instead.
 impl View<BankAccount> for BankAccountView {
     fn update(&mut self, event: &EventEnvelope<BankAccount>) {
+        // Already applied by a replay, see `event_sourcing::replay`.
+        if event.sequence <= self.sequence {
+            return;
+        }
+        self.sequence = event.sequence;
         match &event.payload {
             BankAccountEvent::AccountOpened {
                 base_event,
@@ -45,6 +55,7 @@ impl View<BankAccount> for BankAccountView {
             } => {
                 self.id = base_event.get_aggregate_id();
                 self.parent_id = base_event.get_parent_id();
+                self.tenant_id = base_event.get_tenant_id();
                 self.status = BankAccountStatus::Pending;
                 self.created_at = base_event.get_created_at();
                 self.updated_at = base_event.get_created_at();
@@ -88,10 +99,16 @@ pub type LedgerQuery = GenericQuery<PostgresViewRepository<LedgerView, Ledger>,
 // This updates the view with events as they are committed.
 impl View<Ledger> for LedgerView {
     fn update(&mut self, event: &EventEnvelope<Ledger>) {
+        // Already applied by a replay, see `event_sourcing::replay`.
+        if event.sequence <= self.sequence {
+            return;
+        }
+        self.sequence = event.sequence;
         match &event.payload {
             LedgerEvent::LedgerInitiated { base_event, amount } => {
                 self.id = base_event.get_aggregate_id();
                 self.account_id = base_event.get_parent_id();
+                self.tenant_id = base_event.get_tenant_id();
                 self.created_at = base_event.get_created_at();
                 self.updated_at = base_event.get_created_at();
                 self.available = *amount;
@@ -109,18 +126,187 @@ impl View<Ledger> for LedgerView {
                 let account_id = base_event.get_parent_id();
                 self.id = base_event.get_aggregate_id();
                 self.account_id = account_id.clone();
-                self.available = self.available + *available_delta;
-                self.pending = self.pending + *pending_delta;
-                self.current = self.available + self.pending;
+                let balances = self
+                    .available
+                    .try_add(*available_delta)
+                    .and_then(|available| {
+                        let pending = self.pending.try_add(*pending_delta)?;
+                        Ok((available, pending, available.try_add(pending)?))
+                    });
+                match balances {
+                    Ok((available, pending, current)) => {
+                        self.available = available;
+                        self.pending = pending;
+                        self.current = current;
+                    }
+                    Err(err) => error!("Ledger view {} skipped an update: {}", self.id, err),
+                }
                 self.updated_at = base_event.get_created_at();
             }
         }
     }
 }
 
+// Queues a webhook delivery for every committed event that tenants can
+// subscribe to, only the endpoints of the tenant owning the aggregate receive
+// it. Deliveries are sent by the webhook job, so a slow endpoint never holds
+// up the command that produced the event.
+pub struct WebhookDispatcher<C: DatabaseClient + Send + Sync> {
+    database: Arc<Adapter<C>>,
+}
+
+impl<C: DatabaseClient + Send + Sync> WebhookDispatcher<C> {
+    pub fn new(database: Arc<Adapter<C>>) -> Self {
+        Self { database }
+    }
+
+    async fn enqueue<A: Aggregate>(
+        &self,
+        event_type: &str,
+        base_event: &BaseEvent,
+        event: &EventEnvelope<A>,
+    ) {
+        let created_at = base_event.get_created_at();
+        let payload = json!({
+            "type": event_type,
+            "aggregate_id": event.aggregate_id,
+            "sequence": event.sequence,
+            "created_at": created_at,
+            "data": event.payload,
+        });
+        if let Err(e) = self
+            .database
+            .enqueue_webhook_deliveries(base_event.get_tenant_id(), event_type.to_string(), payload)
+            .await
+        {
+            error!("Failed to enqueue webhook {}: {}", event_type, e);
+        }
+    }
+}
+
+#[async_trait]
+impl<C: DatabaseClient + Send + Sync + 'static> Query<BankAccount> for WebhookDispatcher<C> {
+    async fn dispatch(&self, _aggregate_id: &str, events: &[EventEnvelope<BankAccount>]) {
+        for event in events {
+            let (event_type, base_event) = match &event.payload {
+                BankAccountEvent::AccountOpened { base_event, .. } => {
+                    ("bank_account.opened", base_event)
+                }
+                BankAccountEvent::AccountKycApproved { base_event, .. } => {
+                    ("bank_account.kyc_approved", base_event)
+                }
+                // Deposits and withdrawals are announced once their transaction
+                // settles, see `transaction.completed` and `transaction.failed`.
+                _ => continue,
+            };
+            self.enqueue(event_type, base_event, event).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<C: DatabaseClient + Send + Sync + 'static> Query<Ledger> for WebhookDispatcher<C> {
+    async fn dispatch(&self, _aggregate_id: &str, events: &[EventEnvelope<Ledger>]) {
+        for event in events {
+            if let LedgerEvent::LedgerUpdated { base_event, .. } = &event.payload {
+                self.enqueue("ledger.updated", base_event, event).await;
+            }
+        }
+    }
+}
+
+// Pushes account status changes to the live event stream.
+pub struct AccountStream {
+    stream: Arc<EventStream>,
+}
+
+impl AccountStream {
+    pub fn new(stream: Arc<EventStream>) -> Self {
+        Self { stream }
+    }
+}
+
+#[async_trait]
+impl Query<BankAccount> for AccountStream {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<BankAccount>]) {
+        for event in events {
+            let (base_event, data) = match &event.payload {
+                BankAccountEvent::AccountOpened { base_event, .. } => {
+                    (base_event, json!({ "status": BankAccountStatus::Pending }))
+                }
+                BankAccountEvent::AccountKycApproved {
+                    ledger_id,
+                    base_event,
+                } => (
+                    base_event,
+                    json!({ "status": BankAccountStatus::Approved, "ledger_id": ledger_id }),
+                ),
+                _ => continue,
+            };
+            self.stream.publish(
+                base_event.get_tenant_id(),
+                ACCOUNT_STATUS,
+                aggregate_id.to_string(),
+                data,
+            );
+        }
+    }
+}
+
+// Pushes the new ledger balances to the live event stream. It must be
+// registered after `LedgerQuery` so the view already reflects the events.
+pub struct LedgerStream {
+    stream: Arc<EventStream>,
+    query: Arc<PostgresViewRepository<LedgerView, Ledger>>,
+}
+
+impl LedgerStream {
+    pub fn new(
+        stream: Arc<EventStream>,
+        query: Arc<PostgresViewRepository<LedgerView, Ledger>>,
+    ) -> Self {
+        Self { stream, query }
+    }
+}
+
+#[async_trait]
+impl Query<Ledger> for LedgerStream {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<Ledger>]) {
+        let Some(event) = events.last() else {
+            return;
+        };
+        let view = match self.query.load(aggregate_id).await {
+            Ok(Some(view)) => view,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to load ledger {} for stream: {}", aggregate_id, e);
+                return;
+            }
+        };
+        let transaction_id = match &event.payload {
+            LedgerEvent::LedgerUpdated { transaction_id, .. } => Some(transaction_id),
+            LedgerEvent::LedgerInitiated { .. } => None,
+        };
+        let data = json!({
+            "ledger_id": view.id,
+            "sequence": event.sequence,
+            "transaction_id": transaction_id,
+            "available": view.available,
+            "pending": view.pending,
+            "current": view.current,
+            "updated_at": view.updated_at,
+        });
+        self.stream
+            .publish(view.tenant_id, BALANCE_UPDATED, view.account_id, data);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{common::money::Currency, event_sourcing::event::BaseEvent};
+    use crate::{
+        common::money::Currency, domain::events::LedgerTransactionType,
+        event_sourcing::event::BaseEvent,
+    };
 
     use super::*;
     use chrono::Utc;
@@ -132,7 +318,8 @@ mod tests {
         let base_event = BaseEvent {
             aggregate_id: "ledger1".to_string(),
             parent_id: "account1".to_string(),
-            created_at: Utc::now().to_string(),
+            created_at: Utc::now(),
+            tenant_id: 3,
         };
         let amount = Money::new(Decimal::new(1000, 2), Currency::USD);
         let event = EventEnvelope {
@@ -149,6 +336,7 @@ mod tests {
 
         assert_eq!(ledger_view.id, base_event.get_aggregate_id());
         assert_eq!(ledger_view.account_id, base_event.get_parent_id());
+        assert_eq!(ledger_view.tenant_id, 3);
         assert_eq!(ledger_view.created_at, base_event.get_created_at());
         assert_eq!(ledger_view.updated_at, base_event.get_created_at());
         assert_eq!(ledger_view.available, amount);
@@ -165,7 +353,8 @@ mod tests {
         let base_event = BaseEvent {
             aggregate_id: "ledger1".to_string(),
             parent_id: "account1".to_string(),
-            created_at: Utc::now().to_string(),
+            created_at: Utc::now(),
+            tenant_id: 3,
         };
         let available_delta = Money::new(Decimal::new(500, 2), Currency::USD);
         let pending_delta = Money::new(Decimal::new(-200, 2), Currency::USD);
@@ -176,7 +365,7 @@ mod tests {
             payload: LedgerEvent::LedgerUpdated {
                 amount: available_delta,
                 transaction_id: "transaction1".to_string(),
-                transaction_type: "credit".to_string(),
+                transaction_type: LedgerTransactionType::CreditRelease,
                 available_delta,
                 pending_delta,
                 base_event: base_event.clone(),
@@ -189,7 +378,110 @@ mod tests {
         assert_eq!(ledger_view.account_id, base_event.get_parent_id());
         assert_eq!(ledger_view.available, available_delta);
         assert_eq!(ledger_view.pending, pending_delta);
-        assert_eq!(ledger_view.current, available_delta + pending_delta);
+        assert_eq!(
+            ledger_view.current,
+            available_delta.try_add(pending_delta).unwrap()
+        );
         assert_eq!(ledger_view.updated_at, base_event.get_created_at());
     }
+
+    #[test]
+    fn test_update_ignores_applied_events() {
+        let mut ledger_view = LedgerView::default();
+        let base_event = BaseEvent {
+            aggregate_id: "ledger1".to_string(),
+            parent_id: "account1".to_string(),
+            created_at: Utc::now(),
+            tenant_id: 3,
+        };
+        let delta = Money::new(Decimal::new(500, 2), Currency::USD);
+        let event = EventEnvelope {
+            aggregate_id: "ledger1".to_string(),
+            metadata: Default::default(),
+            sequence: 2,
+            payload: LedgerEvent::LedgerUpdated {
+                amount: delta,
+                transaction_id: "transaction1".to_string(),
+                transaction_type: LedgerTransactionType::CreditRelease,
+                available_delta: delta,
+                pending_delta: Money::new(Decimal::ZERO, Currency::USD),
+                base_event,
+            },
+        };
+
+        // Delivered by the service again after a replay applied it.
+        ledger_view.update(&event);
+        ledger_view.update(&event);
+
+        assert_eq!(ledger_view.sequence, 2);
+        assert_eq!(ledger_view.available, delta);
+    }
+
+    #[test]
+    fn test_update_with_other_currency_keeps_balances() {
+        let mut ledger_view = LedgerView::default();
+        let base_event = BaseEvent {
+            aggregate_id: "ledger1".to_string(),
+            parent_id: "account1".to_string(),
+            created_at: Utc::now(),
+            tenant_id: 3,
+        };
+        let delta = Money::new(Decimal::new(500, 0), Currency::TWD);
+        let event = EventEnvelope {
+            aggregate_id: "ledger1".to_string(),
+            metadata: Default::default(),
+            sequence: 2,
+            payload: LedgerEvent::LedgerUpdated {
+                amount: delta,
+                transaction_id: "transaction1".to_string(),
+                transaction_type: LedgerTransactionType::CreditRelease,
+                available_delta: delta,
+                pending_delta: delta,
+                base_event: base_event.clone(),
+            },
+        };
+
+        ledger_view.update(&event);
+
+        assert_eq!(ledger_view.available, Money::default());
+        assert_eq!(ledger_view.pending, Money::default());
+        assert_eq!(ledger_view.current, Money::default());
+        assert_eq!(ledger_view.updated_at, base_event.get_created_at());
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_go_to_the_event_tenant() {
+        use crate::domain::models::{BankAccountKind, BankAccountType};
+        use crate::repository::adapter::MockDatabaseClient;
+
+        let mut db = MockDatabaseClient::new();
+        db.expect_enqueue_webhook_deliveries()
+            .withf(|tenant_id, event_type, payload| {
+                *tenant_id == 3
+                    && event_type == "bank_account.opened"
+                    && payload["aggregate_id"] == "account1"
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(1));
+        let dispatcher = WebhookDispatcher::new(Arc::new(Adapter::new(db)));
+        let event = EventEnvelope {
+            aggregate_id: "account1".to_string(),
+            metadata: Default::default(),
+            sequence: 1,
+            payload: BankAccountEvent::AccountOpened {
+                account_type: BankAccountType::Retail,
+                kind: BankAccountKind::Checking,
+                currency: Currency::USD,
+                user_id: "user1".to_string(),
+                base_event: BaseEvent {
+                    aggregate_id: "account1".to_string(),
+                    parent_id: String::new(),
+                    created_at: Utc::now(),
+                    tenant_id: 3,
+                },
+            },
+        };
+
+        Query::<BankAccount>::dispatch(&dispatcher, "account1", &[event]).await;
+    }
 }
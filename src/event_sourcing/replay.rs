@@ -0,0 +1,417 @@
+use std::marker::PhantomData;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use cqrs_es::persist::{EventUpcaster, SerializedEvent};
+use cqrs_es::{Aggregate, EventEnvelope, View};
+use sqlx::postgres::PgRow;
+use sqlx::{Connection, PgConnection, PgPool, Row};
+use tracing::info;
+
+use crate::domain::models::{BankAccount, BankAccountView, Ledger, LedgerView};
+use crate::event_sourcing::upcaster::{bank_account_upcasters, ledger_upcasters, upcast};
+
+// A row of an events table, selected with every column of `SerializedEvent`.
+pub fn serialized_event(row: &PgRow) -> Result<SerializedEvent, sqlx::Error> {
+    Ok(SerializedEvent::new(
+        row.try_get("aggregate_id")?,
+        row.try_get::<i64, _>("sequence")? as usize,
+        row.try_get("aggregate_type")?,
+        row.try_get("event_type")?,
+        row.try_get("event_version")?,
+        row.try_get("payload")?,
+        row.try_get("metadata")?,
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    // Only rebuild the views of these aggregates, all of them when empty.
+    pub aggregate_ids: Vec<String>,
+    // Number of aggregates loaded and written per round trip.
+    pub batch_size: i64,
+    // Build into a shadow table and swap it with the live one at the end.
+    pub shadow: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    pub aggregates: usize,
+    pub events: usize,
+}
+
+/// A materialized view that can be rebuilt from its event table.
+#[async_trait]
+pub trait Projection: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn rebuild(
+        &self,
+        pool: &PgPool,
+        options: &ReplayOptions,
+    ) -> Result<ReplayReport, anyhow::Error>;
+}
+
+/// Every projection known to `--mode replay`, register new views here.
+pub fn projections() -> Vec<Box<dyn Projection>> {
+    vec![
+        Box::new(ViewProjection::<BankAccountView, BankAccount>::new(
+            "bank_account_events",
+            "bank_account_views",
+            bank_account_upcasters,
+        )),
+        Box::new(ViewProjection::<LedgerView, Ledger>::new(
+            "ledger_events",
+            "ledger_views",
+            ledger_upcasters,
+        )),
+    ]
+}
+
+/// Rebuild the named projections, or every projection when `names` is empty.
+pub async fn replay(
+    pool: &PgPool,
+    names: &[String],
+    options: &ReplayOptions,
+) -> Result<(), anyhow::Error> {
+    if options.shadow && !options.aggregate_ids.is_empty() {
+        bail!("--shadow rebuilds whole tables and cannot be combined with --aggregate-id");
+    }
+    if options.batch_size < 1 {
+        bail!("--batch-size must be positive");
+    }
+
+    let projections = projections();
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !projections.iter().any(|p| p.name() == name.as_str()))
+    {
+        bail!("unknown projection: {}", unknown);
+    }
+
+    for projection in projections
+        .iter()
+        .filter(|p| names.is_empty() || names.iter().any(|name| name == p.name()))
+    {
+        let report = projection.rebuild(pool, options).await?;
+        info!(
+            "Rebuilt {}: {} aggregates from {} events",
+            projection.name(),
+            report.aggregates,
+            report.events
+        );
+    }
+    Ok(())
+}
+
+// Table names are compile-time constants, never user input, so they are
+// safe to format into the statements below.
+pub struct ViewProjection<V, A> {
+    events_table: &'static str,
+    view_table: &'static str,
+    upcasters: fn() -> Vec<Box<dyn EventUpcaster>>,
+    _phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> ViewProjection<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    pub fn new(
+        events_table: &'static str,
+        view_table: &'static str,
+        upcasters: fn() -> Vec<Box<dyn EventUpcaster>>,
+    ) -> Self {
+        Self {
+            events_table,
+            view_table,
+            upcasters,
+            _phantom: PhantomData,
+        }
+    }
+
+    fn shadow_table(&self) -> String {
+        format!("{}_shadow", self.view_table)
+    }
+
+    // Keeps new events out and the service from writing views until the
+    // transaction commits. Events committed before are in the rebuilt views,
+    // and views ignore them when the service delivers them afterwards.
+    async fn lock_tables(&self, conn: &mut PgConnection) -> Result<(), anyhow::Error> {
+        for statement in [
+            format!("LOCK TABLE {} IN SHARE MODE", self.events_table),
+            format!("LOCK TABLE {} IN EXCLUSIVE MODE", self.view_table),
+        ] {
+            sqlx::query(&statement).execute(&mut *conn).await?;
+        }
+        Ok(())
+    }
+
+    // Load the events of `aggregate_ids`, fold them into fresh views and
+    // write those to `table`. Returns the number of events applied.
+    async fn rebuild_batch(
+        &self,
+        conn: &mut PgConnection,
+        table: &str,
+        aggregate_ids: &[String],
+    ) -> Result<usize, anyhow::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+            FROM {}
+            WHERE aggregate_id = ANY($1)
+            ORDER BY aggregate_id, sequence",
+            self.events_table
+        ))
+        .bind(aggregate_ids)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let upcasters = (self.upcasters)();
+        let mut views: Vec<(String, i64, V)> = vec![];
+        for row in &rows {
+            let event = upcast(&upcasters, serialized_event(row)?);
+            let envelope = EventEnvelope::<A>::try_from(event)?;
+            match views.last_mut() {
+                Some((view_id, version, view)) if *view_id == envelope.aggregate_id => {
+                    view.update(&envelope);
+                    *version += 1;
+                }
+                _ => {
+                    let mut view = V::default();
+                    view.update(&envelope);
+                    views.push((envelope.aggregate_id.clone(), 1, view));
+                }
+            }
+        }
+
+        // An existing row keeps counting up from its own version, so writers
+        // that loaded it before the rebuild fail their optimistic lock instead
+        // of overwriting the rebuilt view.
+        let statement = format!(
+            "INSERT INTO {table} (view_id, version, payload)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (view_id)
+            DO UPDATE SET version = {table}.version + 1, payload = EXCLUDED.payload",
+            table = table
+        );
+        for (view_id, version, view) in views {
+            sqlx::query(&statement)
+                .bind(view_id)
+                .bind(version)
+                .bind(serde_json::to_value(&view)?)
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(rows.len())
+    }
+
+    // Replace the live table with the shadow one. Aggregates with events
+    // committed while the shadow was built are rebuilt again first, under the
+    // locks that keep new events and view writes out until the swap commits.
+    async fn swap(&self, pool: &PgPool) -> Result<(), anyhow::Error> {
+        let shadow = self.shadow_table();
+        let mut tx = pool.begin().await?;
+        self.lock_tables(&mut tx).await?;
+
+        let changed: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT e.aggregate_id
+            FROM {events} e
+            LEFT JOIN {shadow} s ON s.view_id = e.aggregate_id
+            GROUP BY e.aggregate_id
+            HAVING MAX(e.sequence) > COALESCE(MAX((s.payload->>'sequence')::bigint), 0)",
+            events = self.events_table,
+            shadow = shadow
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+        if !changed.is_empty() {
+            info!("{}: catching up {} views", self.view_table, changed.len());
+            self.rebuild_batch(&mut tx, &shadow, &changed).await?;
+        }
+
+        // Foreign keys follow the renamed table, so they are recreated against
+        // the new one. Recreating them also validates the rebuilt views.
+        let foreign_keys = sqlx::query(
+            "SELECT conrelid::regclass::text AS table_name, conname, pg_get_constraintdef(oid) AS definition
+            FROM pg_constraint
+            WHERE contype = 'f' AND confrelid = $1::regclass",
+        )
+        .bind(self.view_table)
+        .fetch_all(&mut *tx)
+        .await?;
+        for fk in &foreign_keys {
+            let table: String = fk.try_get("table_name")?;
+            let name: String = fk.try_get("conname")?;
+            sqlx::query(&format!("ALTER TABLE {} DROP CONSTRAINT {}", table, name))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for statement in [
+            format!("DROP TABLE {}", self.view_table),
+            format!("ALTER TABLE {} RENAME TO {}", shadow, self.view_table),
+            format!(
+                "ALTER INDEX {}_pkey RENAME TO {}_pkey",
+                shadow, self.view_table
+            ),
+        ] {
+            sqlx::query(&statement).execute(&mut *tx).await?;
+        }
+
+        for fk in &foreign_keys {
+            let table: String = fk.try_get("table_name")?;
+            let name: String = fk.try_get("conname")?;
+            let definition: String = fk.try_get("definition")?;
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD CONSTRAINT {} {}",
+                table, name, definition
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<V, A> Projection for ViewProjection<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    fn name(&self) -> &str {
+        self.view_table
+    }
+
+    async fn rebuild(
+        &self,
+        pool: &PgPool,
+        options: &ReplayOptions,
+    ) -> Result<ReplayReport, anyhow::Error> {
+        let mut conn = pool.acquire().await?;
+        let target = if options.shadow {
+            let shadow = self.shadow_table();
+            sqlx::query(&format!("DROP TABLE IF EXISTS {}", shadow))
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&format!(
+                "CREATE TABLE {} (LIKE {} INCLUDING ALL)",
+                shadow, self.view_table
+            ))
+            .execute(&mut *conn)
+            .await?;
+            shadow
+        } else {
+            self.view_table.to_string()
+        };
+
+        let total: i64 = if options.aggregate_ids.is_empty() {
+            sqlx::query_scalar(&format!(
+                "SELECT COUNT(DISTINCT aggregate_id) FROM {}",
+                self.events_table
+            ))
+            .fetch_one(&mut *conn)
+            .await?
+        } else {
+            options.aggregate_ids.len() as i64
+        };
+
+        let mut report = ReplayReport::default();
+        let mut last_id = String::new();
+        loop {
+            let batch: Vec<String> = if options.aggregate_ids.is_empty() {
+                sqlx::query_scalar(&format!(
+                    "SELECT DISTINCT aggregate_id FROM {}
+                    WHERE aggregate_id > $1
+                    ORDER BY aggregate_id
+                    LIMIT $2",
+                    self.events_table
+                ))
+                .bind(&last_id)
+                .bind(options.batch_size)
+                .fetch_all(&mut *conn)
+                .await?
+            } else {
+                options
+                    .aggregate_ids
+                    .iter()
+                    .skip(report.aggregates)
+                    .take(options.batch_size as usize)
+                    .cloned()
+                    .collect()
+            };
+            let Some(last) = batch.last() else {
+                break;
+            };
+            last_id = last.clone();
+
+            // In place, every batch is rebuilt under the locks so the running
+            // service cannot commit an event the rebuilt views miss.
+            report.events += if options.shadow {
+                self.rebuild_batch(&mut conn, &target, &batch).await?
+            } else {
+                let mut tx = conn.begin().await?;
+                self.lock_tables(&mut tx).await?;
+                let events = self.rebuild_batch(&mut tx, &target, &batch).await?;
+                tx.commit().await?;
+                events
+            };
+            report.aggregates += batch.len();
+            info!(
+                "{}: {}/{} aggregates, {} events",
+                self.view_table, report.aggregates, total, report.events
+            );
+        }
+
+        if options.shadow {
+            self.swap(pool).await?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> ReplayOptions {
+        ReplayOptions {
+            aggregate_ids: vec![],
+            batch_size: 100,
+            shadow: false,
+        }
+    }
+
+    fn lazy_pool() -> PgPool {
+        PgPool::connect_lazy("postgres://localhost/bankie_test").unwrap()
+    }
+
+    #[test]
+    fn test_projections_are_named_after_view_tables() {
+        let names: Vec<String> = projections().iter().map(|p| p.name().to_string()).collect();
+        assert_eq!(names, vec!["bank_account_views", "ledger_views"]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_rejects_unknown_projection() {
+        let err = replay(&lazy_pool(), &["user_views".to_string()], &options())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown projection: user_views");
+    }
+
+    #[tokio::test]
+    async fn test_replay_rejects_shadow_with_aggregate_filter() {
+        let mut options = options();
+        options.shadow = true;
+        options.aggregate_ids = vec!["ledger1".to_string()];
+        assert!(replay(&lazy_pool(), &[], &options).await.is_err());
+
+        options.shadow = false;
+        options.batch_size = 0;
+        assert!(replay(&lazy_pool(), &[], &options).await.is_err());
+    }
+}
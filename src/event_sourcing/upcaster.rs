@@ -0,0 +1,211 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use cqrs_es::persist::{EventUpcaster, SemanticVersionEventUpcaster, SerializedEvent};
+use serde_json::{json, Value};
+
+// Version 2.0 types `base_event.created_at` as a UTC timestamp and
+// `LedgerUpdated.transaction_type` as `LedgerTransactionType`. The latter kept
+// the 1.0 string values, so only the timestamp has to be rewritten.
+const V2_0: &str = "2.0.0";
+
+const BANK_ACCOUNT_EVENT_TYPES: [&str; 4] = [
+    "bank_account.opened",
+    "bank_account.kyc_approved",
+    "bank_account.deposited",
+    "bank_account.withdrew",
+];
+const LEDGER_EVENT_TYPES: [&str; 2] = ["ledger.initiated", "ledger.updated"];
+
+/// Upcasters for stored `BankAccountEvent`s, in the order they are applied.
+pub fn bank_account_upcasters() -> Vec<Box<dyn EventUpcaster>> {
+    v2_upcasters(&BANK_ACCOUNT_EVENT_TYPES)
+}
+
+/// Upcasters for stored `LedgerEvent`s, in the order they are applied.
+pub fn ledger_upcasters() -> Vec<Box<dyn EventUpcaster>> {
+    v2_upcasters(&LEDGER_EVENT_TYPES)
+}
+
+/// Apply every matching upcaster to a stored event, the same way the event
+/// store does when an aggregate is loaded.
+pub fn upcast(upcasters: &[Box<dyn EventUpcaster>], mut event: SerializedEvent) -> SerializedEvent {
+    for upcaster in upcasters {
+        if upcaster.can_upcast(&event.event_type, &event.event_version) {
+            event = upcaster.upcast(event);
+        }
+    }
+    event
+}
+
+fn v2_upcasters(event_types: &[&str]) -> Vec<Box<dyn EventUpcaster>> {
+    event_types
+        .iter()
+        .map(|event_type| {
+            Box::new(SemanticVersionEventUpcaster::new(
+                event_type,
+                V2_0,
+                Box::new(upcast_created_at_v2),
+            )) as Box<dyn EventUpcaster>
+        })
+        .collect()
+}
+
+// Events are serialized as `{"<Variant>": {..., "base_event": {...}}}`.
+fn upcast_created_at_v2(mut payload: Value) -> Value {
+    let created_at = payload
+        .as_object_mut()
+        .and_then(|variant| variant.values_mut().next())
+        .and_then(|event| event.pointer_mut("/base_event/created_at"));
+    if let Some(created_at) = created_at {
+        // Timestamps that were never set are stored as an empty string, they
+        // become the Unix epoch like a defaulted `BaseEvent`.
+        let parsed = created_at
+            .as_str()
+            .and_then(parse_v1_timestamp)
+            .unwrap_or_default();
+        *created_at = json!(parsed);
+    }
+    payload
+}
+
+// Version 1.0 stored RFC 3339 strings, or `Utc::now().to_string()` in a few
+// older writers.
+fn parse_v1_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f UTC")
+                .map(|dt| dt.and_utc())
+                .ok()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::events::{
+        BankAccountEvent, LedgerEvent, LedgerTransactionType, EVENT_VERSION,
+    };
+    use crate::event_sourcing::event::Event;
+    use chrono::TimeZone;
+    use cqrs_es::persist::SerializedEvent;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        event_type: String,
+        event_version: String,
+        payload: Value,
+    }
+
+    fn load_fixtures(content: &str) -> Vec<SerializedEvent> {
+        let fixtures: Vec<Fixture> = serde_json::from_str(content).unwrap();
+        fixtures
+            .into_iter()
+            .enumerate()
+            .map(|(i, f)| {
+                SerializedEvent::new(
+                    "aggregate".to_string(),
+                    i + 1,
+                    String::new(),
+                    f.event_type,
+                    f.event_version,
+                    f.payload,
+                    Value::Null,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_upcast_ledger_fixtures() {
+        let upcasters = ledger_upcasters();
+        let fixtures = load_fixtures(include_str!(
+            "../../tests/fixtures/events/ledger_events.json"
+        ));
+        let mut versions: Vec<String> = fixtures.iter().map(|e| e.event_version.clone()).collect();
+        versions.dedup();
+        assert_eq!(versions, vec!["1.0", "2.0.0"]);
+
+        let events: Vec<LedgerEvent> = fixtures
+            .into_iter()
+            .map(|e| {
+                let event = upcast(&upcasters, e);
+                assert_eq!(event.event_version, EVENT_VERSION);
+                serde_json::from_value(event.payload).unwrap()
+            })
+            .collect();
+
+        let LedgerEvent::LedgerUpdated {
+            transaction_type,
+            base_event,
+            ..
+        } = &events[1]
+        else {
+            panic!("expected LedgerUpdated");
+        };
+        assert_eq!(*transaction_type, LedgerTransactionType::CreditHold);
+        // Offsets are normalized to UTC.
+        assert_eq!(
+            base_event.created_at,
+            Utc.with_ymd_and_hms(2024, 7, 1, 8, 30, 0).unwrap()
+        );
+
+        let LedgerEvent::LedgerUpdated { base_event, .. } = &events[2] else {
+            panic!("expected LedgerUpdated");
+        };
+        assert_eq!(
+            base_event.get_created_at(),
+            "2024-07-01T08:30:00.123456+00:00"
+        );
+    }
+
+    #[test]
+    fn test_upcast_bank_account_fixtures() {
+        let upcasters = bank_account_upcasters();
+        let fixtures = load_fixtures(include_str!(
+            "../../tests/fixtures/events/bank_account_events.json"
+        ));
+        let mut event_types: Vec<String> = fixtures.iter().map(|e| e.event_type.clone()).collect();
+        event_types.dedup();
+        assert_eq!(event_types, BANK_ACCOUNT_EVENT_TYPES);
+
+        let events: Vec<BankAccountEvent> = fixtures
+            .into_iter()
+            .map(|e| {
+                let event = upcast(&upcasters, e);
+                assert_eq!(event.event_version, EVENT_VERSION);
+                serde_json::from_value(event.payload).unwrap()
+            })
+            .collect();
+
+        let BankAccountEvent::AccountKycApproved { base_event, .. } = &events[1] else {
+            panic!("expected AccountKycApproved");
+        };
+        assert_eq!(base_event.created_at, DateTime::<Utc>::default());
+    }
+
+    #[test]
+    fn test_upcast_skips_current_version() {
+        let payload = json!({
+            "LedgerInitiated": {
+                "amount": { "amount": "0", "currency": "USD" },
+                "base_event": {
+                    "created_at": "not a timestamp",
+                    "aggregate_id": "ledger1",
+                    "parent_id": "account1"
+                }
+            }
+        });
+        let event = SerializedEvent::new(
+            "ledger1".to_string(),
+            1,
+            String::new(),
+            "ledger.initiated".to_string(),
+            EVENT_VERSION.to_string(),
+            payload.clone(),
+            Value::Null,
+        );
+        assert_eq!(upcast(&ledger_upcasters(), event).payload, payload);
+    }
+}
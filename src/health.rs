@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+// State of the background work that `/readyz` and the diagnostics report on,
+// kept up to date by the server.
+#[derive(Debug, Default)]
+pub struct Health {
+    scheduler_running: AtomicBool,
+    queued_commands: AtomicUsize,
+    shutting_down: AtomicBool,
+}
+
+impl Health {
+    pub fn set_scheduler_running(&self, running: bool) {
+        self.scheduler_running.store(running, Ordering::Relaxed);
+    }
+
+    pub fn scheduler_running(&self) -> bool {
+        self.scheduler_running.load(Ordering::Relaxed)
+    }
+
+    // Called when a command is sent to the command processor.
+    pub fn command_queued(&self) {
+        self.queued_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Called when the command processor takes a command, or sending failed.
+    pub fn command_taken(&self) {
+        self.queued_commands.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn queued_commands(&self) -> usize {
+        self.queued_commands.load(Ordering::Relaxed)
+    }
+
+    // Set on SIGTERM, new commands are refused from then on.
+    pub fn set_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    pub fn shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Ok,
+    Failed,
+    // Not set up on this server, e.g. no Redis client.
+    Disabled,
+}
+
+impl Check {
+    // Outcome of a probe run under a timeout, failed when it errs or times out.
+    pub fn probed<T, E, Elapsed>(result: Result<Result<T, E>, Elapsed>) -> Check {
+        match result {
+            Ok(Ok(_)) => Check::Ok,
+            _ => Check::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: Check,
+    pub redis: Check,
+    pub scheduler: Check,
+    pub command_queue: Check,
+    pub queued_commands: usize,
+}
+
+impl Readiness {
+    // Redis only backs caches and rate limits, which fail open, so a Redis
+    // outage is reported without taking the server out of rotation.
+    pub fn new(
+        database: Check,
+        redis: Check,
+        scheduler_running: bool,
+        queued_commands: usize,
+        max_queued_commands: usize,
+    ) -> Self {
+        let scheduler = match scheduler_running {
+            true => Check::Ok,
+            false => Check::Failed,
+        };
+        let command_queue = match queued_commands <= max_queued_commands {
+            true => Check::Ok,
+            false => Check::Failed,
+        };
+        Readiness {
+            ready: database == Check::Ok && scheduler == Check::Ok && command_queue == Check::Ok,
+            database,
+            redis,
+            scheduler,
+            command_queue,
+            queued_commands,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let ready = Readiness::new(Check::Ok, Check::Failed, true, 10, 10);
+        assert!(ready.ready);
+        assert_eq!(ready.redis, Check::Failed);
+
+        let busy = Readiness::new(Check::Ok, Check::Ok, true, 11, 10);
+        assert!(!busy.ready);
+        assert_eq!(busy.command_queue, Check::Failed);
+
+        assert!(!Readiness::new(Check::Failed, Check::Ok, true, 0, 10).ready);
+        assert!(!Readiness::new(Check::Ok, Check::Ok, false, 0, 10).ready);
+    }
+
+    #[test]
+    fn test_queued_commands() {
+        let health = Health::default();
+        health.command_queued();
+        health.command_queued();
+        health.command_taken();
+        assert_eq!(health.queued_commands(), 1);
+    }
+}
@@ -1,115 +1,229 @@
-use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{anyhow, Context};
-use rust_decimal::Decimal;
+use chrono::Utc;
+use sqlx::postgres::PgListener;
+use tokio::sync::Notify;
+use tokio::task;
 use tokio_cron_scheduler::{Job, JobSchedulerError};
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
-    common::money::{Currency, Money},
-    domain::finance::Outbox,
-    event_sourcing::command::LedgerCommand,
-    repository::redis::{acquire_lock, release_lock, LOCK_KEY, LOCK_TIMEOUT},
-    state::LedgerLoaderSaver,
+    configs::settings::SETTINGS,
+    metrics::METRICS,
+    outbox::{fail_event, process_event},
+    repository::adapter::{Adapter, DatabaseClient},
+    webhook::{deliver, http_client, next_attempt_at},
     SharedState,
 };
 
-pub async fn create_ledger_job(state: SharedState) -> Result<Job, JobSchedulerError> {
-    Job::new_async("1/10 * * * * *", move |_uuid, _l| {
-        let db = state.database.clone();
-        let ledger = state.ledger.clone().unwrap();
-        let cache = state.cache.clone().unwrap();
-        Box::pin(async move {
-            match db.get_unprocessed_outbox().await {
-                Ok(events) => {
-                    // acquire lock
-                    let identifier = acquire_lock(&cache, LOCK_KEY, LOCK_TIMEOUT).await.unwrap();
+// Channel notified by the `notify_outbox` trigger.
+const OUTBOX_CHANNEL: &str = "outbox";
+const OUTBOX_BATCH_SIZE: i64 = 100;
+// Wait before connecting again after the listener failed.
+const LISTENER_RETRY: Duration = Duration::from_secs(5);
 
-                    // process events
-                    for event in events {
-                        info!("Processing event: {:?}", event);
-                        match process_event(event, &ledger).await {
-                            Ok(transaction_id) => {
-                                // mark outbox processed and complete transaction
-                                if let Err(err) = db.complete_transaction(transaction_id).await {
-                                    error!("Error completing transaction: {:?}", err);
-                                }
-                            }
-                            Err(e) => {
-                                error!("Error processing event: {:?}", e);
-                            }
+// Settles outbox commands as soon as they are queued: the listener wakes the
+// processor on every notification, and a sweep every `outbox.poll_interval_secs`
+// picks up retries and whatever was queued while the listener was down.
+// Once `shutdown` is cancelled the processor finishes the command at hand,
+// hands the rest of its batch back and stops, `tracker` waits for that.
+pub fn spawn_outbox_processor(
+    state: SharedState,
+    tracker: &TaskTracker,
+    shutdown: CancellationToken,
+) {
+    let wake = Arc::new(Notify::new());
+    let worker_id = Uuid::new_v4().to_string();
+    info!("Outbox worker: {}", worker_id);
+    task::spawn(listen_outbox(wake.clone()));
+    tracker.spawn(async move {
+        let interval = Duration::from_secs(SETTINGS.outbox.poll_interval_secs);
+        while !shutdown.is_cancelled() {
+            // A panic only loses this run, like a failed cron job. A full batch
+            // means more rows are due.
+            let run = process_outbox(state.clone(), worker_id.clone(), shutdown.clone());
+            match task::spawn(run).await {
+                Ok(processed) if processed as i64 == OUTBOX_BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => error!("Outbox run failed: {:?}", e),
+            }
+            tokio::select! {
+                _ = wake.notified() => {}
+                _ = tokio::time::sleep(interval) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+
+        // Other instances take over the claimed commands without waiting for
+        // the lease to expire.
+        if let Err(e) = state.database.release_outbox_claims(worker_id).await {
+            error!("Error releasing outbox claims: {:?}", e);
+        }
+        info!("Outbox processor stopped");
+    });
+}
+
+async fn listen_outbox(wake: Arc<Notify>) {
+    loop {
+        match PgListener::connect(&SETTINGS.database.connection_string()).await {
+            Ok(mut listener) => match listener.listen(OUTBOX_CHANNEL).await {
+                Ok(()) => loop {
+                    match listener.try_recv().await {
+                        Ok(Some(_)) => wake.notify_one(),
+                        // The connection was lost and is reopened by the next
+                        // call, sweep for what was missed meanwhile.
+                        Ok(None) => {
+                            warn!("Outbox listener reconnecting");
+                            wake.notify_one();
+                        }
+                        Err(e) => {
+                            error!("Outbox listener failed: {:?}", e);
+                            break;
                         }
                     }
+                },
+                Err(e) => error!("Error listening to outbox: {:?}", e),
+            },
+            Err(e) => error!("Error connecting outbox listener: {:?}", e),
+        }
+        tokio::time::sleep(LISTENER_RETRY).await;
+    }
+}
 
-                    // release lock
-                    release_lock(&cache, LOCK_KEY, &identifier).await;
+// Applies the due outbox commands to the ledger, returns how many were claimed.
+// Claims are leased per instance, so several instances work through the outbox
+// side by side.
+async fn process_outbox(
+    state: SharedState,
+    worker_id: String,
+    shutdown: CancellationToken,
+) -> usize {
+    let db = &state.database;
+    let ledger = state.ledger.clone().unwrap();
+    let lease_secs = SETTINGS.outbox.lease_secs;
+    match db
+        .claim_outbox(worker_id.clone(), OUTBOX_BATCH_SIZE, lease_secs)
+        .await
+    {
+        Ok(events) => {
+            // process events
+            let claimed = events.len();
+            METRICS.outbox_batch_size.observe(claimed as f64);
+            let _timer = METRICS.outbox_batch_duration.start_timer();
+            for event in events {
+                if shutdown.is_cancelled() {
+                    break;
                 }
-                Err(e) => {
-                    error!("Error fetching events: {:?}", e);
+                info!("Processing event: {:?}", event);
+                let result = match process_event(&event, &ledger).await {
+                    // mark outbox processed and complete transaction
+                    Ok(()) => {
+                        METRICS
+                            .outbox_commands
+                            .with_label_values(&["processed"])
+                            .inc();
+                        db.complete_transaction(event.transaction_id, worker_id.clone())
+                            .await
+                    }
+                    Err(e) => {
+                        error!("Error processing event {}: {:?}", event.id, e);
+                        METRICS.outbox_commands.with_label_values(&["failed"]).inc();
+                        fail_event(&state, &event, &worker_id, e).await
+                    }
+                };
+                if let Err(err) = result {
+                    error!("Error updating outbox {}: {:?}", event.id, err);
                 }
             }
-        })
-    })
+            claimed
+        }
+        Err(e) => {
+            error!("Error claiming events: {:?}", e);
+            0
+        }
+    }
 }
 
-async fn process_event(event: Outbox, ledger: &LedgerLoaderSaver) -> Result<Uuid, anyhow::Error> {
-    let key = match event.event_type.as_str() {
-        "LedgerCommand::Credit" => "Credit",
-        "LedgerCommand::Debit" => "DebitRelease",
-        _ => panic!("Unknown event type: {}", event.event_type),
-    };
-    let payload = event.payload;
-
-    info!("payload: {}", payload[key]);
-    let id_str = payload[key]["id"].as_str().context("Missing 'id' field")?;
-    let id = Uuid::parse_str(id_str).context("Invalid 'id' format")?;
-
-    let account_id_str = payload[key]["account_id"]
-        .as_str()
-        .context("Missing 'account_id' field")?;
-    let account_id = Uuid::parse_str(account_id_str).context("Invalid 'account_id' format")?;
-
-    let transaction_id_str = payload[key]["transaction_id"]
-        .as_str()
-        .context("Missing 'transaction_id' field")?;
-    let transaction_id =
-        Uuid::parse_str(transaction_id_str).context("Invalid 'transaction_id' format")?;
-
-    let amount_str = payload[key]["amount"]["amount"]
-        .as_str()
-        .context("Missing 'amount' field")?;
-    let amount = Decimal::from_str(amount_str).context("Invalid 'amount' format")?;
-
-    let currency_str = payload[key]["amount"]["currency"]
-        .as_str()
-        .context("Missing 'currency' field")?;
-    let currency = Currency::from(currency_str.to_string());
-
-    let amount = Money::new(amount, currency);
+// Registers the custom assets of every tenant, including those created
+// through another server since the last run.
+pub async fn load_assets<C: DatabaseClient + Send + Sync>(
+    database: &Adapter<C>,
+) -> Result<(), sqlx::Error> {
+    for asset in database.get_assets().await? {
+        asset.register();
+    }
+    Ok(())
+}
 
-    let command = match event.event_type.as_str() {
-        "LedgerCommand::Credit" => LedgerCommand::Credit {
-            id,
-            account_id,
-            transaction_id,
-            amount,
-        },
-        "LedgerCommand::Debit" => LedgerCommand::DebitRelease {
-            id,
-            account_id,
-            transaction_id,
-            amount,
-        },
-        _ => panic!("Unknown event type: {}", event.event_type),
-    };
+pub async fn create_asset_job(state: SharedState) -> Result<Job, JobSchedulerError> {
+    Job::new_async("0/30 * * * * *", move |_uuid, _l| {
+        let state = state.clone();
+        Box::pin(async move {
+            // The assets known so far stay registered.
+            if let Err(e) = load_assets(&state.database).await {
+                error!("Error loading assets: {:?}", e);
+            }
+        })
+    })
+}
 
-    // Note ledger changes and update balance
-    ledger
-        .cqrs
-        .execute(id_str, command)
-        .await
-        .map_err(|e| anyhow!("Failed to write ledger: {}", e))?;
+const WEBHOOK_BATCH_SIZE: i64 = 100;
 
-    Ok(transaction_id)
+// Deliveries are leased per instance like outbox commands, so several
+// instances deliver side by side. Runs are tracked so a shutdown lets the
+// deliveries in progress finish.
+pub async fn create_webhook_job(
+    state: SharedState,
+    tracker: TaskTracker,
+) -> Result<Job, JobSchedulerError> {
+    let client = http_client();
+    let worker_id = Uuid::new_v4().to_string();
+    info!("Webhook worker: {}", worker_id);
+    Job::new_async("1/5 * * * * *", move |_uuid, _l| {
+        let db = state.database.clone();
+        let client = client.clone();
+        let worker_id = worker_id.clone();
+        Box::pin(tracker.track_future(async move {
+            let lease_secs = SETTINGS.webhook.lease_secs;
+            match db
+                .claim_webhook_deliveries(worker_id.clone(), WEBHOOK_BATCH_SIZE, lease_secs)
+                .await
+            {
+                Ok(deliveries) => {
+                    for delivery in deliveries {
+                        let result = match deliver(&client, &delivery).await {
+                            Ok(()) => {
+                                db.mark_webhook_delivered(delivery.id, worker_id.clone())
+                                    .await
+                            }
+                            Err(e) => {
+                                let retry_at =
+                                    next_attempt_at(delivery.attempts + 1, Utc::now().naive_utc());
+                                if retry_at.is_none() {
+                                    error!("Webhook delivery dead-lettered: {}", delivery.id);
+                                }
+                                db.mark_webhook_failed(
+                                    delivery.id,
+                                    worker_id.clone(),
+                                    e.to_string(),
+                                    retry_at,
+                                )
+                                .await
+                            }
+                        };
+                        if let Err(err) = result {
+                            error!("Error updating webhook delivery: {:?}", err);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Error claiming webhook deliveries: {:?}", e);
+                }
+            }
+        }))
+    })
 }
@@ -1,27 +1,32 @@
-use auth::jwt::{generate_jwt, generate_secret_key};
-use auth::middleware::authorize;
-use axum::Router;
-use axum::{middleware, routing::get, routing::post};
+use auth::jwt::{generate_client_credentials, generate_jwt, generate_secret_key};
+use auth::keys::{retire_signing_key, rotate_signing_key, RS256};
+use auth::scope::parse_scopes;
 use clap::Parser;
 use clap_derive::Parser;
+use configs::settings::SETTINGS;
 use event_sourcing::command::BankAccountCommand;
-use job::create_ledger_job;
-use route::{
-    bank_account_command_handler, bank_account_query_handler, house_account_create_handler,
-    house_account_query_handler, ledger_query_handler, transaction_query_handler,
-    user_query_handler,
-};
+use event_sourcing::error::DomainError;
+use event_sourcing::metadata::with_metadata;
+use event_sourcing::replay::{replay, ReplayOptions};
+use job::{create_asset_job, create_webhook_job, load_assets, spawn_outbox_processor};
+use metrics::{timed_execute, AGGREGATE_BANK_ACCOUNT, METRICS};
+use postgres_es::default_postgress_pool;
+use repository::adapter::Adapter;
+use route::router;
 use sqlx::PgPool;
-use state::{new_application_state, ApplicationState};
+use state::{new_application_state, ApplicationState, CommandMessage};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc;
 use tokio::task;
+use tokio::time::{sleep_until, timeout_at, Instant};
 use tokio_cron_scheduler::JobScheduler;
-use tower_http::add_extension::AddExtensionLayer;
-use tower_http::compression::CompressionLayer;
-use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
 
 mod auth;
 mod command;
@@ -29,30 +34,105 @@ mod common;
 mod configs;
 mod domain;
 mod event_sourcing;
+mod health;
 mod house_account;
 mod job;
+mod metrics;
+mod outbox;
+mod payment;
+mod rate_limit;
 mod repository;
 mod route;
 mod service;
+mod shutdown;
 mod state;
+mod stream;
+mod webhook;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Mode to generate secret key or JWT
+    /// Mode to generate secret key, JWT or client secret, rotate or retire signing keys,
+    /// run the server or replay views
     #[arg(short, long)]
     mode: String,
 
     /// Service ID for JWT token
     #[arg(short, long)]
     service: Option<String>,
+
+    /// Tenant to issue a new client secret for
+    #[arg(long)]
+    tenant_id: Option<i32>,
+
+    /// Scopes granted to the JWT token, the default set when omitted
+    #[arg(long, value_delimiter = ',')]
+    scope: Vec<String>,
+
+    /// Algorithm of the signing key added by rotate_key, RS256 or EdDSA
+    #[arg(long, default_value = RS256)]
+    algorithm: String,
+
+    /// Signing key to retire
+    #[arg(long)]
+    kid: Option<String>,
+
+    /// Views to rebuild in replay mode, all of them when omitted
+    #[arg(long, value_delimiter = ',')]
+    projection: Vec<String>,
+
+    /// Only replay the events of these aggregates
+    #[arg(long, value_delimiter = ',')]
+    aggregate_id: Vec<String>,
+
+    /// Number of aggregates replayed per batch
+    #[arg(long, default_value_t = 500)]
+    batch_size: i64,
+
+    /// Build into a shadow table and swap it in when done
+    #[arg(long)]
+    shadow: bool,
 }
 
 // Wrap ApplicationState in Arc for thread-safe sharing
 type SharedState = Arc<ApplicationState<PgPool>>;
 
-async fn process_commands(state: SharedState, mut rx: mpsc::UnboundedReceiver<BankAccountCommand>) {
-    while let Some(command) = rx.recv().await {
+// Commands still queued this long before the shutdown deadline are refused, so
+// their requests are answered before the process exits.
+const COMMAND_REJECT_MARGIN: Duration = Duration::from_secs(5);
+
+// Applies the queued commands until `drain` is cancelled, then closes the queue
+// and applies what is still in it before returning. Once `reject` is cancelled
+// the rest is answered with `DomainError::ShuttingDown` instead.
+async fn process_commands(
+    state: SharedState,
+    mut rx: mpsc::UnboundedReceiver<CommandMessage>,
+    drain: CancellationToken,
+    reject: CancellationToken,
+) {
+    loop {
+        let message = tokio::select! {
+            message = rx.recv() => message,
+            _ = drain.cancelled(), if !drain.is_cancelled() => {
+                rx.close();
+                info!("Draining {} queued commands", rx.len());
+                continue;
+            }
+        };
+        let Some((command, metadata, reply)) = message else {
+            break;
+        };
+        state.health.command_taken();
+        if reject.is_cancelled() {
+            warn!("Command refused on shutdown: {:?}", command);
+            let err = DomainError::ShuttingDown;
+            METRICS
+                .commands
+                .with_label_values(&[command.name(), err.code().as_str()])
+                .inc();
+            let _ = reply.send(Err(err));
+            continue;
+        }
         info!("Processing command: {:?}", command);
         let id = match &command {
             BankAccountCommand::OpenAccount { id, .. } => id,
@@ -62,7 +142,26 @@ async fn process_commands(state: SharedState, mut rx: mpsc::UnboundedReceiver<Ba
         }
         .to_string();
         if let Some(bank_account) = &state.bank_account {
-            match bank_account.cqrs.execute(&id, command).await {
+            // Ledger commands executed by the aggregate services carry the same
+            // metadata as the bank account events.
+            let name = command.name();
+            let result = with_metadata(
+                metadata.clone(),
+                timed_execute(
+                    AGGREGATE_BANK_ACCOUNT,
+                    bank_account
+                        .cqrs
+                        .execute_with_metadata(&id, command, metadata),
+                ),
+            )
+            .await
+            .map_err(DomainError::from);
+            let outcome = match &result {
+                Ok(_) => "ok",
+                Err(e) => e.code().as_str(),
+            };
+            METRICS.commands.with_label_values(&[name, outcome]).inc();
+            match &result {
                 Ok(_) => {
                     info!("Command processed successfully: {}", id);
                 }
@@ -70,8 +169,11 @@ async fn process_commands(state: SharedState, mut rx: mpsc::UnboundedReceiver<Ba
                     error!("Error processing command: {:?}", e);
                 }
             }
+            // The request may have gone away in the meantime.
+            let _ = reply.send(result);
         }
     }
+    info!("Command queue drained");
 }
 
 #[tokio::main]
@@ -87,57 +189,152 @@ async fn main() {
         }
         "jwt" => {
             if let Some(s) = args.service {
-                if let Ok(secret_key) = std::env::var("JWT_SECRET") {
-                    let jwt = generate_jwt(s.as_str(), &secret_key).await.unwrap();
-                    info!("Generated: {}", jwt);
+                let scopes = match parse_scopes(&args.scope) {
+                    Ok(scopes) => scopes,
+                    Err(e) => {
+                        error!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let credentials = generate_jwt(s.as_str(), scopes).await.unwrap();
+                info!("Generated: {}", credentials.jwt);
+                info!(
+                    "Client ID: {}, client secret: {}",
+                    credentials.tenant_id, credentials.client_secret
+                );
+            }
+        }
+        "client_secret" => {
+            if let Some(tenant_id) = args.tenant_id {
+                let client_secret = generate_client_credentials(tenant_id).await.unwrap();
+                info!("Client ID: {}, client secret: {}", tenant_id, client_secret);
+            }
+        }
+        "rotate_key" => match rotate_signing_key(&args.algorithm).await {
+            Ok(kid) => info!("Signing key: {}", kid),
+            Err(e) => {
+                error!("Key rotation failed: {:?}", e);
+                std::process::exit(1);
+            }
+        },
+        "retire_key" => {
+            if let Some(kid) = args.kid {
+                if let Err(e) = retire_signing_key(&kid).await {
+                    error!("Key retirement failed: {:?}", e);
+                    std::process::exit(1);
                 }
+                info!("Retired signing key: {}", kid);
+            }
+        }
+        "replay" => {
+            let pool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
+            // Events in custom assets only parse once they are known.
+            if let Err(e) = load_assets(&Adapter::new(pool.clone())).await {
+                error!("Loading assets failed: {:?}", e);
+                std::process::exit(1);
+            }
+            let options = ReplayOptions {
+                aggregate_ids: args.aggregate_id,
+                batch_size: args.batch_size,
+                shadow: args.shadow,
+            };
+            if let Err(e) = replay(&pool, &args.projection, &options).await {
+                error!("Replay failed: {:?}", e);
+                std::process::exit(1);
             }
         }
         "server" => {
-            let (tx, rx): (
-                UnboundedSender<BankAccountCommand>,
-                UnboundedReceiver<BankAccountCommand>,
-            ) = mpsc::unbounded_channel();
+            // Fail on a misconfigured proxy list before taking requests.
+            lazy_static::initialize(&command::TRUSTED_PROXIES);
+            let (tx, rx) = mpsc::unbounded_channel::<CommandMessage>();
             let state = new_application_state(tx).await;
+            // Events and requests in custom assets only parse once they are known.
+            if let Err(e) = load_assets(&state.database).await {
+                error!("Loading assets failed: {:?}", e);
+                std::process::exit(1);
+            }
+
+            // Cancelled on SIGTERM, the work in `tracker` is awaited before exiting.
+            let shutdown = CancellationToken::new();
+            let tracker = TaskTracker::new();
 
             // Clone Arc for the background task
             let command_state = state.clone();
             // Spawn a background task to process commands
-            task::spawn(async move {
-                process_commands(command_state, rx).await;
-            });
+            let drain = CancellationToken::new();
+            let reject = CancellationToken::new();
+            tracker.spawn(process_commands(
+                command_state,
+                rx,
+                drain.clone(),
+                reject.clone(),
+            ));
+
+            // Update ledger from outbox events as they are queued
+            spawn_outbox_processor(state.clone(), &tracker, shutdown.clone());
 
-            // Add cron job for update ledger from outbox events
-            let sched = JobScheduler::new().await.unwrap();
-            let job = create_ledger_job(state.clone()).await.unwrap();
+            let mut sched = JobScheduler::new().await.unwrap();
+            // Add cron job for sending queued webhook deliveries
+            let job = create_webhook_job(state.clone(), tracker.clone())
+                .await
+                .unwrap();
+            sched.add(job).await.unwrap();
+            // Add cron job for picking up assets registered on other servers
+            let job = create_asset_job(state.clone()).await.unwrap();
             sched.add(job).await.unwrap();
             sched.start().await.unwrap();
+            state.health.set_scheduler_running(true);
 
-            // Configure the Axum routes and services.
-            // For this example a single logical endpoint is used and the HTTP method
-            // distinguishes whether the call is a command or a query.
-            let comression_layer: CompressionLayer = CompressionLayer::new();
-            let router = Router::new()
-                .route("/v1/bank_account/:id", get(bank_account_query_handler))
-                .route("/v1/bank_account", post(bank_account_command_handler))
-                .route("/v1/ledger/:id", get(ledger_query_handler))
-                .route(
-                    "/v1/house_account",
-                    get(house_account_query_handler).post(house_account_create_handler),
-                )
-                .route("/v1/user/:id", get(user_query_handler))
-                .route("/v1/transaction", get(transaction_query_handler))
-                .layer(middleware::from_fn(authorize::<PgPool>))
-                .layer(AddExtensionLayer::new(state.clone()))
-                .layer(comression_layer)
-                .layer(TraceLayer::new_for_http())
-                .with_state(state);
+            let health = state.health.clone();
+            let router = router(state);
             // Start the Axum server.
             let listener = TcpListener::bind("0.0.0.0:3030").await.unwrap();
             info!("Server running on: {}", listener.local_addr().unwrap());
-            axum::serve(listener, router.into_make_service())
-                .await
-                .unwrap();
+            let server = axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            let mut server = task::spawn(server.into_future());
+
+            let stopped = tokio::select! {
+                _ = shutdown::signal() => {
+                    info!("Shutting down");
+                    false
+                }
+                result = &mut server => {
+                    error!("Server stopped: {:?}", result);
+                    true
+                }
+            };
+            health.set_shutting_down();
+            shutdown.cancel();
+            let deadline = Instant::now() + Duration::from_secs(SETTINGS.shutdown.deadline_secs);
+            let reject_at = deadline
+                .checked_sub(COMMAND_REJECT_MARGIN)
+                .unwrap_or(deadline);
+            task::spawn(async move {
+                sleep_until(reject_at).await;
+                reject.cancel();
+            });
+
+            // No new job runs, the running ones are tracked.
+            health.set_scheduler_running(false);
+            if let Err(e) = sched.shutdown().await {
+                error!("Error stopping jobs: {:?}", e);
+            }
+            // Requests still open have their commands applied before the queue
+            // is drained.
+            if !stopped && timeout_at(deadline, &mut server).await.is_err() {
+                warn!("Requests still open at the shutdown deadline");
+            }
+            drain.cancel();
+            tracker.close();
+            if timeout_at(deadline, tracker.wait()).await.is_err() {
+                error!("Shutdown deadline passed, running jobs were abandoned");
+                std::process::exit(1);
+            }
+            info!("Shutdown complete");
         }
         _ => {}
     }
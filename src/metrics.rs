@@ -0,0 +1,229 @@
+use std::future::Future;
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use rust_decimal::prelude::ToPrimitive;
+
+use crate::domain::diagnostics::{LedgerTotal, OutboxBacklog};
+
+pub const AGGREGATE_BANK_ACCOUNT: &str = "bank_account";
+pub const AGGREGATE_LEDGER: &str = "ledger";
+
+// Outbox batches hold at most 100 commands.
+const BATCH_SIZE_BUCKETS: [f64; 8] = [0.0, 1.0, 5.0, 10.0, 25.0, 50.0, 75.0, 100.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+pub struct Metrics {
+    registry: Registry,
+    pub commands: IntCounterVec,
+    pub execute_duration: HistogramVec,
+    pub outbox_batch_size: Histogram,
+    pub outbox_batch_duration: Histogram,
+    pub outbox_commands: IntCounterVec,
+    outbox_pending: IntGauge,
+    outbox_dead: IntGauge,
+    outbox_lag: IntGauge,
+    pub http_requests: IntCounterVec,
+    pub http_duration: HistogramVec,
+    ledger_balance: GaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let metrics = Metrics {
+            registry: Registry::new_custom(Some("bankie".to_string()), None).unwrap(),
+            commands: IntCounterVec::new(
+                Opts::new(
+                    "commands_total",
+                    "Bank account commands executed, by command and outcome",
+                ),
+                &["command", "outcome"],
+            )
+            .unwrap(),
+            execute_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "cqrs_execute_duration_seconds",
+                    "Time to execute a command on an aggregate",
+                ),
+                &["aggregate"],
+            )
+            .unwrap(),
+            outbox_batch_size: Histogram::with_opts(
+                HistogramOpts::new("outbox_batch_size", "Outbox commands claimed per batch")
+                    .buckets(BATCH_SIZE_BUCKETS.to_vec()),
+            )
+            .unwrap(),
+            outbox_batch_duration: Histogram::with_opts(HistogramOpts::new(
+                "outbox_batch_duration_seconds",
+                "Time to apply a batch of outbox commands",
+            ))
+            .unwrap(),
+            outbox_commands: IntCounterVec::new(
+                Opts::new(
+                    "outbox_commands_total",
+                    "Outbox commands applied to the ledger, by outcome",
+                ),
+                &["outcome"],
+            )
+            .unwrap(),
+            outbox_pending: IntGauge::new(
+                "outbox_pending",
+                "Outbox commands waiting to be applied",
+            )
+            .unwrap(),
+            outbox_dead: IntGauge::new("outbox_dead", "Dead-lettered outbox commands").unwrap(),
+            outbox_lag: IntGauge::new(
+                "outbox_lag_seconds",
+                "Age of the oldest pending outbox command",
+            )
+            .unwrap(),
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests, by route and status"),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            http_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Time to answer a request"),
+                &["method", "route"],
+            )
+            .unwrap(),
+            ledger_balance: GaugeVec::new(
+                Opts::new(
+                    "ledger_balance",
+                    "Balances held in the ledgers of every tenant, by currency",
+                ),
+                &["currency", "balance"],
+            )
+            .unwrap(),
+        };
+        metrics.register();
+        metrics
+    }
+
+    fn register(&self) {
+        let collectors: [Box<dyn prometheus::core::Collector>; 11] = [
+            Box::new(self.commands.clone()),
+            Box::new(self.execute_duration.clone()),
+            Box::new(self.outbox_batch_size.clone()),
+            Box::new(self.outbox_batch_duration.clone()),
+            Box::new(self.outbox_commands.clone()),
+            Box::new(self.outbox_pending.clone()),
+            Box::new(self.outbox_dead.clone()),
+            Box::new(self.outbox_lag.clone()),
+            Box::new(self.http_requests.clone()),
+            Box::new(self.http_duration.clone()),
+            Box::new(self.ledger_balance.clone()),
+        ];
+        for collector in collectors {
+            self.registry.register(collector).unwrap();
+        }
+    }
+
+    // Backlog and balances are read from the database on every scrape.
+    pub fn set_outbox_backlog(&self, backlog: &OutboxBacklog) {
+        self.outbox_pending.set(backlog.pending);
+        self.outbox_dead.set(backlog.dead);
+        self.outbox_lag
+            .set(backlog.oldest_pending_secs.unwrap_or(0));
+    }
+
+    pub fn set_ledger_totals(&self, totals: &[LedgerTotal]) {
+        // Currencies whose ledgers are gone should not linger.
+        self.ledger_balance.reset();
+        for total in totals {
+            for (balance, amount) in [
+                ("available", total.available),
+                ("pending", total.pending),
+                ("current", total.current),
+            ] {
+                self.ledger_balance
+                    .with_label_values(&[&total.currency, balance])
+                    .set(amount.to_f64().unwrap_or(f64::NAN));
+            }
+        }
+    }
+
+    // Text exposition format of every metric.
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+// Runs `execute`, a `cqrs.execute` on `aggregate`, and records its latency.
+pub async fn timed_execute<F: Future>(aggregate: &str, execute: F) -> F::Output {
+    let _timer = METRICS
+        .execute_duration
+        .with_label_values(&[aggregate])
+        .start_timer();
+    execute.await
+}
+
+// Counts requests by their route template rather than the path, so ids do
+// not blow up the number of series.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    METRICS
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    METRICS
+        .http_duration
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_ledger_totals() {
+        METRICS.set_ledger_totals(&[LedgerTotal {
+            currency: "TWD".to_string(),
+            available: dec!(100.50),
+            pending: dec!(20),
+            current: dec!(120.50),
+        }]);
+
+        let text = METRICS.encode();
+
+        assert!(text.contains(r#"bankie_ledger_balance{balance="available",currency="TWD"} 100.5"#));
+        assert!(text.contains(r#"bankie_ledger_balance{balance="current",currency="TWD"} 120.5"#));
+    }
+
+    #[tokio::test]
+    async fn test_timed_execute() {
+        let result = timed_execute(AGGREGATE_LEDGER, async { 42 }).await;
+
+        assert_eq!(result, 42);
+        let count = METRICS
+            .execute_duration
+            .with_label_values(&[AGGREGATE_LEDGER])
+            .get_sample_count();
+        assert!(count >= 1);
+    }
+}
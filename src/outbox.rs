@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use chrono::{NaiveDateTime, Utc};
+use cqrs_es::persist::ViewRepository;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        backoff,
+        money::{Currency, Money},
+    },
+    configs::settings::SETTINGS,
+    domain::finance::{Outbox, OutboxEntry, OUTBOX_CREDIT, OUTBOX_DEBIT},
+    event_sourcing::{command::LedgerCommand, error::DomainError, metadata::Metadata},
+    metrics::{timed_execute, AGGREGATE_LEDGER},
+    repository::adapter::DatabaseClient,
+    state::{ApplicationState, LedgerLoaderSaver},
+};
+
+/// Time of the next attempt after `attempts` failed ones, or `None` once the
+/// command should be dead-lettered.
+pub fn next_attempt_at(attempts: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let settings = &SETTINGS.outbox;
+    backoff::next_attempt_at(
+        attempts,
+        settings.max_attempts,
+        settings.backoff_base_secs,
+        now,
+    )
+}
+
+/// The ledger command queued in an outbox row, withdrawals settle the hold
+/// placed when they were accepted.
+pub fn parse_command(event_type: &str, payload: &Value) -> Result<LedgerCommand, anyhow::Error> {
+    let key = match event_type {
+        OUTBOX_CREDIT => "Credit",
+        OUTBOX_DEBIT => "DebitRelease",
+        _ => return Err(anyhow!("Unknown event type: {}", event_type)),
+    };
+
+    info!("payload: {}", payload[key]);
+    let id_str = payload[key]["id"].as_str().context("Missing 'id' field")?;
+    let id = Uuid::parse_str(id_str).context("Invalid 'id' format")?;
+
+    let account_id_str = payload[key]["account_id"]
+        .as_str()
+        .context("Missing 'account_id' field")?;
+    let account_id = Uuid::parse_str(account_id_str).context("Invalid 'account_id' format")?;
+
+    let transaction_id_str = payload[key]["transaction_id"]
+        .as_str()
+        .context("Missing 'transaction_id' field")?;
+    let transaction_id =
+        Uuid::parse_str(transaction_id_str).context("Invalid 'transaction_id' format")?;
+
+    let amount_str = payload[key]["amount"]["amount"]
+        .as_str()
+        .context("Missing 'amount' field")?;
+    let amount = Decimal::from_str(amount_str).context("Invalid 'amount' format")?;
+
+    let currency_str = payload[key]["amount"]["currency"]
+        .as_str()
+        .context("Missing 'currency' field")?;
+    let currency = Currency::from_str(currency_str).context("Invalid 'currency' format")?;
+
+    let amount = Money::new(amount, currency);
+
+    Ok(match event_type {
+        OUTBOX_CREDIT => LedgerCommand::Credit {
+            id,
+            account_id,
+            transaction_id,
+            amount,
+        },
+        _ => LedgerCommand::DebitRelease {
+            id,
+            account_id,
+            transaction_id,
+            amount,
+        },
+    })
+}
+
+// Note ledger changes and update balance
+pub async fn process_event(
+    event: &Outbox,
+    ledger: &LedgerLoaderSaver,
+) -> Result<(), anyhow::Error> {
+    let command = parse_command(&event.event_type, &event.payload)?;
+    // Rows queued before metadata was recorded hold an empty object.
+    let metadata: Metadata = serde_json::from_value(event.metadata.clone()).unwrap_or_default();
+
+    let id = command.ledger_id().to_string();
+    let execute = ledger.cqrs.execute_with_metadata(&id, command, metadata);
+    timed_execute(AGGREGATE_LEDGER, execute)
+        .await
+        .map_err(|e| anyhow!("Failed to write ledger: {}", e))
+}
+
+/// Records a failed attempt of `event`. After `outbox.max_attempts` the
+/// command is dead-lettered: a withdrawal gives its hold back and the
+/// transaction is failed. Fails with `RowNotFound` once `worker_id` lost the
+/// claim of `event`.
+pub async fn fail_event<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    event: &Outbox,
+    worker_id: &str,
+    err: anyhow::Error,
+) -> Result<(), sqlx::Error> {
+    let db = &state.database;
+    let error = err.to_string();
+    if let Some(retry_at) = next_attempt_at(event.attempts + 1, Utc::now().naive_utc()) {
+        return db
+            .mark_outbox_failed(event.id, worker_id.to_string(), error, retry_at)
+            .await;
+    }
+
+    error!("Outbox command dead-lettered: {}: {}", event.id, error);
+    let hold_released = match event.event_type.as_str() {
+        OUTBOX_DEBIT => {
+            let metadata = serde_json::from_value(event.metadata.clone()).unwrap_or_default();
+            match move_hold(state, &event.event_type, &event.payload, false, metadata).await {
+                Ok(()) => true,
+                Err(err) => {
+                    error!(
+                        "Hold of transaction {} not released: {}",
+                        event.transaction_id, err
+                    );
+                    false
+                }
+            }
+        }
+        _ => false,
+    };
+    db.fail_transaction(
+        event.transaction_id,
+        worker_id.to_string(),
+        error,
+        hold_released,
+    )
+    .await
+}
+
+/// Queues a dead-lettered command again. A withdrawal that gave its hold back
+/// places it again first, provided the funds are still available.
+pub async fn retry_entry<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    tenant_id: i32,
+    entry: &OutboxEntry,
+    metadata: Metadata,
+) -> Result<(), DomainError> {
+    if entry.hold_released {
+        move_hold(
+            state,
+            &entry.event_type,
+            &entry.payload,
+            true,
+            metadata.clone(),
+        )
+        .await?;
+    }
+    if let Err(err) = state.database.retry_outbox(tenant_id, entry.id).await {
+        // Another request retried or skipped the entry in the meantime.
+        if entry.hold_released {
+            move_hold(state, &entry.event_type, &entry.payload, false, metadata).await?;
+        }
+        return Err(match err {
+            sqlx::Error::RowNotFound => DomainError::ConcurrentModification,
+            err => DomainError::Internal(err.to_string()),
+        });
+    }
+    Ok(())
+}
+
+// Places (`hold`) or gives back the hold of the withdrawal settled by a
+// `LedgerCommand::Debit` outbox row.
+async fn move_hold<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    event_type: &str,
+    payload: &Value,
+    hold: bool,
+    metadata: Metadata,
+) -> Result<(), DomainError> {
+    let ledger = state
+        .ledger
+        .as_ref()
+        .ok_or_else(|| DomainError::Internal("Ledger not configured".to_string()))?;
+    let LedgerCommand::DebitRelease {
+        id,
+        account_id,
+        transaction_id,
+        amount,
+    } = parse_command(event_type, payload)?
+    else {
+        return Ok(());
+    };
+
+    let command = if hold {
+        let available = ledger
+            .query
+            .load(&id.to_string())
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .ok_or(DomainError::AccountNotFound)?
+            .available;
+        if available.try_sub(amount)?.amount.is_sign_negative() {
+            return Err(DomainError::InsufficientFunds);
+        }
+        LedgerCommand::DebitHold {
+            id,
+            account_id,
+            transaction_id,
+            amount,
+        }
+    } else {
+        LedgerCommand::DebitCancel {
+            id,
+            account_id,
+            transaction_id,
+            amount,
+        }
+    };
+    let id = id.to_string();
+    let execute = ledger.cqrs.execute_with_metadata(&id, command, metadata);
+    timed_execute(AGGREGATE_LEDGER, execute)
+        .await
+        .map_err(DomainError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn payload(key: &str) -> Value {
+        json!({
+            key: {
+                "id": "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f",
+                "account_id": "6f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f",
+                "transaction_id": "7f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f",
+                "amount": {"amount": "10.50", "currency": "USD"},
+            }
+        })
+    }
+
+    #[test]
+    fn test_parse_command() {
+        match parse_command(OUTBOX_DEBIT, &payload("DebitRelease")).unwrap() {
+            LedgerCommand::DebitRelease { id, amount, .. } => {
+                assert_eq!(id.to_string(), "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f");
+                assert_eq!(amount.amount, Decimal::new(1050, 2));
+            }
+            command => panic!("Unexpected command: {:?}", command),
+        }
+        assert!(matches!(
+            parse_command(OUTBOX_CREDIT, &payload("Credit")).unwrap(),
+            LedgerCommand::Credit { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_command_rejects_poisoned_rows() {
+        let err = parse_command("LedgerCommand::Unknown", &payload("Credit")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown event type: LedgerCommand::Unknown"
+        );
+
+        let err = parse_command(OUTBOX_CREDIT, &payload("DebitRelease")).unwrap_err();
+        assert_eq!(err.to_string(), "Missing 'id' field");
+    }
+
+    #[test]
+    fn test_next_attempt_at() {
+        let now = Utc::now().naive_utc();
+        let max_attempts = SETTINGS.outbox.max_attempts;
+
+        assert_eq!(
+            next_attempt_at(1, now),
+            Some(now + chrono::Duration::seconds(SETTINGS.outbox.backoff_base_secs))
+        );
+        assert!(next_attempt_at(max_attempts - 1, now).is_some());
+        assert!(next_attempt_at(max_attempts, now).is_none());
+    }
+}
@@ -0,0 +1,526 @@
+use anyhow::{anyhow, Context};
+use chrono::{Duration, Utc};
+use cqrs_es::persist::ViewRepository;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    common::{money::Currency, snowflake::generate_transaction_reference},
+    configs::settings::SETTINGS,
+    domain::{
+        finance::{JournalEntry, JournalLine, Transaction, TRANS_DEPOSIT},
+        payment::{
+            AchFile, AchFileEntry, PayoutAccountKind, PayoutDestination, ACH_ENTRY_RETURNED,
+            ACH_ENTRY_SENT, ACH_FILE_MODIFIER_KEY,
+        },
+    },
+    event_sourcing::metadata::Metadata,
+    repository::adapter::DatabaseClient,
+    state::ApplicationState,
+};
+
+use super::nacha::{
+    parse_returns, BatchHeader, EntryDetail, FileHeader, NachaBatch, NachaFile,
+    TRANSACTION_CODE_CHECKING_CREDIT, TRANSACTION_CODE_SAVINGS_CREDIT,
+};
+
+const FILE_ID_MODIFIERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const ENTRY_DESCRIPTION: &str = "WITHDRAWAL";
+
+/// Batch every completed USD withdrawal of the tenant that carries a payout
+/// destination and has not been sent yet into a new NACHA file. Returns `None`
+/// when there is nothing to pay out.
+pub async fn generate_ach_file<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    tenant_id: i32,
+) -> Result<Option<AchFile>, anyhow::Error> {
+    let db = state.database.clone();
+    let settings = &SETTINGS.ach;
+
+    let withdrawals: Vec<(Transaction, PayoutDestination)> = db
+        .get_pending_ach_withdrawals(tenant_id)
+        .await?
+        .into_iter()
+        .filter_map(|t| {
+            match serde_json::from_value::<PayoutDestination>(t.metadata["destination"].clone()) {
+                Ok(destination) if destination.is_valid() => Some((t, destination)),
+                _ => {
+                    error!("Invalid payout destination on transaction: {}", t.id);
+                    None
+                }
+            }
+        })
+        .collect();
+    if withdrawals.is_empty() {
+        return Ok(None);
+    }
+
+    let now = Utc::now().naive_utc();
+    let traces = db
+        .reserve_ach_trace_numbers(withdrawals.len() as i64)
+        .await?;
+
+    let file_id = Uuid::new_v4();
+    let mut details = vec![];
+    let mut entries = vec![];
+    for ((transaction, destination), trace) in withdrawals.into_iter().zip(traces) {
+        let trace_number = format!("{}{:0>7}", settings.odfi_id, trace);
+        let amount_cents = (transaction.amount * Decimal::ONE_HUNDRED)
+            .round()
+            .to_u64()
+            .context("Invalid withdrawal amount")?;
+        details.push(EntryDetail {
+            transaction_code: match destination.account_kind {
+                PayoutAccountKind::Checking => TRANSACTION_CODE_CHECKING_CREDIT,
+                PayoutAccountKind::Savings => TRANSACTION_CODE_SAVINGS_CREDIT,
+            },
+            routing_number: destination.routing_number,
+            account_number: destination.account_number,
+            amount_cents,
+            individual_id: transaction.transaction_reference.clone(),
+            individual_name: destination.account_holder_name,
+            trace_number: trace_number.clone(),
+        });
+        entries.push(AchFileEntry {
+            trace_number,
+            ach_file_id: file_id,
+            transaction_id: transaction.id,
+            bank_account_id: transaction.bank_account_id,
+            amount: transaction.amount,
+            status: ACH_ENTRY_SENT.to_string(),
+            return_code: None,
+        });
+    }
+
+    let mut nacha = NachaFile {
+        header: FileHeader {
+            immediate_destination: settings.immediate_destination.clone(),
+            immediate_origin: settings.immediate_origin.clone(),
+            immediate_destination_name: settings.immediate_destination_name.clone(),
+            immediate_origin_name: settings.immediate_origin_name.clone(),
+            created_at: now,
+            // Set for each attempt below.
+            file_id_modifier: 'A',
+        },
+        batches: vec![NachaBatch {
+            header: BatchHeader {
+                company_name: settings.company_name.clone(),
+                company_id: settings.company_id.clone(),
+                entry_description: ENTRY_DESCRIPTION.to_string(),
+                // Entries settle on the next day at the earliest.
+                effective_date: (now + Duration::days(1)).date(),
+                odfi_id: settings.odfi_id.clone(),
+                batch_number: 1,
+            },
+            entries: details,
+        }],
+    };
+
+    // Today's file count is only a first guess of the next free modifier,
+    // files created concurrently take theirs first and the next one is tried.
+    let files_today = db.count_ach_files_created_on(now.date()).await? as usize;
+    for file_id_modifier in FILE_ID_MODIFIERS.chars().skip(files_today) {
+        nacha.header.file_id_modifier = file_id_modifier;
+        let file = AchFile {
+            id: file_id,
+            file_id_modifier: file_id_modifier.to_string(),
+            entry_count: nacha.entry_count() as i32,
+            total_credit: Decimal::new(nacha.total_credit_cents() as i64, 2),
+            entry_hash: nacha.entry_hash() as i64,
+            content: nacha.to_string(),
+            created_at: now,
+            tenant_id,
+        };
+        match db.create_ach_file(file, entries.clone()).await {
+            Ok(_) => {
+                info!("ACH file created: {}", file_id);
+                return db
+                    .get_ach_file(tenant_id, file_id)
+                    .await
+                    .map(Some)
+                    .map_err(|e| anyhow!("Failed to load ACH file: {}", e));
+            }
+            Err(sqlx::Error::Database(err)) if err.constraint() == Some(ACH_FILE_MODIFIER_KEY) => {
+                info!("ACH file ID modifier taken: {}", file_id_modifier);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err(anyhow!(
+        "All {} ACH file ID modifiers of {} are used",
+        FILE_ID_MODIFIERS.len(),
+        now.date()
+    ))
+}
+
+/// Process a return file from the RDFI. Every returned entry is marked with
+/// its R-code and the withdrawal is reversed by crediting the amount back to
+/// the account ledger through the outbox. Entries already returned are
+/// skipped, so a file can be safely reprocessed after a partial failure. Only
+/// entries of files sent for the tenant can be returned.
+pub async fn process_ach_returns<C: DatabaseClient + Send + Sync>(
+    state: &ApplicationState<C>,
+    tenant_id: i32,
+    content: &str,
+    metadata: &Metadata,
+) -> Result<Vec<AchFileEntry>, anyhow::Error> {
+    let db = state.database.clone();
+    let returns = parse_returns(content)?;
+    let house_account = db
+        .get_house_account(tenant_id, Currency::USD)
+        .await
+        .context("Failed to get house account")?;
+
+    let mut returned = vec![];
+    for item in returns {
+        let mut entry = db
+            .get_ach_file_entry(tenant_id, item.original_trace_number.clone())
+            .await
+            .with_context(|| format!("Unknown trace {}", item.original_trace_number))?;
+        if entry.status == ACH_ENTRY_RETURNED {
+            info!("ACH entry already returned: {}", entry.trace_number);
+            continue;
+        }
+
+        let account = state
+            .bank_account
+            .clone()
+            .context("Bank account view not configured")?
+            .query
+            .load(&entry.bank_account_id.to_string())
+            .await?
+            .context("Account not found")?;
+
+        let description = format!("ACH return {} for {}", item.return_code, entry.trace_number);
+        let reversal = Transaction {
+            id: Uuid::new_v4(),
+            bank_account_id: entry.bank_account_id,
+            transaction_reference: generate_transaction_reference(TRANS_DEPOSIT),
+            transaction_date: Utc::now().date_naive(),
+            amount: entry.amount,
+            currency: Currency::USD.to_string(),
+            description: Some(description.clone()),
+            metadata: serde_json::json!({
+                "ach_return": {
+                    "trace_number": entry.trace_number,
+                    "return_code": item.return_code,
+                    "original_transaction_id": entry.transaction_id,
+                }
+            }),
+            journal_entry_id: None,
+            status: "processing".to_string(),
+            tenant_id,
+        };
+        let journal_entry = JournalEntry {
+            id: Uuid::new_v4(),
+            entry_date: Utc::now().date_naive(),
+            description: Some(description),
+            status: "posted".to_string(),
+        };
+        let journal_lines = vec![
+            JournalLine {
+                id: Uuid::new_v4(),
+                journal_entry_id: None,
+                ledger_id: house_account.ledger_id.clone(),
+                debit_amount: entry.amount,
+                credit_amount: Decimal::ZERO,
+                currency: Currency::USD.to_string(),
+                description: None,
+            },
+            JournalLine {
+                id: Uuid::new_v4(),
+                journal_entry_id: None,
+                ledger_id: account.ledger_id.clone(),
+                debit_amount: Decimal::ZERO,
+                credit_amount: entry.amount,
+                currency: Currency::USD.to_string(),
+                description: None,
+            },
+        ];
+
+        db.return_ach_entry(
+            entry.trace_number.clone(),
+            item.return_code.clone(),
+            reversal,
+            account.ledger_id,
+            journal_entry,
+            journal_lines,
+            metadata.clone(),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to return {}: {}", entry.trace_number, e))?;
+        info!(
+            "ACH entry returned: {} {}",
+            entry.trace_number, item.return_code
+        );
+
+        entry.status = ACH_ENTRY_RETURNED.to_string();
+        entry.return_code = Some(item.return_code);
+        returned.push(entry);
+    }
+
+    Ok(returned)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+    use sqlx::error::{DatabaseError, ErrorKind};
+
+    use super::*;
+    use crate::domain::models::HouseAccount;
+    use crate::payment::nacha::RECORD_SIZE;
+    use crate::repository::adapter::{Adapter, MockDatabaseClient};
+
+    const TENANT_ID: i32 = 1;
+    const TRACE: &str = "091000010000001";
+
+    #[derive(Debug)]
+    struct UniqueViolation(&'static str);
+
+    impl fmt::Display for UniqueViolation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "duplicate key value violates unique constraint {}",
+                self.0
+            )
+        }
+    }
+
+    impl Error for UniqueViolation {}
+
+    impl DatabaseError for UniqueViolation {
+        fn message(&self) -> &str {
+            "duplicate key value"
+        }
+
+        fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            Some(self.0)
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::UniqueViolation
+        }
+    }
+
+    fn withdrawal(amount: Decimal, routing_number: &str) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            bank_account_id: Uuid::new_v4(),
+            transaction_reference: "WI1".to_string(),
+            transaction_date: NaiveDate::from_ymd_opt(2024, 8, 20).unwrap(),
+            amount,
+            currency: "USD".to_string(),
+            description: None,
+            metadata: json!({
+                "destination": {
+                    "routing_number": routing_number,
+                    "account_number": "123456789",
+                    "account_holder_name": "Jane Doe",
+                }
+            }),
+            status: "completed".to_string(),
+            journal_entry_id: None,
+            tenant_id: TENANT_ID,
+        }
+    }
+
+    fn ach_file(id: Uuid) -> AchFile {
+        AchFile {
+            id,
+            file_id_modifier: "A".to_string(),
+            entry_count: 0,
+            total_credit: Decimal::ZERO,
+            entry_hash: 0,
+            content: String::new(),
+            created_at: Utc::now().naive_utc(),
+            tenant_id: TENANT_ID,
+        }
+    }
+
+    fn ach_entry(status: &str) -> AchFileEntry {
+        AchFileEntry {
+            trace_number: TRACE.to_string(),
+            ach_file_id: Uuid::new_v4(),
+            transaction_id: Uuid::new_v4(),
+            bank_account_id: Uuid::new_v4(),
+            amount: dec!(100.00),
+            status: status.to_string(),
+            return_code: Some("R01".to_string()),
+        }
+    }
+
+    // A return file with a single R01 return of `TRACE`.
+    fn return_file() -> String {
+        let entry = format!("6{}{:010}", " ".repeat(28), 10000);
+        let addenda = format!("799R01{}", TRACE);
+        format!(
+            "{:<width$}\n{:<width$}\n",
+            entry,
+            addenda,
+            width = RECORD_SIZE
+        )
+    }
+
+    fn database_with_withdrawals(withdrawals: Vec<Transaction>) -> MockDatabaseClient {
+        let mut db = MockDatabaseClient::new();
+        db.expect_get_pending_ach_withdrawals()
+            .withf(|tenant_id| *tenant_id == TENANT_ID)
+            .return_once(move |_| Ok(withdrawals));
+        db.expect_reserve_ach_trace_numbers()
+            .returning(|count| Ok((1..=count).collect()));
+        db.expect_get_ach_file().returning(|_, id| Ok(ach_file(id)));
+        db
+    }
+
+    #[tokio::test]
+    async fn test_generate_skips_invalid_destinations() {
+        let valid = withdrawal(dec!(12.34), "021000021");
+        let valid_id = valid.id;
+        let mut db = database_with_withdrawals(vec![withdrawal(dec!(5), "123"), valid]);
+        db.expect_count_ach_files_created_on().returning(|_| Ok(0));
+        db.expect_create_ach_file()
+            .withf(move |file, entries| {
+                file.entry_count == 1
+                    && file.total_credit == dec!(12.34)
+                    && entries.len() == 1
+                    && entries[0].transaction_id == valid_id
+                    && entries[0].trace_number == "091000010000001"
+            })
+            .times(1)
+            .returning(|file, _| Ok(file.id));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        assert!(generate_ach_file(&state, TENANT_ID)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_generate_converts_amounts_to_cents() {
+        let content = Arc::new(Mutex::new(String::new()));
+        let written = content.clone();
+        let mut db = database_with_withdrawals(vec![
+            withdrawal(dec!(12.34), "021000021"),
+            withdrawal(dec!(0.5), "021000021"),
+        ]);
+        db.expect_count_ach_files_created_on().returning(|_| Ok(0));
+        db.expect_create_ach_file().returning(move |file, _| {
+            *written.lock().unwrap() = file.content.clone();
+            Ok(file.id)
+        });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        generate_ach_file(&state, TENANT_ID).await.unwrap();
+
+        let content = content.lock().unwrap();
+        let amounts: Vec<&str> = content
+            .lines()
+            .filter(|line| line.starts_with('6'))
+            .map(|line| &line[29..39])
+            .collect();
+        assert_eq!(amounts, vec!["0000001234", "0000000050"]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_takes_next_free_modifier() {
+        let mut db = database_with_withdrawals(vec![withdrawal(dec!(1), "021000021")]);
+        db.expect_count_ach_files_created_on().returning(|_| Ok(1));
+        let modifiers = Arc::new(Mutex::new(vec![]));
+        let tried = modifiers.clone();
+        db.expect_create_ach_file().returning(move |file, _| {
+            let mut tried = tried.lock().unwrap();
+            tried.push(file.file_id_modifier.clone());
+            match tried.len() {
+                1 => Err(sqlx::Error::Database(Box::new(UniqueViolation(
+                    ACH_FILE_MODIFIER_KEY,
+                )))),
+                _ => Ok(file.id),
+            }
+        });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        generate_ach_file(&state, TENANT_ID).await.unwrap();
+
+        assert_eq!(*modifiers.lock().unwrap(), vec!["B", "C"]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_fails_once_modifiers_run_out() {
+        let mut db = database_with_withdrawals(vec![withdrawal(dec!(1), "021000021")]);
+        db.expect_count_ach_files_created_on()
+            .returning(|_| Ok(FILE_ID_MODIFIERS.len() as i64));
+        db.expect_create_ach_file().never();
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let err = generate_ach_file(&state, TENANT_ID).await.unwrap_err();
+
+        assert!(err.to_string().contains("file ID modifiers"));
+    }
+
+    fn database_with_house_account() -> MockDatabaseClient {
+        let mut db = MockDatabaseClient::new();
+        db.expect_get_house_account().returning(|_, currency| {
+            Ok(HouseAccount {
+                currency,
+                ledger_id: Uuid::new_v4().to_string(),
+                ..Default::default()
+            })
+        });
+        db
+    }
+
+    #[tokio::test]
+    async fn test_returns_are_idempotent() {
+        let mut db = database_with_house_account();
+        db.expect_get_ach_file_entry()
+            .withf(|tenant_id, trace| *tenant_id == TENANT_ID && trace == TRACE)
+            .returning(|_, _| Ok(ach_entry(ACH_ENTRY_RETURNED)));
+        db.expect_return_ach_entry().never();
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let returned = process_ach_returns(&state, TENANT_ID, &return_file(), &Metadata::default())
+            .await
+            .unwrap();
+
+        assert!(returned.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_returns_of_unknown_trace_fail() {
+        let mut db = database_with_house_account();
+        db.expect_get_ach_file_entry()
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        db.expect_return_ach_entry().never();
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let err = process_ach_returns(&state, TENANT_ID, &return_file(), &Metadata::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), format!("Unknown trace {}", TRACE));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod ach;
+pub mod nacha;
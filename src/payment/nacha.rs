@@ -0,0 +1,410 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fmt;
+
+pub const RECORD_SIZE: usize = 94;
+pub const BLOCKING_FACTOR: usize = 10;
+
+// Service class code for batches that only contain credit entries.
+pub const SERVICE_CLASS_CREDITS_ONLY: u16 = 220;
+pub const TRANSACTION_CODE_CHECKING_CREDIT: u8 = 22;
+pub const TRANSACTION_CODE_SAVINGS_CREDIT: u8 = 32;
+
+#[derive(Debug, PartialEq)]
+pub enum NachaError {
+    InvalidRecordLength(usize),
+    InvalidField(&'static str),
+    OrphanAddenda(usize),
+}
+
+impl fmt::Display for NachaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NachaError::InvalidRecordLength(line) => {
+                write!(
+                    f,
+                    "record on line {} is not {} characters",
+                    line, RECORD_SIZE
+                )
+            }
+            NachaError::InvalidField(field) => write!(f, "invalid field: {}", field),
+            NachaError::OrphanAddenda(line) => {
+                write!(f, "addenda on line {} has no entry detail", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NachaError {}
+
+/// ABA routing numbers are 9 digits whose weighted sum (3, 7, 1) is a
+/// multiple of 10.
+pub fn is_valid_routing_number(routing_number: &str) -> bool {
+    if routing_number.len() != 9 || !routing_number.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let checksum: u32 = routing_number
+        .chars()
+        .zip([3, 7, 1].iter().cycle())
+        .map(|(c, weight)| c.to_digit(10).unwrap() * weight)
+        .sum();
+    checksum.is_multiple_of(10)
+}
+
+// Alphanumeric fields are upper-cased, left-justified and space padded.
+fn alpha(value: &str, len: usize) -> String {
+    let value: String = value.to_uppercase().chars().take(len).collect();
+    format!("{:<len$}", value, len = len)
+}
+
+// Numeric fields are right-justified and zero padded, keeping the rightmost
+// digits when the value is too wide (as required for hash totals).
+fn numeric(value: u64, len: usize) -> String {
+    let value = format!("{:0>len$}", value, len = len);
+    value[value.len() - len..].to_string()
+}
+
+pub struct FileHeader {
+    pub immediate_destination: String,
+    pub immediate_origin: String,
+    pub immediate_destination_name: String,
+    pub immediate_origin_name: String,
+    pub created_at: NaiveDateTime,
+    pub file_id_modifier: char,
+}
+
+impl FileHeader {
+    fn record(&self) -> String {
+        format!(
+            "101{:>10}{:>10}{}{}{}094{}1{}{}{}",
+            self.immediate_destination,
+            self.immediate_origin,
+            self.created_at.format("%y%m%d"),
+            self.created_at.format("%H%M"),
+            self.file_id_modifier,
+            BLOCKING_FACTOR,
+            alpha(&self.immediate_destination_name, 23),
+            alpha(&self.immediate_origin_name, 23),
+            alpha("", 8),
+        )
+    }
+}
+
+pub struct BatchHeader {
+    pub company_name: String,
+    pub company_id: String,
+    pub entry_description: String,
+    pub effective_date: NaiveDate,
+    pub odfi_id: String,
+    pub batch_number: u32,
+}
+
+pub struct EntryDetail {
+    pub transaction_code: u8,
+    pub routing_number: String,
+    pub account_number: String,
+    pub amount_cents: u64,
+    pub individual_id: String,
+    pub individual_name: String,
+    pub trace_number: String,
+}
+
+impl EntryDetail {
+    // First 8 digits of the routing number identify the receiving DFI, the
+    // 9th digit is the check digit.
+    fn receiving_dfi(&self) -> u64 {
+        self.routing_number[..8].parse().unwrap_or_default()
+    }
+
+    fn record(&self) -> String {
+        format!(
+            "6{}{}{}{}{}{}{}0{}",
+            self.transaction_code,
+            &self.routing_number[..9],
+            alpha(&self.account_number, 17),
+            numeric(self.amount_cents, 10),
+            alpha(&self.individual_id, 15),
+            alpha(&self.individual_name, 22),
+            alpha("", 2),
+            numeric(self.trace_number.parse().unwrap_or_default(), 15),
+        )
+    }
+}
+
+pub struct NachaBatch {
+    pub header: BatchHeader,
+    pub entries: Vec<EntryDetail>,
+}
+
+impl NachaBatch {
+    pub fn entry_hash(&self) -> u64 {
+        self.entries.iter().map(|e| e.receiving_dfi()).sum()
+    }
+
+    pub fn total_credit_cents(&self) -> u64 {
+        self.entries.iter().map(|e| e.amount_cents).sum()
+    }
+
+    fn header_record(&self) -> String {
+        format!(
+            "5{}{}{}{}PPD{}{}{}{}1{}{}",
+            SERVICE_CLASS_CREDITS_ONLY,
+            alpha(&self.header.company_name, 16),
+            alpha("", 20),
+            alpha(&self.header.company_id, 10),
+            alpha(&self.header.entry_description, 10),
+            self.header.effective_date.format("%y%m%d"),
+            self.header.effective_date.format("%y%m%d"),
+            alpha("", 3),
+            alpha(&self.header.odfi_id, 8),
+            numeric(self.header.batch_number as u64, 7),
+        )
+    }
+
+    fn control_record(&self) -> String {
+        format!(
+            "8{}{}{}{}{}{}{}{}{}{}",
+            SERVICE_CLASS_CREDITS_ONLY,
+            numeric(self.entries.len() as u64, 6),
+            numeric(self.entry_hash(), 10),
+            numeric(0, 12),
+            numeric(self.total_credit_cents(), 12),
+            alpha(&self.header.company_id, 10),
+            alpha("", 19),
+            alpha("", 6),
+            alpha(&self.header.odfi_id, 8),
+            numeric(self.header.batch_number as u64, 7),
+        )
+    }
+}
+
+pub struct NachaFile {
+    pub header: FileHeader,
+    pub batches: Vec<NachaBatch>,
+}
+
+impl NachaFile {
+    pub fn entry_count(&self) -> usize {
+        self.batches.iter().map(|b| b.entries.len()).sum()
+    }
+
+    pub fn entry_hash(&self) -> u64 {
+        self.batches.iter().map(|b| b.entry_hash()).sum::<u64>() % 10_000_000_000
+    }
+
+    pub fn total_credit_cents(&self) -> u64 {
+        self.batches.iter().map(|b| b.total_credit_cents()).sum()
+    }
+
+    fn records(&self) -> Vec<String> {
+        let mut records = vec![self.header.record()];
+        for batch in &self.batches {
+            records.push(batch.header_record());
+            records.extend(batch.entries.iter().map(|e| e.record()));
+            records.push(batch.control_record());
+        }
+
+        // The file control record is counted when computing the block count.
+        let block_count = (records.len() + 1).div_ceil(BLOCKING_FACTOR);
+        records.push(format!(
+            "9{}{}{}{}{}{}{}",
+            numeric(self.batches.len() as u64, 6),
+            numeric(block_count as u64, 6),
+            numeric(self.entry_count() as u64, 8),
+            numeric(self.entry_hash(), 10),
+            numeric(0, 12),
+            numeric(self.total_credit_cents(), 12),
+            alpha("", 39),
+        ));
+
+        // Pad the last block with filler records made of 9s.
+        while records.len() % BLOCKING_FACTOR != 0 {
+            records.push("9".repeat(RECORD_SIZE));
+        }
+        records
+    }
+}
+
+impl fmt::Display for NachaFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in self.records() {
+            writeln!(f, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReturnEntry {
+    pub return_code: String,
+    pub original_trace_number: String,
+    pub amount_cents: u64,
+}
+
+/// Parse a return file received from the RDFI and collect every entry that
+/// carries a return addenda (type 99) with its R-code.
+pub fn parse_returns(content: &str) -> Result<Vec<ReturnEntry>, NachaError> {
+    let mut returns = vec![];
+    let mut last_amount: Option<u64> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != RECORD_SIZE || !line.is_ascii() {
+            return Err(NachaError::InvalidRecordLength(index + 1));
+        }
+        match &line[..1] {
+            "6" => {
+                let amount = line[29..39]
+                    .parse()
+                    .map_err(|_| NachaError::InvalidField("amount"))?;
+                last_amount = Some(amount);
+            }
+            "7" if &line[1..3] == "99" => {
+                let amount_cents = last_amount
+                    .take()
+                    .ok_or(NachaError::OrphanAddenda(index + 1))?;
+                returns.push(ReturnEntry {
+                    return_code: line[3..6].to_string(),
+                    original_trace_number: line[6..21].to_string(),
+                    amount_cents,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(returns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn create_file(entries: Vec<EntryDetail>) -> NachaFile {
+        NachaFile {
+            header: FileHeader {
+                immediate_destination: "091000019".to_string(),
+                immediate_origin: "1234567890".to_string(),
+                immediate_destination_name: "Receiving Bank".to_string(),
+                immediate_origin_name: "Bankie".to_string(),
+                created_at: NaiveDate::from_ymd_opt(2024, 8, 20)
+                    .unwrap()
+                    .and_hms_opt(9, 30, 0)
+                    .unwrap(),
+                file_id_modifier: 'A',
+            },
+            batches: vec![NachaBatch {
+                header: BatchHeader {
+                    company_name: "Bankie".to_string(),
+                    company_id: "1234567890".to_string(),
+                    entry_description: "WITHDRAWAL".to_string(),
+                    effective_date: NaiveDate::from_ymd_opt(2024, 8, 21).unwrap(),
+                    odfi_id: "09100001".to_string(),
+                    batch_number: 1,
+                },
+                entries,
+            }],
+        }
+    }
+
+    fn create_entry(routing_number: &str, amount_cents: u64, trace: &str) -> EntryDetail {
+        EntryDetail {
+            transaction_code: TRANSACTION_CODE_CHECKING_CREDIT,
+            routing_number: routing_number.to_string(),
+            account_number: "123456789".to_string(),
+            amount_cents,
+            individual_id: "WI1234".to_string(),
+            individual_name: "Jane Doe".to_string(),
+            trace_number: trace.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_is_valid_routing_number() {
+        assert!(is_valid_routing_number("021000021"));
+        assert!(is_valid_routing_number("011000015"));
+        assert!(!is_valid_routing_number("021000022"));
+        assert!(!is_valid_routing_number("02100002"));
+        assert!(!is_valid_routing_number("02100002a"));
+    }
+
+    #[test]
+    fn test_file_records_are_blocked() {
+        let file = create_file(vec![
+            create_entry("021000021", 10000, "091000010000001"),
+            create_entry("011000015", 2550, "091000010000002"),
+        ]);
+        let content = file.to_string();
+        let lines: Vec<&str> = content.lines().collect();
+
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|l| l.len() == RECORD_SIZE));
+        assert!(lines[0].starts_with("101 091000019"));
+        assert!(lines[1].starts_with("5220BANKIE"));
+        assert!(lines[2].starts_with("622021000021123456789"));
+        assert_eq!(&lines[2][29..39], "0000010000");
+        assert_eq!(&lines[2][79..94], "091000010000001");
+        assert!(lines[4].starts_with("8220000002"));
+        assert!(lines[5].starts_with("9000001000001"));
+        assert_eq!(lines[9], "9".repeat(RECORD_SIZE));
+    }
+
+    #[test]
+    fn test_file_totals_and_hash() {
+        let file = create_file(vec![
+            create_entry("021000021", 10000, "091000010000001"),
+            create_entry("011000015", 2550, "091000010000002"),
+        ]);
+        assert_eq!(file.entry_count(), 2);
+        assert_eq!(file.entry_hash(), 2100002 + 1100001);
+        assert_eq!(file.total_credit_cents(), 12550);
+
+        let content = file.to_string();
+        let control = content.lines().nth(5).unwrap();
+        assert_eq!(&control[13..21], "00000002");
+        assert_eq!(&control[21..31], "0003200003");
+        assert_eq!(&control[31..43], "000000000000");
+        assert_eq!(&control[43..55], "000000012550");
+    }
+
+    #[test]
+    fn test_parse_returns() {
+        let original = create_entry("021000021", 10000, "091000010000001");
+        let mut entry = original.record();
+        entry.replace_range(78..79, "1");
+        let addenda = format!(
+            "799R01{}      02100002{}{}",
+            "091000010000001",
+            alpha("", 44),
+            "021000020000001"
+        );
+        let content = format!("{}\n{}\n{}\n", "9".repeat(RECORD_SIZE), entry, addenda);
+
+        let returns = parse_returns(&content).unwrap();
+        assert_eq!(
+            returns,
+            vec![ReturnEntry {
+                return_code: "R01".to_string(),
+                original_trace_number: "091000010000001".to_string(),
+                amount_cents: 10000,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_returns_invalid_record() {
+        assert_eq!(
+            parse_returns("6220210000"),
+            Err(NachaError::InvalidRecordLength(1))
+        );
+    }
+
+    #[test]
+    fn test_parse_returns_orphan_addenda() {
+        let addenda = format!("799R01{}", alpha("", 88));
+        assert_eq!(parse_returns(&addenda), Err(NachaError::OrphanAddenda(1)));
+    }
+}
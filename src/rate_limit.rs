@@ -0,0 +1,358 @@
+use std::sync::Arc;
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderName, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use tracing::warn;
+
+use crate::common::error::AppError;
+use crate::configs::settings::SETTINGS;
+use crate::domain::rate_limit::{RateLimit, TenantRateLimits, ROUTE_CLASS_MONEY, ROUTE_CLASS_READ};
+use crate::repository::adapter::DatabaseClient;
+use crate::state::ApplicationState;
+
+// Routes that move money when called with POST.
+const MONEY_ROUTES: [&str; 4] = [
+    "/v1/bank_account",
+    "/v1/house_account",
+    "/v1/ach_file",
+    "/v1/ach_return",
+];
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+lazy_static! {
+    // KEYS: token bucket, request counter of the day.
+    // ARGV: rate per second, burst, now in milliseconds, daily quota, counter ttl.
+    // Refills the bucket for the time since the last request, then takes a
+    // token when one is left and the daily quota is not used up. Returns
+    // whether the request is allowed, the tokens left and today's count.
+    static ref TAKE_TOKEN: redis::Script = redis::Script::new(
+        r"
+        local rate = tonumber(ARGV[1])
+        local burst = tonumber(ARGV[2])
+        local now = tonumber(ARGV[3])
+        local quota = tonumber(ARGV[4])
+        local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+        local tokens = tonumber(bucket[1]) or burst
+        local updated_at = tonumber(bucket[2]) or now
+        tokens = math.min(burst, tokens + math.max(0, now - updated_at) * rate / 1000)
+        local used = tonumber(redis.call('GET', KEYS[2])) or 0
+        local allowed = 0
+        if tokens >= 1 and (quota == 0 or used < quota) then
+            allowed = 1
+            tokens = tokens - 1
+            used = redis.call('INCR', KEYS[2])
+            if used == 1 then
+                redis.call('EXPIRE', KEYS[2], ARGV[5])
+            end
+        end
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+        redis.call('PEXPIRE', KEYS[1], math.ceil(burst / rate * 1000) + 1000)
+        return {allowed, tostring(tokens), used}
+        "
+    );
+}
+
+struct Usage {
+    allowed: bool,
+    // Tokens left in the bucket after this request.
+    tokens: f64,
+    // Requests counted today, including this one when allowed.
+    used: u64,
+}
+
+pub fn route_class(method: &Method, path: &str) -> &'static str {
+    if method == Method::POST && MONEY_ROUTES.contains(&path) {
+        ROUTE_CLASS_MONEY
+    } else {
+        ROUTE_CLASS_READ
+    }
+}
+
+fn request_class(req: &Request) -> &'static str {
+    let path = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str(),
+        None => req.uri().path(),
+    };
+    route_class(req.method(), path)
+}
+
+fn class_limit(limits: &TenantRateLimits, class: &str) -> RateLimit {
+    match class {
+        ROUTE_CLASS_MONEY => limits.money.unwrap_or(SETTINGS.rate_limit.money),
+        _ => limits.read.unwrap_or(SETTINGS.rate_limit.read),
+    }
+}
+
+fn seconds_to_midnight(now: DateTime<Utc>) -> u64 {
+    let midnight = (now.date_naive() + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    ((midnight - now).num_milliseconds() as u64).div_ceil(1000)
+}
+
+async fn take_token(
+    cache: &redis::Client,
+    tenant_id: i32,
+    class: &str,
+    limit: &RateLimit,
+    now: DateTime<Utc>,
+) -> redis::RedisResult<Usage> {
+    let mut con = cache.get_multiplexed_async_connection().await?;
+    let (allowed, tokens, used): (i64, String, i64) = TAKE_TOKEN
+        .key(format!("rate_limit:{}:{}", tenant_id, class))
+        .key(format!(
+            "quota:{}:{}:{}",
+            tenant_id,
+            class,
+            now.format("%Y-%m-%d")
+        ))
+        .arg(limit.rate)
+        .arg(limit.burst)
+        .arg(now.timestamp_millis())
+        .arg(limit.daily_quota)
+        .arg(seconds_to_midnight(now) + 60)
+        .invoke_async(&mut con)
+        .await?;
+
+    Ok(Usage {
+        allowed: allowed == 1,
+        tokens: tokens.parse().unwrap_or(0.0),
+        used: used as u64,
+    })
+}
+
+fn limit_headers(limit: &RateLimit, usage: &Usage, now: DateTime<Utc>) -> Vec<(HeaderName, u64)> {
+    let quota_left = (limit.daily_quota > 0).then(|| limit.daily_quota.saturating_sub(usage.used));
+    let quota_exhausted = quota_left == Some(0);
+    let tokens = usage.tokens.clamp(0.0, limit.burst as f64);
+    let remaining = match quota_left {
+        Some(left) => left.min(tokens as u64),
+        None => tokens as u64,
+    };
+    let reset = match quota_exhausted {
+        true => seconds_to_midnight(now),
+        false => ((limit.burst as f64 - tokens) / limit.rate).ceil() as u64,
+    };
+
+    let mut headers = vec![
+        (RATELIMIT_LIMIT, limit.burst as u64),
+        (RATELIMIT_REMAINING, remaining),
+        (RATELIMIT_RESET, reset),
+    ];
+    if !usage.allowed {
+        let retry_after = match quota_exhausted {
+            true => seconds_to_midnight(now),
+            false => ((1.0 - tokens) / limit.rate).ceil().max(1.0) as u64,
+        };
+        headers.push((RETRY_AFTER, retry_after));
+    }
+    headers
+}
+
+fn limit_policy(limit: &RateLimit) -> String {
+    let window = (limit.burst as f64 / limit.rate).ceil() as u64;
+    match limit.daily_quota {
+        0 => format!("{};w={}", limit.burst, window),
+        quota => format!("{};w={}, {};w=86400", limit.burst, window, quota),
+    }
+}
+
+/// Limits the requests of the tenant resolved by `authorize` with a Redis
+/// token bucket and daily quota per route class. Requests pass unlimited while
+/// Redis is unavailable.
+pub async fn rate_limit<C: DatabaseClient + Send + Sync + 'static>(
+    req: Request,
+    next: Next,
+) -> Response {
+    let cache = req
+        .extensions()
+        .get::<Arc<ApplicationState<C>>>()
+        .and_then(|state| state.cache.clone());
+    let (Some(cache), Some(&tenant_id)) = (cache, req.extensions().get::<i32>()) else {
+        return next.run(req).await;
+    };
+    let class = request_class(&req);
+    let limits = req
+        .extensions()
+        .get::<TenantRateLimits>()
+        .cloned()
+        .unwrap_or_default();
+    let limit = class_limit(&limits, class);
+
+    let now = Utc::now();
+    let usage = match take_token(&cache, tenant_id, class, &limit, now).await {
+        Ok(usage) => usage,
+        Err(err) => {
+            warn!("Rate limit not applied, Redis unavailable: {}", err);
+            return next.run(req).await;
+        }
+    };
+
+    let mut response = match usage.allowed {
+        true => next.run(req).await,
+        false if limit.daily_quota > 0 && usage.used >= limit.daily_quota => {
+            AppError::TooManyRequests(format!(
+                "Daily quota of {} {} requests exceeded",
+                limit.daily_quota, class
+            ))
+            .into_response()
+        }
+        false => AppError::TooManyRequests(format!("Rate limit of {} requests exceeded", class))
+            .into_response(),
+    };
+    let headers = response.headers_mut();
+    for (name, value) in limit_headers(&limit, &usage, now) {
+        headers.insert(name, HeaderValue::from(value));
+    }
+    if let Ok(policy) = HeaderValue::from_str(&limit_policy(&limit)) {
+        headers.insert(RATELIMIT_POLICY, policy);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::{get, post};
+    use axum::{middleware, Router};
+    use chrono::TimeZone;
+    use tower::ServiceExt;
+
+    const LIMIT: RateLimit = RateLimit {
+        rate: 2.0,
+        burst: 10,
+        daily_quota: 100,
+    };
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 8, 29, 23, 59, 0).unwrap()
+    }
+
+    fn header(headers: &[(HeaderName, u64)], name: &HeaderName) -> Option<u64> {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| *value)
+    }
+
+    #[test]
+    fn test_route_class() {
+        assert_eq!(
+            route_class(&Method::POST, "/v1/bank_account"),
+            ROUTE_CLASS_MONEY
+        );
+        assert_eq!(
+            route_class(&Method::POST, "/v1/ach_file"),
+            ROUTE_CLASS_MONEY
+        );
+        assert_eq!(
+            route_class(&Method::GET, "/v1/house_account"),
+            ROUTE_CLASS_READ
+        );
+        assert_eq!(route_class(&Method::POST, "/v1/webhook"), ROUTE_CLASS_READ);
+    }
+
+    #[tokio::test]
+    async fn test_request_class_uses_route_pattern() {
+        let app = Router::new()
+            .route("/v1/bank_account/:id", get(|| async {}))
+            .route("/v1/bank_account", post(|| async {}))
+            .layer(middleware::from_fn(|req: Request, _: Next| async move {
+                request_class(&req)
+            }));
+
+        for (method, uri, class) in [
+            ("GET", "/v1/bank_account/1", ROUTE_CLASS_READ),
+            ("POST", "/v1/bank_account", ROUTE_CLASS_MONEY),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, class);
+        }
+    }
+
+    #[test]
+    fn test_seconds_to_midnight() {
+        assert_eq!(seconds_to_midnight(now()), 60);
+        let midnight = Utc.with_ymd_and_hms(2024, 8, 29, 0, 0, 0).unwrap();
+        assert_eq!(seconds_to_midnight(midnight), 86400);
+    }
+
+    #[test]
+    fn test_limit_headers_allowed() {
+        let usage = Usage {
+            allowed: true,
+            tokens: 6.5,
+            used: 20,
+        };
+
+        let headers = limit_headers(&LIMIT, &usage, now());
+
+        assert_eq!(header(&headers, &RATELIMIT_LIMIT), Some(10));
+        assert_eq!(header(&headers, &RATELIMIT_REMAINING), Some(6));
+        // 3.5 tokens to refill at 2 per second.
+        assert_eq!(header(&headers, &RATELIMIT_RESET), Some(2));
+        assert_eq!(header(&headers, &RETRY_AFTER), None);
+        assert_eq!(limit_policy(&LIMIT), "10;w=5, 100;w=86400");
+    }
+
+    #[test]
+    fn test_limit_headers_rate_exceeded() {
+        let usage = Usage {
+            allowed: false,
+            tokens: 0.2,
+            used: 20,
+        };
+
+        let headers = limit_headers(&LIMIT, &usage, now());
+
+        assert_eq!(header(&headers, &RATELIMIT_REMAINING), Some(0));
+        assert_eq!(header(&headers, &RETRY_AFTER), Some(1));
+    }
+
+    #[test]
+    fn test_limit_headers_quota_exceeded() {
+        let usage = Usage {
+            allowed: false,
+            tokens: 10.0,
+            used: 100,
+        };
+
+        let headers = limit_headers(&LIMIT, &usage, now());
+
+        assert_eq!(header(&headers, &RATELIMIT_REMAINING), Some(0));
+        assert_eq!(header(&headers, &RATELIMIT_RESET), Some(60));
+        assert_eq!(header(&headers, &RETRY_AFTER), Some(60));
+    }
+
+    #[test]
+    fn test_class_limit_prefers_tenant_limits() {
+        let limits = TenantRateLimits {
+            read: None,
+            money: Some(LIMIT),
+        };
+        assert_eq!(class_limit(&limits, ROUTE_CLASS_MONEY), LIMIT);
+        assert_eq!(
+            class_limit(&limits, ROUTE_CLASS_READ),
+            SETTINGS.rate_limit.read
+        );
+    }
+}
@@ -1,16 +1,24 @@
 use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
 use mockall::automock;
+use serde_json::Value;
 use sqlx::Error;
 use uuid::Uuid;
 
 use crate::{
     common::money::Currency,
     domain::{
-        finance::{JournalEntry, JournalLine, Outbox, Transaction},
+        asset::Asset,
+        diagnostics::{Diagnostics, LedgerTotal, OutboxBacklog},
+        finance::{JournalEntry, JournalLine, Outbox, OutboxEntry, Transaction},
         models::{BankAccountKind, HouseAccount},
-        tenant::Tenant,
+        payment::{AchFile, AchFileEntry},
+        signing_key::SigningKey,
+        tenant::{Tenant, TenantAuditEntry, TenantSummary, TenantToken},
         user::BankAccountWithLedger,
+        webhook::{PendingWebhookDelivery, WebhookDelivery, WebhookEndpoint},
     },
+    event_sourcing::metadata::Metadata,
 };
 
 #[automock]
@@ -18,36 +26,209 @@ use crate::{
 pub trait DatabaseClient {
     async fn get_user_bank_accounts(
         &self,
+        tenant_id: i32,
         user_id: String,
     ) -> Result<Vec<BankAccountWithLedger>, Error>;
-    async fn fail_transaction(&self, transaction_id: Uuid) -> Result<(), Error>;
-    async fn complete_transaction(&self, transaction_id: Uuid) -> Result<(), Error>;
+    // Tenant owning the bank account or ledger view, `RowNotFound` if missing.
+    async fn get_bank_account_tenant(&self, account_id: String) -> Result<i32, Error>;
+    async fn get_ledger_tenant(&self, ledger_id: String) -> Result<i32, Error>;
+    // Fails the transaction and dead-letters its pending outbox commands. This
+    // and `complete_transaction` fail with `RowNotFound` unless `worker_id`
+    // still holds the claim of the commands.
+    async fn fail_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+        error: String,
+        hold_released: bool,
+    ) -> Result<(), Error>;
+    async fn complete_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+    ) -> Result<(), Error>;
     async fn create_transaction_with_journal(
         &self,
         transaction: Transaction,
         ledger_id: String,
         journal_entry: JournalEntry,
         journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
     ) -> Result<Uuid, Error>;
     async fn create_house_account(&self, account: HouseAccount) -> Result<(), Error>;
-    async fn get_house_account(&self, currency: Currency) -> Result<HouseAccount, Error>;
-    async fn get_house_accounts(&self, currency: Currency) -> Result<Vec<HouseAccount>, Error>;
+    async fn get_house_account(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<HouseAccount, Error>;
+    async fn get_house_accounts(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<Vec<HouseAccount>, Error>;
     async fn validate_bank_account_exists(
         &self,
+        tenant_id: i32,
         user_id: String,
         currency: Currency,
         kind: BankAccountKind,
     ) -> Result<bool, Error>;
-    async fn create_tenant_profile(&self, name: &str, scope: &str) -> Result<i32, Error>;
+    // Writes `audit`, when given, for the new tenant in the same transaction.
+    async fn create_tenant_profile(
+        &self,
+        name: &str,
+        scope: &str,
+        audit: Option<TenantAuditEntry>,
+    ) -> Result<i32, Error>;
     async fn update_tenant_profile(&self, id: i32, jwt: &str) -> Result<i32, Error>;
     async fn get_tenant_profile(&self, tenant_id: i32) -> Result<Tenant, Error>;
-    async fn get_unprocessed_outbox(&self) -> Result<Vec<Outbox>, Error>;
+    // `RowNotFound` if the tenant is inactive or has no client secret.
+    async fn get_tenant_client_secret_hash(&self, tenant_id: i32) -> Result<String, Error>;
+    async fn set_tenant_client_secret_hash(&self, tenant_id: i32, hash: &str) -> Result<(), Error>;
+    async fn create_tenant_token(&self, token: TenantToken) -> Result<(), Error>;
+    async fn get_tenant_token(&self, jti: Uuid) -> Result<TenantToken, Error>;
+    // Tokens of the tenant that have not expired yet.
+    async fn get_tenant_tokens(&self, tenant_id: i32) -> Result<Vec<TenantToken>, Error>;
+    // The token stops working at `revoked_at`, unless it already stops earlier.
+    async fn revoke_tenant_token(
+        &self,
+        tenant_id: i32,
+        jti: Uuid,
+        revoked_at: NaiveDateTime,
+    ) -> Result<TenantToken, Error>;
+    // Revokes every token of the tenant that is not revoked or expired yet.
+    async fn revoke_tenant_tokens(
+        &self,
+        tenant_id: i32,
+        revoked_at: NaiveDateTime,
+    ) -> Result<Vec<TenantToken>, Error>;
+    async fn get_tenants(&self) -> Result<Vec<TenantSummary>, Error>;
+    async fn get_tenant(&self, tenant_id: i32) -> Result<TenantSummary, Error>;
+    // `RowNotFound` unless the tenant's status is one of `from`. The tenant
+    // updates write `audit` in the same transaction.
+    async fn update_tenant_status(
+        &self,
+        tenant_id: i32,
+        from: Vec<String>,
+        status: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error>;
+    async fn update_tenant_scope(
+        &self,
+        tenant_id: i32,
+        scope: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error>;
+    async fn update_tenant_rate_limits(
+        &self,
+        tenant_id: i32,
+        rate_limits: Value,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error>;
+    async fn create_tenant_audit(&self, entry: TenantAuditEntry) -> Result<(), Error>;
+    async fn get_tenant_audit(&self, tenant_id: i32) -> Result<Vec<TenantAuditEntry>, Error>;
+    // Active signing keys, newest first.
+    async fn get_signing_keys(&self) -> Result<Vec<SigningKey>, Error>;
+    async fn create_signing_key(&self, key: SigningKey) -> Result<(), Error>;
+    async fn retire_signing_key(&self, kid: &str) -> Result<(), Error>;
+    // Leases up to `limit` due commands to `worker_id` for `lease_secs`.
+    async fn claim_outbox(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<Outbox>, Error>;
+    // Hands the pending commands claimed by `worker_id` back to the others.
+    async fn release_outbox_claims(&self, worker_id: String) -> Result<(), Error>;
+    // `RowNotFound` unless `worker_id` still holds the claim of the command.
+    async fn mark_outbox_failed(
+        &self,
+        id: i32,
+        worker_id: String,
+        error: String,
+        next_attempt_at: NaiveDateTime,
+    ) -> Result<(), Error>;
+    async fn get_outbox_entries(
+        &self,
+        tenant_id: i32,
+        status: String,
+    ) -> Result<Vec<OutboxEntry>, Error>;
+    async fn get_outbox_entry(&self, tenant_id: i32, id: i32) -> Result<OutboxEntry, Error>;
+    // Puts a dead-lettered command back in the queue with a fresh retry budget.
+    async fn retry_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error>;
+    async fn skip_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error>;
+    async fn ping(&self) -> Result<(), Error>;
+    // Backlogs across every tenant, for platform operators.
+    async fn get_diagnostics(&self, stuck_transaction_secs: i64) -> Result<Diagnostics, Error>;
+    async fn get_outbox_backlog(&self) -> Result<OutboxBacklog, Error>;
+    async fn get_ledger_totals(&self) -> Result<Vec<LedgerTotal>, Error>;
     async fn get_transactions(
         &self,
+        tenant_id: i32,
         bank_account_id: String,
         offset: i64,
         limit: i64,
     ) -> Result<Vec<Transaction>, Error>;
+    async fn get_pending_ach_withdrawals(&self, tenant_id: i32) -> Result<Vec<Transaction>, Error>;
+    async fn reserve_ach_trace_numbers(&self, count: i64) -> Result<Vec<i64>, Error>;
+    async fn count_ach_files_created_on(&self, date: NaiveDate) -> Result<i64, Error>;
+    async fn create_ach_file(
+        &self,
+        file: AchFile,
+        entries: Vec<AchFileEntry>,
+    ) -> Result<Uuid, Error>;
+    async fn get_ach_file(&self, tenant_id: i32, id: Uuid) -> Result<AchFile, Error>;
+    async fn get_ach_file_entry(
+        &self,
+        tenant_id: i32,
+        trace_number: String,
+    ) -> Result<AchFileEntry, Error>;
+    #[allow(clippy::too_many_arguments)]
+    async fn return_ach_entry(
+        &self,
+        trace_number: String,
+        return_code: String,
+        reversal: Transaction,
+        ledger_id: String,
+        journal_entry: JournalEntry,
+        journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
+    ) -> Result<Uuid, Error>;
+    async fn create_webhook_endpoint(&self, endpoint: WebhookEndpoint) -> Result<Uuid, Error>;
+    async fn get_webhook_endpoints(&self, tenant_id: i32) -> Result<Vec<WebhookEndpoint>, Error>;
+    async fn disable_webhook_endpoint(&self, tenant_id: i32, id: Uuid) -> Result<(), Error>;
+    async fn enqueue_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        event_type: String,
+        payload: Value,
+    ) -> Result<u64, Error>;
+    // Leases up to `limit` due deliveries to `worker_id` for `lease_secs`.
+    async fn claim_webhook_deliveries(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>, Error>;
+    // These fail with `RowNotFound` once another instance claimed the delivery.
+    async fn mark_webhook_delivered(&self, id: Uuid, worker_id: String) -> Result<(), Error>;
+    async fn mark_webhook_failed(
+        &self,
+        id: Uuid,
+        worker_id: String,
+        error: String,
+        next_attempt_at: Option<NaiveDateTime>,
+    ) -> Result<(), Error>;
+    async fn get_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        endpoint_id: Uuid,
+        status: Option<String>,
+    ) -> Result<Vec<WebhookDelivery>, Error>;
+    async fn replay_webhook_delivery(&self, tenant_id: i32, id: Uuid) -> Result<(), Error>;
+    async fn create_asset(&self, asset: Asset) -> Result<Asset, Error>;
+    // Custom assets of every tenant, they are resolved without a tenant.
+    async fn get_assets(&self) -> Result<Vec<Asset>, Error>;
 }
 
 pub struct Adapter<C: DatabaseClient + Send + Sync> {
@@ -59,12 +240,34 @@ impl<C: DatabaseClient + Send + Sync> Adapter<C> {
         Adapter { client }
     }
 
-    pub async fn fail_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
-        self.client.fail_transaction(transaction_id).await
+    pub async fn get_bank_account_tenant(&self, account_id: String) -> Result<i32, Error> {
+        self.client.get_bank_account_tenant(account_id).await
+    }
+
+    pub async fn get_ledger_tenant(&self, ledger_id: String) -> Result<i32, Error> {
+        self.client.get_ledger_tenant(ledger_id).await
     }
 
-    pub async fn complete_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
-        self.client.complete_transaction(transaction_id).await
+    pub async fn fail_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+        error: String,
+        hold_released: bool,
+    ) -> Result<(), Error> {
+        self.client
+            .fail_transaction(transaction_id, worker_id, error, hold_released)
+            .await
+    }
+
+    pub async fn complete_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+    ) -> Result<(), Error> {
+        self.client
+            .complete_transaction(transaction_id, worker_id)
+            .await
     }
 
     pub async fn create_transaction_with_journal(
@@ -73,9 +276,16 @@ impl<C: DatabaseClient + Send + Sync> Adapter<C> {
         ledger_id: String,
         journal_entry: JournalEntry,
         journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
     ) -> Result<Uuid, Error> {
         self.client
-            .create_transaction_with_journal(transaction, ledger_id, journal_entry, journal_lines)
+            .create_transaction_with_journal(
+                transaction,
+                ledger_id,
+                journal_entry,
+                journal_lines,
+                metadata,
+            )
             .await
     }
 
@@ -83,27 +293,41 @@ impl<C: DatabaseClient + Send + Sync> Adapter<C> {
         self.client.create_house_account(account).await
     }
 
-    pub async fn get_house_account(&self, currency: Currency) -> Result<HouseAccount, Error> {
-        self.client.get_house_account(currency).await
+    pub async fn get_house_account(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<HouseAccount, Error> {
+        self.client.get_house_account(tenant_id, currency).await
     }
 
-    pub async fn get_house_accounts(&self, currency: Currency) -> Result<Vec<HouseAccount>, Error> {
-        self.client.get_house_accounts(currency).await
+    pub async fn get_house_accounts(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<Vec<HouseAccount>, Error> {
+        self.client.get_house_accounts(tenant_id, currency).await
     }
 
     pub async fn validate_bank_account_exists(
         &self,
+        tenant_id: i32,
         user_id: String,
         currency: Currency,
         kind: BankAccountKind,
     ) -> Result<bool, Error> {
         self.client
-            .validate_bank_account_exists(user_id, currency, kind)
+            .validate_bank_account_exists(tenant_id, user_id, currency, kind)
             .await
     }
 
-    pub async fn create_tenant_profile(&self, name: &str, scope: &str) -> Result<i32, Error> {
-        self.client.create_tenant_profile(name, scope).await
+    pub async fn create_tenant_profile(
+        &self,
+        name: &str,
+        scope: &str,
+        audit: Option<TenantAuditEntry>,
+    ) -> Result<i32, Error> {
+        self.client.create_tenant_profile(name, scope, audit).await
     }
 
     pub async fn update_tenant_profile(&self, id: i32, jwt: &str) -> Result<i32, Error> {
@@ -114,25 +338,330 @@ impl<C: DatabaseClient + Send + Sync> Adapter<C> {
         self.client.get_tenant_profile(tenant_id).await
     }
 
-    pub async fn get_unprocessed_outbox(&self) -> Result<Vec<Outbox>, Error> {
-        self.client.get_unprocessed_outbox().await
+    pub async fn get_tenant_client_secret_hash(&self, tenant_id: i32) -> Result<String, Error> {
+        self.client.get_tenant_client_secret_hash(tenant_id).await
+    }
+
+    pub async fn set_tenant_client_secret_hash(
+        &self,
+        tenant_id: i32,
+        hash: &str,
+    ) -> Result<(), Error> {
+        self.client
+            .set_tenant_client_secret_hash(tenant_id, hash)
+            .await
+    }
+
+    pub async fn create_tenant_token(&self, token: TenantToken) -> Result<(), Error> {
+        self.client.create_tenant_token(token).await
+    }
+
+    pub async fn get_tenant_token(&self, jti: Uuid) -> Result<TenantToken, Error> {
+        self.client.get_tenant_token(jti).await
+    }
+
+    pub async fn get_tenant_tokens(&self, tenant_id: i32) -> Result<Vec<TenantToken>, Error> {
+        self.client.get_tenant_tokens(tenant_id).await
+    }
+
+    pub async fn revoke_tenant_token(
+        &self,
+        tenant_id: i32,
+        jti: Uuid,
+        revoked_at: NaiveDateTime,
+    ) -> Result<TenantToken, Error> {
+        self.client
+            .revoke_tenant_token(tenant_id, jti, revoked_at)
+            .await
+    }
+
+    pub async fn revoke_tenant_tokens(
+        &self,
+        tenant_id: i32,
+        revoked_at: NaiveDateTime,
+    ) -> Result<Vec<TenantToken>, Error> {
+        self.client
+            .revoke_tenant_tokens(tenant_id, revoked_at)
+            .await
+    }
+
+    pub async fn get_tenants(&self) -> Result<Vec<TenantSummary>, Error> {
+        self.client.get_tenants().await
+    }
+
+    pub async fn get_tenant(&self, tenant_id: i32) -> Result<TenantSummary, Error> {
+        self.client.get_tenant(tenant_id).await
+    }
+
+    pub async fn update_tenant_status(
+        &self,
+        tenant_id: i32,
+        from: Vec<String>,
+        status: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        self.client
+            .update_tenant_status(tenant_id, from, status, audit)
+            .await
+    }
+
+    pub async fn update_tenant_scope(
+        &self,
+        tenant_id: i32,
+        scope: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        self.client
+            .update_tenant_scope(tenant_id, scope, audit)
+            .await
+    }
+
+    pub async fn update_tenant_rate_limits(
+        &self,
+        tenant_id: i32,
+        rate_limits: Value,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        self.client
+            .update_tenant_rate_limits(tenant_id, rate_limits, audit)
+            .await
+    }
+
+    pub async fn create_tenant_audit(&self, entry: TenantAuditEntry) -> Result<(), Error> {
+        self.client.create_tenant_audit(entry).await
+    }
+
+    pub async fn get_tenant_audit(&self, tenant_id: i32) -> Result<Vec<TenantAuditEntry>, Error> {
+        self.client.get_tenant_audit(tenant_id).await
+    }
+
+    pub async fn get_signing_keys(&self) -> Result<Vec<SigningKey>, Error> {
+        self.client.get_signing_keys().await
+    }
+
+    pub async fn create_signing_key(&self, key: SigningKey) -> Result<(), Error> {
+        self.client.create_signing_key(key).await
+    }
+
+    pub async fn retire_signing_key(&self, kid: &str) -> Result<(), Error> {
+        self.client.retire_signing_key(kid).await
+    }
+
+    pub async fn claim_outbox(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<Outbox>, Error> {
+        self.client.claim_outbox(worker_id, limit, lease_secs).await
+    }
+
+    pub async fn release_outbox_claims(&self, worker_id: String) -> Result<(), Error> {
+        self.client.release_outbox_claims(worker_id).await
+    }
+
+    pub async fn mark_outbox_failed(
+        &self,
+        id: i32,
+        worker_id: String,
+        error: String,
+        next_attempt_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        self.client
+            .mark_outbox_failed(id, worker_id, error, next_attempt_at)
+            .await
+    }
+
+    pub async fn get_outbox_entries(
+        &self,
+        tenant_id: i32,
+        status: String,
+    ) -> Result<Vec<OutboxEntry>, Error> {
+        self.client.get_outbox_entries(tenant_id, status).await
+    }
+
+    pub async fn get_outbox_entry(&self, tenant_id: i32, id: i32) -> Result<OutboxEntry, Error> {
+        self.client.get_outbox_entry(tenant_id, id).await
+    }
+
+    pub async fn retry_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error> {
+        self.client.retry_outbox(tenant_id, id).await
+    }
+
+    pub async fn skip_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error> {
+        self.client.skip_outbox(tenant_id, id).await
+    }
+
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.client.ping().await
+    }
+
+    pub async fn get_diagnostics(&self, stuck_transaction_secs: i64) -> Result<Diagnostics, Error> {
+        self.client.get_diagnostics(stuck_transaction_secs).await
+    }
+
+    pub async fn get_outbox_backlog(&self) -> Result<OutboxBacklog, Error> {
+        self.client.get_outbox_backlog().await
+    }
+
+    pub async fn get_ledger_totals(&self) -> Result<Vec<LedgerTotal>, Error> {
+        self.client.get_ledger_totals().await
     }
 
     pub async fn get_user_bank_accounts(
         &self,
+        tenant_id: i32,
         user_id: String,
     ) -> Result<Vec<BankAccountWithLedger>, Error> {
-        self.client.get_user_bank_accounts(user_id).await
+        self.client.get_user_bank_accounts(tenant_id, user_id).await
     }
 
     pub async fn get_transactions(
         &self,
+        tenant_id: i32,
         bank_account_id: String,
         offset: i64,
         limit: i64,
     ) -> Result<Vec<Transaction>, Error> {
         self.client
-            .get_transactions(bank_account_id, offset, limit)
+            .get_transactions(tenant_id, bank_account_id, offset, limit)
             .await
     }
+
+    pub async fn get_pending_ach_withdrawals(
+        &self,
+        tenant_id: i32,
+    ) -> Result<Vec<Transaction>, Error> {
+        self.client.get_pending_ach_withdrawals(tenant_id).await
+    }
+
+    pub async fn reserve_ach_trace_numbers(&self, count: i64) -> Result<Vec<i64>, Error> {
+        self.client.reserve_ach_trace_numbers(count).await
+    }
+
+    pub async fn count_ach_files_created_on(&self, date: NaiveDate) -> Result<i64, Error> {
+        self.client.count_ach_files_created_on(date).await
+    }
+
+    pub async fn create_ach_file(
+        &self,
+        file: AchFile,
+        entries: Vec<AchFileEntry>,
+    ) -> Result<Uuid, Error> {
+        self.client.create_ach_file(file, entries).await
+    }
+
+    pub async fn get_ach_file(&self, tenant_id: i32, id: Uuid) -> Result<AchFile, Error> {
+        self.client.get_ach_file(tenant_id, id).await
+    }
+
+    pub async fn get_ach_file_entry(
+        &self,
+        tenant_id: i32,
+        trace_number: String,
+    ) -> Result<AchFileEntry, Error> {
+        self.client
+            .get_ach_file_entry(tenant_id, trace_number)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn return_ach_entry(
+        &self,
+        trace_number: String,
+        return_code: String,
+        reversal: Transaction,
+        ledger_id: String,
+        journal_entry: JournalEntry,
+        journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
+    ) -> Result<Uuid, Error> {
+        self.client
+            .return_ach_entry(
+                trace_number,
+                return_code,
+                reversal,
+                ledger_id,
+                journal_entry,
+                journal_lines,
+                metadata,
+            )
+            .await
+    }
+
+    pub async fn create_webhook_endpoint(&self, endpoint: WebhookEndpoint) -> Result<Uuid, Error> {
+        self.client.create_webhook_endpoint(endpoint).await
+    }
+
+    pub async fn get_webhook_endpoints(
+        &self,
+        tenant_id: i32,
+    ) -> Result<Vec<WebhookEndpoint>, Error> {
+        self.client.get_webhook_endpoints(tenant_id).await
+    }
+
+    pub async fn disable_webhook_endpoint(&self, tenant_id: i32, id: Uuid) -> Result<(), Error> {
+        self.client.disable_webhook_endpoint(tenant_id, id).await
+    }
+
+    pub async fn enqueue_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        event_type: String,
+        payload: Value,
+    ) -> Result<u64, Error> {
+        self.client
+            .enqueue_webhook_deliveries(tenant_id, event_type, payload)
+            .await
+    }
+
+    pub async fn claim_webhook_deliveries(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>, Error> {
+        self.client
+            .claim_webhook_deliveries(worker_id, limit, lease_secs)
+            .await
+    }
+
+    pub async fn mark_webhook_delivered(&self, id: Uuid, worker_id: String) -> Result<(), Error> {
+        self.client.mark_webhook_delivered(id, worker_id).await
+    }
+
+    pub async fn mark_webhook_failed(
+        &self,
+        id: Uuid,
+        worker_id: String,
+        error: String,
+        next_attempt_at: Option<NaiveDateTime>,
+    ) -> Result<(), Error> {
+        self.client
+            .mark_webhook_failed(id, worker_id, error, next_attempt_at)
+            .await
+    }
+
+    pub async fn get_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        endpoint_id: Uuid,
+        status: Option<String>,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        self.client
+            .get_webhook_deliveries(tenant_id, endpoint_id, status)
+            .await
+    }
+
+    pub async fn replay_webhook_delivery(&self, tenant_id: i32, id: Uuid) -> Result<(), Error> {
+        self.client.replay_webhook_delivery(tenant_id, id).await
+    }
+
+    pub async fn create_asset(&self, asset: Asset) -> Result<Asset, Error> {
+        self.client.create_asset(asset).await
+    }
+
+    pub async fn get_assets(&self) -> Result<Vec<Asset>, Error> {
+        self.client.get_assets().await
+    }
 }
@@ -1,14 +1,20 @@
 use cqrs_es::{persist::PersistedEventStore, CqrsFramework, Query};
-use postgres_es::{PostgresCqrs, PostgresEventRepository, PostgresViewRepository};
+use postgres_es::{PostgresEventRepository, PostgresViewRepository};
 use sqlx::PgPool;
 use std::sync::Arc;
 use tracing::error;
 
 use crate::{
     domain::models::*,
-    event_sourcing::query::{AccountLogging, AccountQuery, LedgerLogging, LedgerQuery},
+    event_sourcing::history::EventHistory,
+    event_sourcing::query::{
+        AccountLogging, AccountQuery, AccountStream, LedgerLogging, LedgerQuery, LedgerStream,
+        WebhookDispatcher,
+    },
+    event_sourcing::upcaster::{bank_account_upcasters, ledger_upcasters},
     service::{BankAccountLogic, BankAccountServices, MockLedgerServices},
-    state::{BankAccountLoader, LedgerLoaderSaver},
+    state::{BankAccountLoader, BankAccountLoaderSaver, LedgerLoaderSaver},
+    stream::EventStream,
 };
 
 use super::adapter::Adapter;
@@ -16,10 +22,8 @@ use super::adapter::Adapter;
 pub fn configure_bank_account(
     pool: PgPool,
     ledger_loader_saver: LedgerLoaderSaver,
-) -> (
-    Arc<PostgresCqrs<BankAccount>>,
-    Arc<PostgresViewRepository<BankAccountView, BankAccount>>,
-) {
+    stream: Arc<EventStream>,
+) -> BankAccountLoaderSaver {
     // A very simple query that writes each event to stdout.
     let logging_query = AccountLogging {};
 
@@ -35,31 +39,41 @@ pub fn configure_bank_account(
     // Consider logging an error or panicking in your own application.
     account_query.use_error_handler(Box::new(|e| error!("{}", e)));
 
+    // Queues webhook deliveries for subscribed tenants.
+    let database = Arc::new(Adapter::new(pool.clone()));
+    let webhook_query = WebhookDispatcher::new(database.clone());
+
     // Create and return an event-sourced `CqrsFramework`.
-    let queries: Vec<Box<dyn Query<BankAccount>>> =
-        vec![Box::new(logging_query), Box::new(account_query)];
+    let queries: Vec<Box<dyn Query<BankAccount>>> = vec![
+        Box::new(logging_query),
+        Box::new(account_query),
+        Box::new(AccountStream::new(stream)),
+        Box::new(webhook_query),
+    ];
     let services = BankAccountServices::new(Box::new(BankAccountLogic {
         bank_account: BankAccountLoader {
             query: Arc::clone(&account_view_repo),
         },
         ledger: ledger_loader_saver,
-        database: Arc::new(Adapter::new(pool.clone())),
+        database,
     }));
 
+    // The event history is read outside of the framework.
+    let history = Arc::new(EventHistory::new(pool.clone(), "bank_account_events"));
     let repo = PostgresEventRepository::new(pool)
         .with_tables("bank_account_events", "bank_account_snapshots");
-    let store = PersistedEventStore::new_snapshot_store(repo, 3);
+    let store =
+        PersistedEventStore::new_snapshot_store(repo, 3).with_upcasters(bank_account_upcasters());
     let cqrs = CqrsFramework::new(store, queries, services);
 
-    (Arc::new(cqrs), account_view_repo)
+    BankAccountLoaderSaver {
+        cqrs: Arc::new(cqrs),
+        query: account_view_repo,
+        history,
+    }
 }
 
-pub fn configure_ledger(
-    pool: PgPool,
-) -> (
-    Arc<PostgresCqrs<Ledger>>,
-    Arc<PostgresViewRepository<LedgerView, Ledger>>,
-) {
+pub fn configure_ledger(pool: PgPool, stream: Arc<EventStream>) -> LedgerLoaderSaver {
     // A very simple query that writes each event to stdout.
     let logging_query = LedgerLogging {};
 
@@ -72,13 +86,31 @@ pub fn configure_ledger(
     // Consider logging an error or panicking in your own application.
     ledger_query.use_error_handler(Box::new(|e| error!("{}", e)));
 
+    // Queues webhook deliveries for subscribed tenants.
+    let webhook_query = WebhookDispatcher::new(Arc::new(Adapter::new(pool.clone())));
+
     // Create and return an event-sourced `CqrsFramework`.
-    let queries: Vec<Box<dyn Query<Ledger>>> =
-        vec![Box::new(logging_query), Box::new(ledger_query)];
+    let queries: Vec<Box<dyn Query<Ledger>>> = vec![
+        Box::new(logging_query),
+        Box::new(ledger_query),
+        Box::new(LedgerStream::new(stream, ledger_view_repo.clone())),
+        Box::new(webhook_query),
+    ];
 
+    // A second repository reads past balances outside of the framework, and
+    // the event history is read from the table.
+    let events = Arc::new(
+        PostgresEventRepository::new(pool.clone()).with_tables("ledger_events", "ledger_snapshots"),
+    );
+    let history = Arc::new(EventHistory::new(pool.clone(), "ledger_events"));
     let repo = PostgresEventRepository::new(pool).with_tables("ledger_events", "ledger_snapshots");
-    let store = PersistedEventStore::new_snapshot_store(repo, 3);
+    let store = PersistedEventStore::new_snapshot_store(repo, 3).with_upcasters(ledger_upcasters());
     let cqrs = CqrsFramework::new(store, queries, MockLedgerServices {});
 
-    (Arc::new(cqrs), ledger_view_repo)
+    LedgerLoaderSaver {
+        cqrs: Arc::new(cqrs),
+        query: ledger_view_repo,
+        events,
+        history,
+    }
 }
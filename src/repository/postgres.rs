@@ -1,22 +1,36 @@
 use crate::common::money::{Currency, Money};
-use crate::domain::finance::{JournalEntry, JournalLine, Outbox, Transaction};
+use crate::domain::asset::Asset;
+use crate::domain::diagnostics::{Diagnostics, LedgerTotal, OutboxBacklog, ProjectionLag};
+use crate::domain::finance::{
+    JournalEntry, JournalLine, Outbox, OutboxEntry, Transaction, OUTBOX_CREDIT, OUTBOX_DEAD,
+    OUTBOX_DEBIT, OUTBOX_PENDING, OUTBOX_PROCESSED, OUTBOX_SKIPPED,
+};
 use crate::domain::models::{BankAccountKind, HouseAccount, LedgerAction};
-use crate::domain::tenant::Tenant;
+use crate::domain::payment::{AchFile, AchFileEntry, ACH_ENTRY_RETURNED, ACH_ENTRY_SENT};
+use crate::domain::signing_key::SigningKey;
+use crate::domain::tenant::{Tenant, TenantAuditEntry, TenantSummary, TenantToken, TENANT_DELETED};
 use crate::domain::user::BankAccountWithLedger;
+use crate::domain::webhook::{
+    PendingWebhookDelivery, WebhookDelivery, WebhookEndpoint, TRANSACTION_COMPLETED,
+    TRANSACTION_FAILED, WEBHOOK_DEAD, WEBHOOK_DELIVERED, WEBHOOK_PENDING,
+};
 use crate::event_sourcing::command::LedgerCommand;
+use crate::event_sourcing::metadata::Metadata;
 
 use super::adapter::DatabaseClient;
 use async_trait::async_trait;
-use chrono::Local;
-use serde_json::to_value;
-use sqlx::postgres::PgPool;
+use chrono::{Local, NaiveDate, NaiveDateTime, Utc};
+use serde_json::{to_value, Value};
+use sqlx::postgres::{PgConnection, PgPool};
 use sqlx::Error;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[async_trait]
 impl DatabaseClient for PgPool {
     async fn get_user_bank_accounts(
         &self,
+        tenant_id: i32,
         user_id: String,
     ) -> Result<Vec<BankAccountWithLedger>, Error> {
         let accounts = sqlx::query_as!(
@@ -35,8 +49,10 @@ impl DatabaseClient for PgPool {
                     b.payload->>'updated_at' as updated_at
                 from bank_account_views b
                 left join ledger_views l on b.payload->>'ledger_id' = l.view_id
-                where b.payload->>'user_id' = $1;
+                where (b.payload->>'tenant_id')::integer = $1
+                and b.payload->>'user_id' = $2;
             "#,
+            tenant_id,
             user_id
         )
         .fetch_all(self)
@@ -45,60 +61,125 @@ impl DatabaseClient for PgPool {
         Ok(accounts)
     }
 
-    async fn fail_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
+    async fn get_bank_account_tenant(&self, account_id: String) -> Result<i32, Error> {
+        let tenant_id = sqlx::query!(
+            r#"
+            SELECT COALESCE((payload->>'tenant_id')::integer, 0) AS "tenant_id!"
+            FROM bank_account_views
+            WHERE view_id = $1
+            "#,
+            account_id
+        )
+        .fetch_one(self)
+        .await?
+        .tenant_id;
+
+        Ok(tenant_id)
+    }
+
+    async fn get_ledger_tenant(&self, ledger_id: String) -> Result<i32, Error> {
+        let tenant_id = sqlx::query!(
+            r#"
+            SELECT COALESCE((payload->>'tenant_id')::integer, 0) AS "tenant_id!"
+            FROM ledger_views
+            WHERE view_id = $1
+            "#,
+            ledger_id
+        )
+        .fetch_one(self)
+        .await?
+        .tenant_id;
+
+        Ok(tenant_id)
+    }
+
+    async fn fail_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+        error: String,
+        hold_released: bool,
+    ) -> Result<(), Error> {
         let mut tx = self.begin().await?;
 
+        // The commands are kept as dead letters so they can be inspected,
+        // retried or skipped, `error` is the failure of their last attempt.
         sqlx::query!(
             r#"
-            UPDATE transactions
-            SET status = 'failed', updated_at = NOW()
-            WHERE id = $1
+            UPDATE outbox
+            SET status = $2, attempts = attempts + 1, last_error = $4, hold_released = $5
+            WHERE transaction_id = $1
+            AND status = $3
+            AND claimed_by = $6 AND claimed_until > NOW()
+            RETURNING id
             "#,
             transaction_id,
+            OUTBOX_DEAD,
+            OUTBOX_PENDING,
+            error,
+            hold_released,
+            worker_id
         )
-        .execute(&mut *tx)
+        .fetch_one(&mut *tx)
         .await?;
 
         sqlx::query!(
             r#"
-            DELETE FROM outbox
-            WHERE transaction_id = $1
+            UPDATE transactions
+            SET status = 'failed', updated_at = NOW()
+            WHERE id = $1
             "#,
             transaction_id,
         )
         .execute(&mut *tx)
         .await?;
 
+        enqueue_transaction_webhooks(&mut tx, transaction_id, TRANSACTION_FAILED).await?;
+
         tx.commit().await?;
 
         Ok(())
     }
 
-    async fn complete_transaction(&self, transaction_id: Uuid) -> Result<(), Error> {
+    async fn complete_transaction(
+        &self,
+        transaction_id: Uuid,
+        worker_id: String,
+    ) -> Result<(), Error> {
         let mut tx = self.begin().await?;
 
+        // An instance whose lease expired leaves the commands to the instance
+        // that claimed them since.
         sqlx::query!(
             r#"
-            UPDATE transactions
-            SET status = 'completed', updated_at = NOW()
-            WHERE id = $1
+            UPDATE outbox
+            SET processed = true, processed_at = NOW(), status = $2, last_error = NULL
+            WHERE transaction_id = $1
+            AND status = $3
+            AND claimed_by = $4 AND claimed_until > NOW()
+            RETURNING id
             "#,
             transaction_id,
+            OUTBOX_PROCESSED,
+            OUTBOX_PENDING,
+            worker_id
         )
-        .execute(&mut *tx)
+        .fetch_one(&mut *tx)
         .await?;
 
         sqlx::query!(
             r#"
-            UPDATE outbox
-            SET processed = true, processed_at = NOW()
-            WHERE transaction_id = $1
+            UPDATE transactions
+            SET status = 'completed', updated_at = NOW()
+            WHERE id = $1
             "#,
             transaction_id,
         )
         .execute(&mut *tx)
         .await?;
 
+        enqueue_transaction_webhooks(&mut tx, transaction_id, TRANSACTION_COMPLETED).await?;
+
         tx.commit().await?;
 
         Ok(())
@@ -110,101 +191,18 @@ impl DatabaseClient for PgPool {
         ledger_id: String,
         journal_entry: JournalEntry,
         journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
     ) -> Result<Uuid, Error> {
         let mut tx = self.begin().await?;
-
-        // Insert JournalEntry
-        let journal_entry_id = sqlx::query!(
-            r#"
-            INSERT INTO journal_entries (id, entry_date, description, status)
-            VALUES ($1, $2, $3, $4)
-            RETURNING id
-            "#,
-            journal_entry.id,
-            journal_entry.entry_date,
-            journal_entry.description,
-            journal_entry.status
-        )
-        .fetch_one(&mut *tx)
-        .await?
-        .id;
-
-        // Insert JournalLines
-        for journal_line in journal_lines {
-            sqlx::query!(
-                r#"
-                INSERT INTO journal_lines (id, journal_entry_id, ledger_id, debit_amount, credit_amount, currency, description)
-                VALUES ($1, $2, $3, $4, $5, $6, $7)
-                "#,
-                journal_line.id,
-                journal_entry_id,
-                journal_line.ledger_id,
-                journal_line.debit_amount,
-                journal_line.credit_amount,
-                journal_line.currency,
-                journal_line.description
-            )
-            .execute(&mut *tx)
-            .await?;
-        }
-
-        // Insert Transaction
-        let transaction_id = sqlx::query!(
-            r#"
-            INSERT INTO transactions (id, bank_account_id, transaction_reference,
-            transaction_date, amount, currency, description, metadata, status, journal_entry_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-            RETURNING id
-            "#,
-            transaction.id,
-            transaction.bank_account_id,
-            transaction.transaction_reference,
-            transaction.transaction_date,
-            transaction.amount,
-            transaction.currency,
-            transaction.description,
-            transaction.metadata,
-            transaction.status,
-            journal_entry_id
-        )
-        .fetch_one(&mut *tx)
-        .await?
-        .id;
-
-        // Insert Outbox
-        let transaction_type = transaction.transaction_type();
-        let event_type = if transaction_type == LedgerAction::Deposit {
-            "LedgerCommand::Credit"
-        } else {
-            "LedgerCommand::Debit"
-        };
-        let cmd = if transaction_type == LedgerAction::Deposit {
-            LedgerCommand::Credit {
-                id: Uuid::parse_str(&ledger_id).unwrap(),
-                account_id: transaction.bank_account_id,
-                transaction_id,
-                amount: Money::new(transaction.amount, Currency::from(transaction.currency)),
-            }
-        } else {
-            LedgerCommand::DebitRelease {
-                id: Uuid::parse_str(&ledger_id).unwrap(),
-                account_id: transaction.bank_account_id,
-                transaction_id,
-                amount: Money::new(transaction.amount, Currency::from(transaction.currency)),
-            }
-        };
-        sqlx::query!(
-            r#"
-            INSERT INTO outbox (transaction_id, event_type, payload)
-            VALUES ($1, $2, $3)
-            "#,
-            transaction_id,
-            event_type,
-            to_value(&cmd).unwrap(),
+        let transaction_id = insert_transaction_with_journal(
+            &mut tx,
+            transaction,
+            ledger_id,
+            journal_entry,
+            journal_lines,
+            metadata,
         )
-        .execute(&mut *tx)
         .await?;
-
         tx.commit().await?;
 
         Ok(transaction_id)
@@ -213,8 +211,8 @@ impl DatabaseClient for PgPool {
     async fn create_house_account(&self, account: HouseAccount) -> Result<(), Error> {
         sqlx::query!(
             r#"
-            INSERT INTO house_accounts (id, account_number, account_name, account_type, ledger_id, currency, status)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO house_accounts (id, account_number, account_name, account_type, ledger_id, currency, status, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
             "#,
             account.id,
             account.account_number,
@@ -222,24 +220,31 @@ impl DatabaseClient for PgPool {
             account.account_type,
             account.ledger_id,
             account.currency.to_string(),
-            account.status
+            account.status,
+            account.tenant_id
         )
         .execute(self)
         .await?;
         Ok(())
     }
 
-    async fn get_house_account(&self, currency: Currency) -> Result<HouseAccount, Error> {
+    async fn get_house_account(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<HouseAccount, Error> {
         let house_account = sqlx::query_as!(
             HouseAccount,
             r#"
-            SELECT id, status, account_number, account_name, account_type, ledger_id, currency as "currency: String"
+            SELECT id, status, account_number, account_name, account_type, ledger_id, currency as "currency: Currency", tenant_id
             FROM house_accounts
-            WHERE currency = $1
+            WHERE tenant_id = $1
+            AND currency = $2
             AND status = 'active'
             AND account_type = 'House'
             LIMIT 1
             "#,
+            tenant_id,
             currency.to_string()
         )
         .fetch_one(self)
@@ -248,15 +253,21 @@ impl DatabaseClient for PgPool {
         Ok(house_account)
     }
 
-    async fn get_house_accounts(&self, currency: Currency) -> Result<Vec<HouseAccount>, Error> {
+    async fn get_house_accounts(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<Vec<HouseAccount>, Error> {
         let house_accounts = sqlx::query_as!(
             HouseAccount,
             r#"
-            SELECT id, status, account_number, account_name, account_type, ledger_id, currency as "currency: String"
+            SELECT id, status, account_number, account_name, account_type, ledger_id, currency as "currency: Currency", tenant_id
             FROM house_accounts
-            WHERE currency = $1
+            WHERE tenant_id = $1
+            AND currency = $2
             AND status = 'active'
             "#,
+            tenant_id,
             currency.to_string()
         )
         .fetch_all(self)
@@ -267,6 +278,7 @@ impl DatabaseClient for PgPool {
 
     async fn validate_bank_account_exists(
         &self,
+        tenant_id: i32,
         user_id: String,
         currency: Currency,
         kind: BankAccountKind,
@@ -274,11 +286,13 @@ impl DatabaseClient for PgPool {
         let count = sqlx::query!(
             r#"
             select count(1) as total from bank_account_views
-            where payload->>'user_id'=$1
-            and payload->>'currency'=$2
-            and payload->>'kind'=$3
+            where (payload->>'tenant_id')::integer = $1
+            and payload->>'user_id'=$2
+            and payload->>'currency'=$3
+            and payload->>'kind'=$4
             and payload->>'status' IN ('Pending', 'Approved', 'Freeze');
             "#,
+            tenant_id,
             user_id,
             currency.to_string(),
             kind.to_string()
@@ -287,14 +301,20 @@ impl DatabaseClient for PgPool {
         .await?
         .total;
 
-        if count.map_or(false, |value| value != 0) {
+        if count.is_some_and(|value| value != 0) {
             Ok(false)
         } else {
             Ok(true)
         }
     }
 
-    async fn create_tenant_profile(&self, name: &str, scope: &str) -> Result<i32, Error> {
+    async fn create_tenant_profile(
+        &self,
+        name: &str,
+        scope: &str,
+        audit: Option<TenantAuditEntry>,
+    ) -> Result<i32, Error> {
+        let mut tx = self.begin().await?;
         let rec = sqlx::query!(
             r#"
             INSERT INTO tenants (name, status, jwt, scope)
@@ -304,9 +324,18 @@ impl DatabaseClient for PgPool {
             name,
             scope
         )
-        .fetch_one(self)
+        .fetch_one(&mut *tx)
         .await?;
 
+        if let Some(entry) = audit {
+            let entry = TenantAuditEntry {
+                tenant_id: rec.id,
+                ..entry
+            };
+            insert_tenant_audit(&mut tx, entry).await?;
+        }
+        tx.commit().await?;
+
         Ok(rec.id)
     }
 
@@ -333,7 +362,7 @@ impl DatabaseClient for PgPool {
     async fn get_tenant_profile(&self, tenant_id: i32) -> Result<Tenant, Error> {
         let rec = sqlx::query!(
             r#"
-            SELECT id, name, jwt, status, scope
+            SELECT id, name, jwt, status, scope, rate_limits
             FROM tenants
             WHERE id = $1 AND status='active'
             "#,
@@ -348,58 +377,1656 @@ impl DatabaseClient for PgPool {
             jwt: rec.jwt,
             status: rec.status.expect("no status"),
             scope: Some(rec.scope.expect("no scope")),
+            // Validated when they are set, unreadable limits fall back to the
+            // settings.
+            rate_limits: serde_json::from_value(rec.rate_limits).unwrap_or_default(),
         })
     }
 
-    async fn get_unprocessed_outbox(&self) -> Result<Vec<Outbox>, Error> {
-        let outbox = sqlx::query_as!(
-            Outbox,
+    async fn get_tenant_client_secret_hash(&self, tenant_id: i32) -> Result<String, Error> {
+        let rec = sqlx::query!(
             r#"
-            SELECT id, transaction_id, event_type, payload, processed
-            FROM outbox
-            WHERE processed = false
+            SELECT client_secret_hash AS "client_secret_hash!"
+            FROM tenants
+            WHERE id = $1 AND status = 'active' AND client_secret_hash IS NOT NULL
+            "#,
+            tenant_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(rec.client_secret_hash)
+    }
+
+    async fn set_tenant_client_secret_hash(&self, tenant_id: i32, hash: &str) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE tenants
+            SET client_secret_hash = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id
+            "#,
+            tenant_id,
+            hash,
+            Local::now().naive_utc()
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_tenant_token(&self, token: TenantToken) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO tenant_tokens (jti, tenant_id, kind, scope, expires_at, revoked_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            token.jti,
+            token.tenant_id,
+            token.kind,
+            token.scope,
+            token.expires_at,
+            token.revoked_at
+        )
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_tenant_token(&self, jti: Uuid) -> Result<TenantToken, Error> {
+        let token = sqlx::query_as!(
+            TenantToken,
+            r#"
+            SELECT jti, tenant_id, kind, scope, expires_at, revoked_at
+            FROM tenant_tokens
+            WHERE jti = $1
+            "#,
+            jti
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn get_tenant_tokens(&self, tenant_id: i32) -> Result<Vec<TenantToken>, Error> {
+        let tokens = sqlx::query_as!(
+            TenantToken,
+            r#"
+            SELECT jti, tenant_id, kind, scope, expires_at, revoked_at
+            FROM tenant_tokens
+            WHERE tenant_id = $1 AND expires_at > $2
             ORDER BY created_at ASC
-            LIMIT 100
             "#,
+            tenant_id,
+            Utc::now().naive_utc()
         )
         .fetch_all(self)
         .await?;
 
-        Ok(outbox)
+        Ok(tokens)
     }
 
-    async fn get_transactions(
+    async fn revoke_tenant_token(
         &self,
-        bank_account_id: String,
-        offset: i64,
-        limit: i64,
-    ) -> Result<Vec<Transaction>, Error> {
-        let transactions = sqlx::query_as!(
-            Transaction,
+        tenant_id: i32,
+        jti: Uuid,
+        revoked_at: NaiveDateTime,
+    ) -> Result<TenantToken, Error> {
+        let token = sqlx::query_as!(
+            TenantToken,
             r#"
-            SELECT
-                id,
-                bank_account_id,
-                transaction_reference,
-                transaction_date,
-                amount,
-                currency,
-                description,
-                metadata,
-                status,
-                journal_entry_id
-            FROM transactions
-            WHERE bank_account_id = $1
-            ORDER BY created_at DESC
-            OFFSET $2 LIMIT $3
+            UPDATE tenant_tokens
+            SET revoked_at = LEAST(COALESCE(revoked_at, $3), $3)
+            WHERE jti = $1 AND tenant_id = $2
+            RETURNING jti, tenant_id, kind, scope, expires_at, revoked_at
             "#,
-            Uuid::parse_str(&bank_account_id).unwrap(),
-            offset,
-            limit,
+            jti,
+            tenant_id,
+            revoked_at
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn revoke_tenant_tokens(
+        &self,
+        tenant_id: i32,
+        revoked_at: NaiveDateTime,
+    ) -> Result<Vec<TenantToken>, Error> {
+        let tokens = sqlx::query_as!(
+            TenantToken,
+            r#"
+            UPDATE tenant_tokens
+            SET revoked_at = LEAST(COALESCE(revoked_at, $2), $2)
+            WHERE tenant_id = $1 AND expires_at > $2
+            RETURNING jti, tenant_id, kind, scope, expires_at, revoked_at
+            "#,
+            tenant_id,
+            revoked_at
         )
         .fetch_all(self)
         .await?;
 
-        Ok(transactions)
+        Ok(tokens)
+    }
+
+    async fn get_tenants(&self) -> Result<Vec<TenantSummary>, Error> {
+        let tenants = sqlx::query_as!(
+            TenantSummary,
+            r#"
+            SELECT id, name, status AS "status!", COALESCE(scope, '') AS "scope!",
+                rate_limits, created_at AS "created_at!", updated_at AS "updated_at!"
+            FROM tenants
+            ORDER BY id ASC
+            "#
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(tenants)
+    }
+
+    async fn get_tenant(&self, tenant_id: i32) -> Result<TenantSummary, Error> {
+        let tenant = sqlx::query_as!(
+            TenantSummary,
+            r#"
+            SELECT id, name, status AS "status!", COALESCE(scope, '') AS "scope!",
+                rate_limits, created_at AS "created_at!", updated_at AS "updated_at!"
+            FROM tenants
+            WHERE id = $1
+            "#,
+            tenant_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(tenant)
+    }
+
+    async fn update_tenant_status(
+        &self,
+        tenant_id: i32,
+        from: Vec<String>,
+        status: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        let mut tx = self.begin().await?;
+        let tenant = sqlx::query_as!(
+            TenantSummary,
+            r#"
+            UPDATE tenants
+            SET status = $3, updated_at = $4
+            WHERE id = $1 AND status = ANY($2)
+            RETURNING id, name, status AS "status!", COALESCE(scope, '') AS "scope!",
+                rate_limits, created_at AS "created_at!", updated_at AS "updated_at!"
+            "#,
+            tenant_id,
+            &from,
+            status,
+            Utc::now().naive_utc()
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        insert_tenant_audit(&mut tx, audit).await?;
+        tx.commit().await?;
+
+        Ok(tenant)
+    }
+
+    async fn update_tenant_scope(
+        &self,
+        tenant_id: i32,
+        scope: &str,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        let mut tx = self.begin().await?;
+        let tenant = sqlx::query_as!(
+            TenantSummary,
+            r#"
+            UPDATE tenants
+            SET scope = $2, updated_at = $3
+            WHERE id = $1 AND status <> $4
+            RETURNING id, name, status AS "status!", COALESCE(scope, '') AS "scope!",
+                rate_limits, created_at AS "created_at!", updated_at AS "updated_at!"
+            "#,
+            tenant_id,
+            scope,
+            Utc::now().naive_utc(),
+            TENANT_DELETED
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        insert_tenant_audit(&mut tx, audit).await?;
+        tx.commit().await?;
+
+        Ok(tenant)
+    }
+
+    async fn update_tenant_rate_limits(
+        &self,
+        tenant_id: i32,
+        rate_limits: Value,
+        audit: TenantAuditEntry,
+    ) -> Result<TenantSummary, Error> {
+        let mut tx = self.begin().await?;
+        let tenant = sqlx::query_as!(
+            TenantSummary,
+            r#"
+            UPDATE tenants
+            SET rate_limits = $2, updated_at = $3
+            WHERE id = $1 AND status <> $4
+            RETURNING id, name, status AS "status!", COALESCE(scope, '') AS "scope!",
+                rate_limits, created_at AS "created_at!", updated_at AS "updated_at!"
+            "#,
+            tenant_id,
+            rate_limits,
+            Utc::now().naive_utc(),
+            TENANT_DELETED
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        insert_tenant_audit(&mut tx, audit).await?;
+        tx.commit().await?;
+
+        Ok(tenant)
+    }
+
+    async fn create_tenant_audit(&self, entry: TenantAuditEntry) -> Result<(), Error> {
+        insert_tenant_audit(&mut *self.acquire().await?, entry).await
+    }
+
+    async fn get_tenant_audit(&self, tenant_id: i32) -> Result<Vec<TenantAuditEntry>, Error> {
+        let entries = sqlx::query_as!(
+            TenantAuditEntry,
+            r#"
+            SELECT id, tenant_id, action, actor_tenant_id, actor, detail, created_at
+            FROM tenant_audit_log
+            WHERE tenant_id = $1
+            ORDER BY id ASC
+            "#,
+            tenant_id
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(entries)
+    }
+
+    async fn get_signing_keys(&self) -> Result<Vec<SigningKey>, Error> {
+        let keys = sqlx::query_as!(
+            SigningKey,
+            r#"
+            SELECT kid, algorithm, private_key, public_jwk, status, created_at
+            FROM signing_keys
+            WHERE status = 'active'
+            ORDER BY created_at DESC
+            "#
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn create_signing_key(&self, key: SigningKey) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO signing_keys (kid, algorithm, private_key, public_jwk, status, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            key.kid,
+            key.algorithm,
+            key.private_key,
+            key.public_jwk,
+            key.status,
+            key.created_at
+        )
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn retire_signing_key(&self, kid: &str) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE signing_keys
+            SET status = 'retired', retired_at = $2
+            WHERE kid = $1 AND status = 'active'
+            RETURNING kid
+            "#,
+            kid,
+            Utc::now().naive_utc()
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn claim_outbox(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<Outbox>, Error> {
+        // Rows locked by a concurrent claim are skipped instead of waited on,
+        // so every instance gets a disjoint batch.
+        let outbox = sqlx::query_as!(
+            Outbox,
+            r#"
+            WITH claimed AS (
+                UPDATE outbox
+                SET claimed_by = $1, claimed_until = NOW() + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT id
+                    FROM outbox
+                    WHERE status = $3
+                    AND next_attempt_at <= NOW()
+                    AND (claimed_until IS NULL OR claimed_until < NOW())
+                    ORDER BY created_at ASC
+                    LIMIT $4
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, transaction_id, event_type, payload, metadata, attempts, created_at
+            )
+            SELECT id as "id!", transaction_id as "transaction_id!", event_type as "event_type!",
+                payload as "payload!", metadata as "metadata!", attempts as "attempts!"
+            FROM claimed
+            ORDER BY created_at ASC
+            "#,
+            worker_id,
+            lease_secs as f64,
+            OUTBOX_PENDING,
+            limit
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(outbox)
+    }
+
+    async fn release_outbox_claims(&self, worker_id: String) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE outbox
+            SET claimed_by = NULL, claimed_until = NULL
+            WHERE claimed_by = $1
+            AND status = $2
+            "#,
+            worker_id,
+            OUTBOX_PENDING
+        )
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        sqlx::query!("SELECT 1 AS one").fetch_one(self).await?;
+
+        Ok(())
+    }
+
+    async fn get_outbox_backlog(&self) -> Result<OutboxBacklog, Error> {
+        let backlog = sqlx::query_as!(
+            OutboxBacklog,
+            r#"
+            SELECT COUNT(*) FILTER (WHERE status = $1) AS "pending!",
+                COUNT(*) FILTER (WHERE status = $2) AS "dead!",
+                EXTRACT(EPOCH FROM NOW()::timestamp - MIN(created_at) FILTER (WHERE status = $1))::bigint
+                    AS oldest_pending_secs
+            FROM outbox
+            WHERE status IN ($1, $2)
+            "#,
+            OUTBOX_PENDING,
+            OUTBOX_DEAD
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(backlog)
+    }
+
+    async fn get_ledger_totals(&self) -> Result<Vec<LedgerTotal>, Error> {
+        let totals = sqlx::query_as!(
+            LedgerTotal,
+            r#"
+            SELECT payload->'available'->>'currency' AS "currency!",
+                COALESCE(SUM((payload->'available'->>'amount')::numeric), 0) AS "available!",
+                COALESCE(SUM((payload->'pending'->>'amount')::numeric), 0) AS "pending!",
+                COALESCE(SUM((payload->'current'->>'amount')::numeric), 0) AS "current!"
+            FROM ledger_views
+            GROUP BY 1
+            ORDER BY 1
+            "#
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(totals)
+    }
+
+    async fn get_diagnostics(&self, stuck_transaction_secs: i64) -> Result<Diagnostics, Error> {
+        let outbox = self.get_outbox_backlog().await?;
+
+        let stuck_transactions = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM transactions
+            WHERE status = 'processing'
+            AND updated_at < NOW() - make_interval(secs => $1)
+            "#,
+            stuck_transaction_secs as f64
+        )
+        .fetch_one(self)
+        .await?;
+
+        // Compares the last event of every aggregate with the last one applied
+        // to its view. The version counts writes, which a replay adds to, so it
+        // only stands in for views written before they recorded the sequence.
+        // A full scan of the event tables.
+        let bank_account_views = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "aggregates_behind!",
+                COALESCE(
+                    MAX(e.sequence - COALESCE((v.payload->>'sequence')::bigint, v.version, 0)),
+                    0
+                ) AS "max_events_behind!"
+            FROM (
+                SELECT aggregate_id, MAX(sequence) AS sequence
+                FROM bank_account_events
+                GROUP BY aggregate_id
+            ) e
+            LEFT JOIN bank_account_views v ON v.view_id = e.aggregate_id
+            WHERE e.sequence > COALESCE((v.payload->>'sequence')::bigint, v.version, 0)
+            "#
+        )
+        .fetch_one(self)
+        .await?;
+
+        let ledger_views = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "aggregates_behind!",
+                COALESCE(
+                    MAX(e.sequence - COALESCE((v.payload->>'sequence')::bigint, v.version, 0)),
+                    0
+                ) AS "max_events_behind!"
+            FROM (
+                SELECT aggregate_id, MAX(sequence) AS sequence
+                FROM ledger_events
+                GROUP BY aggregate_id
+            ) e
+            LEFT JOIN ledger_views v ON v.view_id = e.aggregate_id
+            WHERE e.sequence > COALESCE((v.payload->>'sequence')::bigint, v.version, 0)
+            "#
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(Diagnostics {
+            outbox,
+            stuck_transactions,
+            projections: vec![
+                ProjectionLag {
+                    projection: "bank_account_views".to_string(),
+                    aggregates_behind: bank_account_views.aggregates_behind,
+                    max_events_behind: bank_account_views.max_events_behind,
+                },
+                ProjectionLag {
+                    projection: "ledger_views".to_string(),
+                    aggregates_behind: ledger_views.aggregates_behind,
+                    max_events_behind: ledger_views.max_events_behind,
+                },
+            ],
+        })
+    }
+
+    async fn mark_outbox_failed(
+        &self,
+        id: i32,
+        worker_id: String,
+        error: String,
+        next_attempt_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE outbox
+            SET attempts = attempts + 1, last_error = $2, next_attempt_at = $3,
+                claimed_by = NULL, claimed_until = NULL
+            WHERE id = $1
+            AND claimed_by = $4 AND claimed_until > NOW()
+            RETURNING id
+            "#,
+            id,
+            error,
+            next_attempt_at,
+            worker_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_outbox_entries(
+        &self,
+        tenant_id: i32,
+        status: String,
+    ) -> Result<Vec<OutboxEntry>, Error> {
+        let entries = sqlx::query_as!(
+            OutboxEntry,
+            r#"
+            SELECT o.id, o.transaction_id, o.event_type, o.payload, o.status, o.attempts,
+                o.next_attempt_at, o.last_error, o.hold_released, o.created_at
+            FROM outbox o
+            JOIN transactions t ON t.id = o.transaction_id
+            WHERE t.tenant_id = $1
+            AND o.status = $2
+            ORDER BY o.created_at DESC
+            LIMIT 100
+            "#,
+            tenant_id,
+            status
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(entries)
+    }
+
+    async fn get_outbox_entry(&self, tenant_id: i32, id: i32) -> Result<OutboxEntry, Error> {
+        let entry = sqlx::query_as!(
+            OutboxEntry,
+            r#"
+            SELECT o.id, o.transaction_id, o.event_type, o.payload, o.status, o.attempts,
+                o.next_attempt_at, o.last_error, o.hold_released, o.created_at
+            FROM outbox o
+            JOIN transactions t ON t.id = o.transaction_id
+            WHERE o.id = $1
+            AND t.tenant_id = $2
+            "#,
+            id,
+            tenant_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(entry)
+    }
+
+    async fn retry_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error> {
+        let mut tx = self.begin().await?;
+
+        let transaction_id = sqlx::query!(
+            r#"
+            UPDATE outbox o
+            SET status = $3, attempts = 0, next_attempt_at = NOW(), last_error = NULL,
+                hold_released = false, claimed_by = NULL, claimed_until = NULL
+            FROM transactions t
+            WHERE o.id = $1 AND o.transaction_id = t.id AND t.tenant_id = $2
+            AND o.status = $4
+            RETURNING o.transaction_id
+            "#,
+            id,
+            tenant_id,
+            OUTBOX_PENDING,
+            OUTBOX_DEAD
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .transaction_id;
+
+        sqlx::query!(
+            r#"
+            UPDATE transactions
+            SET status = 'processing', updated_at = NOW()
+            WHERE id = $1
+            "#,
+            transaction_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn skip_outbox(&self, tenant_id: i32, id: i32) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE outbox o
+            SET status = $3
+            FROM transactions t
+            WHERE o.id = $1 AND o.transaction_id = t.id AND t.tenant_id = $2
+            AND o.status = $4
+            RETURNING o.id
+            "#,
+            id,
+            tenant_id,
+            OUTBOX_SKIPPED,
+            OUTBOX_DEAD
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        tenant_id: i32,
+        bank_account_id: String,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Transaction>, Error> {
+        let transactions = sqlx::query_as!(
+            Transaction,
+            r#"
+            SELECT
+                id,
+                bank_account_id,
+                transaction_reference,
+                transaction_date,
+                amount,
+                currency,
+                description,
+                metadata,
+                status,
+                journal_entry_id,
+                tenant_id
+            FROM transactions
+            WHERE tenant_id = $1 AND bank_account_id = $2
+            ORDER BY created_at DESC
+            OFFSET $3 LIMIT $4
+            "#,
+            tenant_id,
+            Uuid::parse_str(&bank_account_id).unwrap(),
+            offset,
+            limit,
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(transactions)
+    }
+
+    async fn get_pending_ach_withdrawals(&self, tenant_id: i32) -> Result<Vec<Transaction>, Error> {
+        let transactions = sqlx::query_as!(
+            Transaction,
+            r#"
+            SELECT
+                t.id,
+                t.bank_account_id,
+                t.transaction_reference,
+                t.transaction_date,
+                t.amount,
+                t.currency,
+                t.description,
+                t.metadata,
+                t.status,
+                t.journal_entry_id,
+                t.tenant_id
+            FROM transactions t
+            WHERE t.tenant_id = $1
+            AND t.status = 'completed'
+            AND t.transaction_reference LIKE 'WI%'
+            AND t.currency = 'USD'
+            AND t.metadata->'destination' IS NOT NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM ach_file_entries e WHERE e.transaction_id = t.id
+            )
+            ORDER BY t.created_at ASC
+            "#,
+            tenant_id
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(transactions)
+    }
+
+    async fn reserve_ach_trace_numbers(&self, count: i64) -> Result<Vec<i64>, Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT nextval('ach_trace_seq') AS "trace!"
+            FROM generate_series(1, $1::bigint)
+            "#,
+            count
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| r.trace).collect())
+    }
+
+    async fn count_ach_files_created_on(&self, date: NaiveDate) -> Result<i64, Error> {
+        let total = sqlx::query!(
+            r#"
+            SELECT count(1) AS "total!" FROM ach_files
+            WHERE created_at::date = $1
+            "#,
+            date
+        )
+        .fetch_one(self)
+        .await?
+        .total;
+
+        Ok(total)
+    }
+
+    async fn create_ach_file(
+        &self,
+        file: AchFile,
+        entries: Vec<AchFileEntry>,
+    ) -> Result<Uuid, Error> {
+        let mut tx = self.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO ach_files (id, file_id_modifier, entry_count, total_credit, entry_hash, content, created_at, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            file.id,
+            file.file_id_modifier,
+            file.entry_count,
+            file.total_credit,
+            file.entry_hash,
+            file.content,
+            file.created_at,
+            file.tenant_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for entry in entries {
+            sqlx::query!(
+                r#"
+                INSERT INTO ach_file_entries (trace_number, ach_file_id, transaction_id, bank_account_id, amount, status)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+                entry.trace_number,
+                file.id,
+                entry.transaction_id,
+                entry.bank_account_id,
+                entry.amount,
+                entry.status
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(file.id)
+    }
+
+    async fn get_ach_file(&self, tenant_id: i32, id: Uuid) -> Result<AchFile, Error> {
+        let file = sqlx::query_as!(
+            AchFile,
+            r#"
+            SELECT id, file_id_modifier, entry_count, total_credit, entry_hash, content, created_at, tenant_id
+            FROM ach_files
+            WHERE tenant_id = $1 AND id = $2
+            "#,
+            tenant_id,
+            id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(file)
+    }
+
+    async fn get_ach_file_entry(
+        &self,
+        tenant_id: i32,
+        trace_number: String,
+    ) -> Result<AchFileEntry, Error> {
+        let entry = sqlx::query_as!(
+            AchFileEntry,
+            r#"
+            SELECT e.trace_number, e.ach_file_id, e.transaction_id, e.bank_account_id, e.amount, e.status, e.return_code
+            FROM ach_file_entries e
+            JOIN ach_files f ON f.id = e.ach_file_id
+            WHERE f.tenant_id = $1 AND e.trace_number = $2
+            "#,
+            tenant_id,
+            trace_number
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(entry)
+    }
+
+    async fn return_ach_entry(
+        &self,
+        trace_number: String,
+        return_code: String,
+        reversal: Transaction,
+        ledger_id: String,
+        journal_entry: JournalEntry,
+        journal_lines: Vec<JournalLine>,
+        metadata: Metadata,
+    ) -> Result<Uuid, Error> {
+        let mut tx = self.begin().await?;
+
+        // Only entries that are still outstanding can be returned, which keeps
+        // reprocessing the same return file from reversing twice.
+        let transaction_id = sqlx::query!(
+            r#"
+            UPDATE ach_file_entries
+            SET status = $3, return_code = $2, returned_at = NOW()
+            WHERE trace_number = $1 AND status = $4
+            RETURNING transaction_id
+            "#,
+            trace_number,
+            return_code,
+            ACH_ENTRY_RETURNED,
+            ACH_ENTRY_SENT,
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .transaction_id;
+
+        sqlx::query!(
+            r#"
+            UPDATE transactions
+            SET status = 'returned', updated_at = NOW()
+            WHERE id = $1
+            "#,
+            transaction_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let reversal_id = insert_transaction_with_journal(
+            &mut tx,
+            reversal,
+            ledger_id,
+            journal_entry,
+            journal_lines,
+            metadata,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(reversal_id)
+    }
+
+    async fn create_webhook_endpoint(&self, endpoint: WebhookEndpoint) -> Result<Uuid, Error> {
+        let rec = sqlx::query!(
+            r#"
+            INSERT INTO webhook_endpoints (id, tenant_id, url, secret, event_types, status)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+            endpoint.id,
+            endpoint.tenant_id,
+            endpoint.url,
+            endpoint.secret,
+            &endpoint.event_types,
+            endpoint.status
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(rec.id)
+    }
+
+    async fn get_webhook_endpoints(&self, tenant_id: i32) -> Result<Vec<WebhookEndpoint>, Error> {
+        let endpoints = sqlx::query_as!(
+            WebhookEndpoint,
+            r#"
+            SELECT id, tenant_id, url, secret, event_types, status
+            FROM webhook_endpoints
+            WHERE tenant_id = $1 AND status = 'active'
+            ORDER BY created_at ASC
+            "#,
+            tenant_id
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(endpoints)
+    }
+
+    async fn disable_webhook_endpoint(&self, tenant_id: i32, id: Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE webhook_endpoints
+            SET status = 'inactive'
+            WHERE id = $1 AND tenant_id = $2 AND status = 'active'
+            RETURNING id
+            "#,
+            id,
+            tenant_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn enqueue_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        event_type: String,
+        payload: Value,
+    ) -> Result<u64, Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (id, endpoint_id, event_type, payload)
+            SELECT gen_random_uuid(), e.id, $2::text, $3
+            FROM webhook_endpoints e
+            WHERE e.tenant_id = $1 AND e.status = 'active' AND $2::text = ANY(e.event_types)
+            "#,
+            tenant_id,
+            event_type,
+            payload
+        )
+        .execute(self)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        worker_id: String,
+        limit: i64,
+        lease_secs: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>, Error> {
+        // Like `claim_outbox`, concurrent claims get disjoint batches.
+        let deliveries = sqlx::query_as!(
+            PendingWebhookDelivery,
+            r#"
+            WITH claimed AS (
+                UPDATE webhook_deliveries
+                SET claimed_by = $1, claimed_until = NOW() + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT d.id
+                    FROM webhook_deliveries d
+                    JOIN webhook_endpoints e ON e.id = d.endpoint_id
+                    WHERE d.status = $3
+                    AND d.next_attempt_at <= NOW()
+                    AND (d.claimed_until IS NULL OR d.claimed_until < NOW())
+                    AND e.status = 'active'
+                    ORDER BY d.next_attempt_at ASC
+                    LIMIT $4
+                    FOR UPDATE OF d SKIP LOCKED
+                )
+                RETURNING id, endpoint_id, event_type, payload, attempts, next_attempt_at
+            )
+            SELECT c.id as "id!", e.url as "url!", e.secret as "secret!",
+                c.event_type as "event_type!", c.payload as "payload!", c.attempts as "attempts!"
+            FROM claimed c
+            JOIN webhook_endpoints e ON e.id = c.endpoint_id
+            ORDER BY c.next_attempt_at ASC
+            "#,
+            worker_id,
+            lease_secs as f64,
+            WEBHOOK_PENDING,
+            limit
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn mark_webhook_delivered(&self, id: Uuid, worker_id: String) -> Result<(), Error> {
+        // A delivery finished after its lease expired still counts, unless
+        // another instance claimed it meanwhile.
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = attempts + 1, delivered_at = NOW(), last_error = NULL,
+                claimed_by = NULL, claimed_until = NULL
+            WHERE id = $1
+            AND claimed_by = $3
+            RETURNING id
+            "#,
+            id,
+            WEBHOOK_DELIVERED,
+            worker_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_webhook_failed(
+        &self,
+        id: Uuid,
+        worker_id: String,
+        error: String,
+        next_attempt_at: Option<NaiveDateTime>,
+    ) -> Result<(), Error> {
+        // Without a next attempt the delivery is moved to the dead-letter state.
+        let status = if next_attempt_at.is_some() {
+            WEBHOOK_PENDING
+        } else {
+            WEBHOOK_DEAD
+        };
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2,
+                attempts = attempts + 1,
+                last_error = $3,
+                next_attempt_at = COALESCE($4, next_attempt_at),
+                claimed_by = NULL,
+                claimed_until = NULL
+            WHERE id = $1
+            AND claimed_by = $5
+            RETURNING id
+            "#,
+            id,
+            status,
+            error,
+            next_attempt_at,
+            worker_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        tenant_id: i32,
+        endpoint_id: Uuid,
+        status: Option<String>,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT d.id, d.endpoint_id, d.event_type, d.payload, d.status, d.attempts,
+                d.next_attempt_at, d.last_error, d.created_at, d.delivered_at
+            FROM webhook_deliveries d
+            JOIN webhook_endpoints e ON e.id = d.endpoint_id
+            WHERE d.endpoint_id = $1
+            AND e.tenant_id = $2
+            AND ($3::text IS NULL OR d.status = $3)
+            ORDER BY d.created_at DESC
+            LIMIT 100
+            "#,
+            endpoint_id,
+            tenant_id,
+            status
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn replay_webhook_delivery(&self, tenant_id: i32, id: Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries d
+            SET status = $3, attempts = 0, next_attempt_at = NOW(), last_error = NULL
+            FROM webhook_endpoints e
+            WHERE d.id = $1 AND d.endpoint_id = e.id AND e.tenant_id = $2
+            RETURNING d.id
+            "#,
+            id,
+            tenant_id,
+            WEBHOOK_PENDING
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_asset(&self, asset: Asset) -> Result<Asset, Error> {
+        let asset = sqlx::query_as!(
+            Asset,
+            r#"
+            INSERT INTO assets (code, tenant_id, name, minor_units)
+            VALUES ($1, $2, $3, $4)
+            RETURNING code, tenant_id, name, minor_units, created_at
+            "#,
+            asset.code,
+            asset.tenant_id,
+            asset.name,
+            asset.minor_units
+        )
+        .fetch_one(self)
+        .await?;
+
+        Ok(asset)
+    }
+
+    async fn get_assets(&self) -> Result<Vec<Asset>, Error> {
+        let assets = sqlx::query_as!(
+            Asset,
+            r#"
+            SELECT code, tenant_id, name, minor_units, created_at
+            FROM assets
+            ORDER BY code
+            "#
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(assets)
+    }
+}
+
+// Inserts the journal, transaction and the outbox command that later settles the
+// ledger, so callers can compose it with other statements in one transaction.
+// `metadata` is stored with the command and written with the ledger events.
+async fn insert_transaction_with_journal(
+    conn: &mut PgConnection,
+    transaction: Transaction,
+    ledger_id: String,
+    journal_entry: JournalEntry,
+    journal_lines: Vec<JournalLine>,
+    metadata: Metadata,
+) -> Result<Uuid, Error> {
+    // Insert JournalEntry
+    let journal_entry_id = sqlx::query!(
+        r#"
+        INSERT INTO journal_entries (id, entry_date, description, status)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        journal_entry.id,
+        journal_entry.entry_date,
+        journal_entry.description,
+        journal_entry.status
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    // Insert JournalLines
+    for journal_line in journal_lines {
+        sqlx::query!(
+            r#"
+            INSERT INTO journal_lines (id, journal_entry_id, ledger_id, debit_amount, credit_amount, currency, description)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            journal_line.id,
+            journal_entry_id,
+            journal_line.ledger_id,
+            journal_line.debit_amount,
+            journal_line.credit_amount,
+            journal_line.currency,
+            journal_line.description
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // Insert Transaction
+    let transaction_id = sqlx::query!(
+        r#"
+        INSERT INTO transactions (id, bank_account_id, transaction_reference,
+        transaction_date, amount, currency, description, metadata, status, journal_entry_id, tenant_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING id
+        "#,
+        transaction.id,
+        transaction.bank_account_id,
+        transaction.transaction_reference,
+        transaction.transaction_date,
+        transaction.amount,
+        transaction.currency,
+        transaction.description,
+        transaction.metadata,
+        transaction.status,
+        journal_entry_id,
+        transaction.tenant_id
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    // Insert Outbox
+    let transaction_type = transaction.transaction_type();
+    let event_type = if transaction_type == LedgerAction::Deposit {
+        OUTBOX_CREDIT
+    } else {
+        OUTBOX_DEBIT
+    };
+    let currency =
+        Currency::from_str(&transaction.currency).map_err(|err| Error::Decode(Box::new(err)))?;
+    let cmd = if transaction_type == LedgerAction::Deposit {
+        LedgerCommand::Credit {
+            id: Uuid::parse_str(&ledger_id).unwrap(),
+            account_id: transaction.bank_account_id,
+            transaction_id,
+            amount: Money::new(transaction.amount, currency),
+        }
+    } else {
+        LedgerCommand::DebitRelease {
+            id: Uuid::parse_str(&ledger_id).unwrap(),
+            account_id: transaction.bank_account_id,
+            transaction_id,
+            amount: Money::new(transaction.amount, currency),
+        }
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO outbox (transaction_id, event_type, payload, metadata)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        transaction_id,
+        event_type,
+        to_value(&cmd).unwrap(),
+        to_value(&metadata).unwrap(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(transaction_id)
+}
+
+// Queues a webhook with the transaction row for every endpoint of the
+// transaction's tenant subscribed to `event_type`, in the same transaction that
+// changes the transaction status.
+async fn enqueue_transaction_webhooks(
+    conn: &mut PgConnection,
+    transaction_id: Uuid,
+    event_type: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_deliveries (id, endpoint_id, event_type, payload)
+        SELECT gen_random_uuid(), e.id, $2::text,
+            jsonb_build_object(
+                'type', $2::text,
+                'aggregate_id', t.id,
+                'created_at', NOW(),
+                'data', to_jsonb(t)
+            )
+        FROM webhook_endpoints e, transactions t
+        WHERE t.id = $1 AND e.tenant_id = t.tenant_id
+        AND e.status = 'active' AND $2::text = ANY(e.event_types)
+        "#,
+        transaction_id,
+        event_type
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Records a tenant change in the audit log, so callers can write it in the
+// transaction of the change.
+async fn insert_tenant_audit(
+    conn: &mut PgConnection,
+    entry: TenantAuditEntry,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tenant_audit_log
+            (tenant_id, action, actor_tenant_id, actor, detail, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        entry.tenant_id,
+        entry.action,
+        entry.actor_tenant_id,
+        entry.actor,
+        entry.detail,
+        entry.created_at
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// These run against the database of `config.local.yaml` with the migrations
+// applied: `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::settings::SETTINGS;
+
+    async fn pool() -> PgPool {
+        PgPool::connect(&SETTINGS.database.connection_string())
+            .await
+            .unwrap()
+    }
+
+    async fn create_tenant(pool: &PgPool) -> i32 {
+        sqlx::query_scalar("INSERT INTO tenants (name, jwt) VALUES ('test', '') RETURNING id")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn create_endpoint(pool: &PgPool, tenant_id: i32) -> Uuid {
+        pool.create_webhook_endpoint(WebhookEndpoint {
+            id: Uuid::new_v4(),
+            tenant_id,
+            url: "https://example.com/hook".to_string(),
+            secret: "secret".to_string(),
+            event_types: vec![TRANSACTION_COMPLETED.to_string()],
+            status: "active".to_string(),
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn create_transaction(pool: &PgPool, tenant_id: i32) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO transactions (id, bank_account_id, transaction_reference, transaction_date,
+                amount, currency, status, tenant_id)
+            VALUES ($1, $2, $3, CURRENT_DATE, 10, 'USD', 'processing', $4)",
+        )
+        .bind(id)
+        .bind(Uuid::new_v4())
+        .bind(id.to_string())
+        .bind(tenant_id)
+        .execute(pool)
+        .await
+        .unwrap();
+        id
+    }
+
+    // Queues a command ahead of every other pending one, so claiming a single
+    // command picks it.
+    async fn queue_command(pool: &PgPool, transaction_id: Uuid) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO outbox (transaction_id, event_type, payload, created_at)
+            VALUES ($1, $2, '{}', '2000-01-01') RETURNING id",
+        )
+        .bind(transaction_id)
+        .bind(OUTBOX_CREDIT)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn delivery_count(pool: &PgPool, endpoint_id: Uuid) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM webhook_deliveries WHERE endpoint_id = $1")
+            .bind(endpoint_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_webhooks_stay_within_tenant() {
+        let pool = pool().await;
+        let (tenant, other_tenant) = (create_tenant(&pool).await, create_tenant(&pool).await);
+        let endpoint = create_endpoint(&pool, tenant).await;
+        let other_endpoint = create_endpoint(&pool, other_tenant).await;
+        let transaction_id = create_transaction(&pool, tenant).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue_transaction_webhooks(&mut conn, transaction_id, TRANSACTION_COMPLETED)
+            .await
+            .unwrap();
+        let queued = pool
+            .enqueue_webhook_deliveries(
+                tenant,
+                TRANSACTION_COMPLETED.to_string(),
+                serde_json::json!({}),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(queued, 1);
+        assert_eq!(delivery_count(&pool, endpoint).await, 2);
+        assert_eq!(delivery_count(&pool, other_endpoint).await, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_expired_claim_is_taken_over() {
+        let pool = pool().await;
+        let tenant = create_tenant(&pool).await;
+        let transaction_id = create_transaction(&pool, tenant).await;
+        let id = queue_command(&pool, transaction_id).await;
+
+        let claimed = pool.claim_outbox("a".to_string(), 1, 300).await.unwrap();
+        assert_eq!(claimed[0].id, id);
+        sqlx::query("UPDATE outbox SET claimed_until = NOW() - INTERVAL '1 second' WHERE id = $1")
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let claimed = pool.claim_outbox("b".to_string(), 1, 300).await.unwrap();
+        assert_eq!(claimed[0].id, id);
+
+        // The first instance came back after its lease expired.
+        let retry_at = Utc::now().naive_utc();
+        assert!(matches!(
+            pool.mark_outbox_failed(id, "a".to_string(), "late".to_string(), retry_at)
+                .await,
+            Err(Error::RowNotFound)
+        ));
+        assert!(matches!(
+            pool.complete_transaction(transaction_id, "a".to_string())
+                .await,
+            Err(Error::RowNotFound)
+        ));
+        pool.complete_transaction(transaction_id, "b".to_string())
+            .await
+            .unwrap();
+
+        let status: String = sqlx::query_scalar("SELECT status FROM outbox WHERE id = $1")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, OUTBOX_PROCESSED);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_claimed_webhook_deliveries_are_skipped() {
+        let pool = pool().await;
+        let tenant = create_tenant(&pool).await;
+        let endpoint = create_endpoint(&pool, tenant).await;
+        pool.enqueue_webhook_deliveries(
+            tenant,
+            TRANSACTION_COMPLETED.to_string(),
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap();
+
+        let claimed = pool
+            .claim_webhook_deliveries("a".to_string(), i64::MAX, 300)
+            .await
+            .unwrap();
+        let id: Uuid =
+            sqlx::query_scalar("SELECT id FROM webhook_deliveries WHERE endpoint_id = $1")
+                .bind(endpoint)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(claimed.iter().any(|delivery| delivery.id == id));
+        let claimed = pool
+            .claim_webhook_deliveries("b".to_string(), i64::MAX, 300)
+            .await
+            .unwrap();
+        assert!(claimed.iter().all(|delivery| delivery.id != id));
+
+        assert!(matches!(
+            pool.mark_webhook_delivered(id, "b".to_string()).await,
+            Err(Error::RowNotFound)
+        ));
+        pool.mark_webhook_delivered(id, "a".to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_replayed_view_is_not_behind() {
+        use crate::event_sourcing::replay::{replay, ReplayOptions};
+
+        async fn ledger_lag(pool: &PgPool) -> ProjectionLag {
+            let diagnostics = pool.get_diagnostics(300).await.unwrap();
+            diagnostics
+                .projections
+                .into_iter()
+                .find(|lag| lag.projection == "ledger_views")
+                .unwrap()
+        }
+
+        let pool = pool().await;
+        let before = ledger_lag(&pool).await;
+        let fixtures: Vec<Value> = serde_json::from_str(include_str!(
+            "../../tests/fixtures/events/ledger_events.json"
+        ))
+        .unwrap();
+        let aggregate_id = Uuid::new_v4().to_string();
+        for (i, fixture) in fixtures.iter().take(3).enumerate() {
+            sqlx::query(
+                "INSERT INTO ledger_events (aggregate_type, aggregate_id, sequence, event_type,
+                    event_version, payload, metadata)
+                VALUES ('ledger', $1, $2, $3, $4, $5::json, '{}')",
+            )
+            .bind(&aggregate_id)
+            .bind(i as i64 + 1)
+            .bind(fixture["event_type"].as_str())
+            .bind(fixture["event_version"].as_str())
+            .bind(&fixture["payload"])
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        assert_eq!(
+            ledger_lag(&pool).await.aggregates_behind,
+            before.aggregates_behind + 1
+        );
+
+        // Every replay writes the view once more, its version no longer
+        // matches the number of events.
+        let options = ReplayOptions {
+            aggregate_ids: vec![aggregate_id],
+            batch_size: 1,
+            shadow: false,
+        };
+        for _ in 0..2 {
+            replay(&pool, &["ledger_views".to_string()], &options)
+                .await
+                .unwrap();
+            assert_eq!(ledger_lag(&pool).await, before);
+        }
+    }
+
+    // An entry the audit log rejects, its action is longer than 50 characters.
+    fn rejected_audit(tenant_id: i32) -> TenantAuditEntry {
+        TenantAuditEntry {
+            id: 0,
+            tenant_id,
+            action: "x".repeat(51),
+            actor_tenant_id: tenant_id,
+            actor: "test".to_string(),
+            detail: Value::Null,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_tenant_change_is_rolled_back_without_audit() {
+        let pool = pool().await;
+        let tenant = create_tenant(&pool).await;
+        let before = pool.get_tenant(tenant).await.unwrap();
+
+        let from = vec![before.status.clone()];
+        let result = pool
+            .update_tenant_status(tenant, from, "suspended", rejected_audit(tenant))
+            .await;
+        assert!(result.is_err());
+        let result = pool
+            .update_tenant_scope(tenant, "ach:admin", rejected_audit(tenant))
+            .await;
+        assert!(result.is_err());
+
+        let after = pool.get_tenant(tenant).await.unwrap();
+        assert_eq!((after.status, after.scope), (before.status, before.scope));
+        assert!(pool.get_tenant_audit(tenant).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_tenant_is_not_created_without_audit() {
+        let pool = pool().await;
+        let name = Uuid::new_v4().to_string();
+
+        let result = pool
+            .create_tenant_profile(&name, "", Some(rejected_audit(0)))
+            .await;
+
+        assert!(result.is_err());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tenants WHERE name = $1")
+            .bind(&name)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database"]
+    async fn test_event_history_loads_one_page() {
+        use crate::event_sourcing::history::{event_history, EventHistory, EventPage};
+        use crate::event_sourcing::upcaster::ledger_upcasters;
+
+        let pool = pool().await;
+        let fixtures: Vec<Value> = serde_json::from_str(include_str!(
+            "../../tests/fixtures/events/ledger_events.json"
+        ))
+        .unwrap();
+        let aggregate_id = Uuid::new_v4().to_string();
+        for (i, fixture) in fixtures.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO ledger_events (aggregate_type, aggregate_id, sequence, event_type,
+                    event_version, payload, metadata)
+                VALUES ('ledger', $1, $2, $3, $4, $5::json, '{}')",
+            )
+            .bind(&aggregate_id)
+            .bind(i as i64 + 1)
+            .bind(fixture["event_type"].as_str())
+            .bind(fixture["event_version"].as_str())
+            .bind(&fixture["payload"])
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let history = EventHistory::new(pool, "ledger_events");
+        let sequences = |page: &EventPage| -> Vec<usize> {
+            page.entries.iter().map(|entry| entry.sequence).collect()
+        };
+
+        let page = event_history(&history, &ledger_upcasters(), &aggregate_id, 1, 2)
+            .await
+            .unwrap();
+        assert_eq!((sequences(&page), page.next_cursor), (vec![2, 3], Some(3)));
+
+        let page = event_history(&history, &ledger_upcasters(), &aggregate_id, 3, 2)
+            .await
+            .unwrap();
+        assert_eq!((sequences(&page), page.next_cursor), (vec![4], None));
     }
 }
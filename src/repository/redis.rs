@@ -1,33 +1,25 @@
 use redis::AsyncCommands;
-use tracing::info;
-use uuid::Uuid;
+use tracing::warn;
 
-pub const LOCK_KEY: &str = "outbox_lock";
-pub const LOCK_TIMEOUT: i64 = 10 * 60; // seconds
-
-pub async fn acquire_lock(
-    client: &redis::Client,
-    lock_key: &str,
-    lock_timeout: i64,
-) -> Option<String> {
-    let mut con = client.get_multiplexed_async_connection().await.unwrap();
-    let lock_value = Uuid::new_v4().to_string();
-    let result: bool = con.set_nx(lock_key, &lock_value).await.unwrap();
+pub async fn ping(client: &redis::Client) -> redis::RedisResult<()> {
+    let mut con = client.get_multiplexed_async_connection().await?;
+    let _: String = redis::cmd("PING").query_async(&mut con).await?;
+    Ok(())
+}
 
-    if result {
-        let _: () = con.expire(lock_key, lock_timeout).await.unwrap();
-        info!("Acquired lock with value: {}", lock_value);
-        Some(lock_value)
-    } else {
-        None
-    }
+// Cache reads and writes are best effort, callers fall back to the database
+// when Redis is unavailable.
+pub async fn get_cached(client: &redis::Client, key: &str) -> Option<String> {
+    let mut con = client.get_multiplexed_async_connection().await.ok()?;
+    con.get(key).await.ok().flatten()
 }
 
-pub async fn release_lock(client: &redis::Client, lock_key: &str, lock_value: &str) {
-    let mut con = client.get_multiplexed_async_connection().await.unwrap();
-    let current_lock_value: String = con.get(lock_key).await.unwrap();
-    if current_lock_value == lock_value {
-        let _: () = con.del(lock_key).await.unwrap();
-        info!("Released lock with value: {}", lock_value);
+pub async fn set_cached(client: &redis::Client, key: &str, value: &str, ttl_secs: u64) {
+    let result: redis::RedisResult<()> = match client.get_multiplexed_async_connection().await {
+        Ok(mut con) => con.set_ex(key, value, ttl_secs).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        warn!("Failed to cache {}: {}", key, err);
     }
 }
@@ -1,27 +1,126 @@
 use std::sync::Arc;
 
-use crate::command::CommandExtractor;
+use crate::auth::jwt::generate_secret_key;
+use crate::auth::jwt::{create_tenant, issue_credentials, Claims, TenantCredentials};
+use crate::auth::keys::key_ring;
+use crate::auth::middleware::authorize;
+use crate::auth::scope::{
+    parse_scopes, scoped, ACH_ADMIN, ASSET_ADMIN, BANK_ACCOUNT_READ, BANK_ACCOUNT_WRITE,
+    HOUSE_ACCOUNT_ADMIN, LEDGER_READ, METRICS_READ, OUTBOX_ADMIN, TENANT_ADMIN, TOKEN_ADMIN,
+    WEBHOOK_ADMIN,
+};
+use crate::auth::token::{cache_token, hash_secret, issue_token};
+use crate::command::{CommandExtractor, RequestMetadata};
 use crate::common::error::AppError;
-use crate::common::money::Money;
-use crate::domain::finance::TransactionWithMoney;
+use crate::common::money::{Currency, Money};
+use crate::configs::settings::SETTINGS;
+use crate::domain::asset::Asset;
+use crate::domain::finance::{OutboxEntry, TransactionWithMoney, OUTBOX_DEAD};
+use crate::domain::rate_limit::TenantRateLimits;
+use crate::domain::tenant::{
+    TenantAuditEntry, TenantSummary, TENANT_ACTIVE, TENANT_DELETED, TENANT_INACTIVE,
+    TENANT_SUSPENDED, TOKEN_KIND_ACCESS, TOKEN_KIND_SERVICE,
+};
+use crate::domain::webhook::WebhookEndpoint;
 use crate::event_sourcing::command::{BankAccountCommand, LedgerCommand};
+use crate::event_sourcing::error::DomainError;
+use crate::event_sourcing::history::{event_history, ledger_balance_as_of, DEFAULT_PAGE_SIZE};
+use crate::event_sourcing::upcaster::{bank_account_upcasters, ledger_upcasters};
+use crate::health::{Check, Readiness};
 use crate::house_account::HouseAccountExtractor;
-use crate::SharedState;
+use crate::metrics::{timed_execute, track_requests, AGGREGATE_LEDGER, METRICS};
+use crate::outbox::retry_entry;
+use crate::payment::ach::{generate_ach_file, process_ach_returns};
+use crate::payment::nacha::NachaError;
+use crate::rate_limit::rate_limit;
+use crate::repository::adapter::DatabaseClient;
+use crate::repository::redis::ping;
+use crate::state::ApplicationState;
+use crate::stream::{forward_to_socket, sse_events, StreamFilter};
+use crate::webhook::check_endpoint_host;
 
+use axum::extract::ws::WebSocketUpgrade;
 use axum::extract::{Extension, Query};
 use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
-use axum::Json;
+use axum::routing::{delete, get, post, put};
+use axum::{middleware, Form, Json, Router};
+use chrono::{DateTime, Duration, Utc};
 use cqrs_es::persist::ViewRepository;
 use rust_decimal::Decimal;
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+use tower_http::add_extension::AddExtensionLayer;
+use tower_http::compression::CompressionLayer;
+use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
+// Probes of `/readyz` fail instead of hanging on an unresponsive dependency.
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Bank accounts and ledgers of other tenants are reported as missing, so their
+// ids cannot be probed.
+fn ensure_owner(owner: Result<i32, sqlx::Error>, tenant_id: i32) -> Result<(), AppError> {
+    match owner {
+        Ok(owner) if owner == tenant_id => Ok(()),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            Err(AppError::NotFound("Resource Not Found".to_string()))
+        }
+        Err(err) => Err(AppError::InternalServerError(err.to_string())),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct HouseAccountParams {
-    pub currency: String,
+    pub currency: Currency,
+}
+
+#[derive(Deserialize)]
+pub struct LedgerParams {
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct EventHistoryParams {
+    #[serde(default)]
+    pub after: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveryParams {
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OutboxParams {
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ClientCredentialsRequest {
+    pub grant_type: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TenantCreateRequest {
+    pub name: String,
+    // The default scopes when empty.
+    #[serde(default)]
+    pub scope: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TenantScopeRequest {
+    pub scope: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -31,13 +130,13 @@ pub struct TransactionParams {
     pub limit: i64,
 }
 
-pub async fn user_query_handler(
-    Extension(_tenant_id): Extension<i32>,
+pub async fn user_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
     Path(id): Path<String>,
-    State(state): State<SharedState>,
+    State(state): State<Arc<ApplicationState<C>>>,
 ) -> Response {
     let client = Arc::clone(&state.database);
-    match client.get_user_bank_accounts(id).await {
+    match client.get_user_bank_accounts(tenant_id, id).await {
         Ok(accounts) => (StatusCode::OK, Json(json!({ "entries": accounts }))).into_response(),
         Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
     }
@@ -45,11 +144,15 @@ pub async fn user_query_handler(
 
 // Serves as our query endpoint to respond with the materialized `BankAccountView`
 // for the requested account.
-pub async fn bank_account_query_handler(
-    Extension(_tenant_id): Extension<i32>,
+pub async fn bank_account_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
     Path(id): Path<String>,
-    State(state): State<SharedState>,
+    State(state): State<Arc<ApplicationState<C>>>,
 ) -> Response {
+    let owner = state.database.get_bank_account_tenant(id.clone()).await;
+    if let Err(err) = ensure_owner(owner, tenant_id) {
+        return err.into_response();
+    }
     let bank_account = &state.bank_account.clone().unwrap();
     let view = match bank_account.query.load(&id).await {
         Ok(view) => view,
@@ -64,33 +167,90 @@ pub async fn bank_account_query_handler(
 }
 
 // Serves as our command endpoint to make changes in a `BankAccount` aggregate.
-pub async fn bank_account_command_handler(
-    Extension(_tenant_id): Extension<i32>,
-    State(state): State<SharedState>,
-    CommandExtractor(_metadata, command): CommandExtractor,
+pub async fn bank_account_command_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    CommandExtractor(metadata, mut command): CommandExtractor,
 ) -> Response {
+    let currency = match &command {
+        BankAccountCommand::OpenAccount { currency, .. } => Some(*currency),
+        BankAccountCommand::Deposit { amount, .. } => Some(amount.currency),
+        BankAccountCommand::Withdrawal { amount, .. } => Some(amount.currency),
+        BankAccountCommand::ApproveAccount { .. } => None,
+    };
+    if let Some(Err(err)) = currency.map(|currency| currency.ensure_enabled(tenant_id)) {
+        return AppError::from(DomainError::from(err)).into_response();
+    }
+    // New accounts belong to the calling tenant, every other command must
+    // target one of its accounts.
+    if let BankAccountCommand::OpenAccount {
+        tenant_id: account_tenant_id,
+        ..
+    } = &mut command
+    {
+        *account_tenant_id = tenant_id;
+    } else {
+        let id = match &command {
+            BankAccountCommand::ApproveAccount { id, .. } => id,
+            BankAccountCommand::Deposit { id, .. } => id,
+            BankAccountCommand::Withdrawal { id, .. } => id,
+            BankAccountCommand::OpenAccount { id, .. } => id,
+        };
+        let owner = state.database.get_bank_account_tenant(id.to_string()).await;
+        if let Err(err) = ensure_owner(owner, tenant_id) {
+            return err.into_response();
+        }
+    }
     let result = match &command {
         BankAccountCommand::OpenAccount { id, .. } => (StatusCode::CREATED, id.to_string()),
         BankAccountCommand::ApproveAccount { id, .. } => (StatusCode::OK, id.to_string()),
         BankAccountCommand::Deposit { id, .. } => (StatusCode::OK, id.to_string()),
         BankAccountCommand::Withdrawal { id, .. } => (StatusCode::OK, id.to_string()),
     };
-    if let Some(command_sender) = &state.command_sender {
-        match command_sender.send(command) {
-            Ok(_) => (result.0, Json(json!({"id": result.1}))).into_response(),
-            Err(err) => AppError::BadRequest(err.to_string()).into_response(),
-        }
-    } else {
-        AppError::InternalServerError("Command Sender not found".to_string()).into_response()
+    let Some(command_sender) = &state.command_sender else {
+        return AppError::InternalServerError("Command Sender not found".to_string())
+            .into_response();
+    };
+    // The queue is drained on shutdown, a command queued now might not be
+    // applied in time.
+    if state.health.shutting_down() {
+        return AppError::from(DomainError::ShuttingDown).into_response();
+    }
+    let (reply, outcome) = oneshot::channel();
+    state.health.command_queued();
+    if let Err(err) = command_sender.send((command, metadata, reply)) {
+        state.health.command_taken();
+        return AppError::InternalServerError(err.to_string()).into_response();
+    }
+    // Commands are applied one at a time by the command processor, the
+    // request waits for its own so a rejection reaches the caller.
+    match outcome.await {
+        Ok(Ok(())) => (result.0, Json(json!({"id": result.1}))).into_response(),
+        Ok(Err(err)) => AppError::from(err).into_response(),
+        Err(_) => AppError::InternalServerError("Command was dropped".to_string()).into_response(),
     }
 }
 
-pub async fn ledger_query_handler(
-    Extension(_tenant_id): Extension<i32>,
+// With `as_of` the balances are replayed from the ledger events as they were
+// at that instant, otherwise the latest `LedgerView` is returned.
+pub async fn ledger_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
     Path(id): Path<String>,
-    State(state): State<SharedState>,
+    Query(params): Query<LedgerParams>,
+    State(state): State<Arc<ApplicationState<C>>>,
 ) -> Response {
+    let owner = state.database.get_ledger_tenant(id.clone()).await;
+    if let Err(err) = ensure_owner(owner, tenant_id) {
+        return err.into_response();
+    }
     let ledger = &state.ledger.clone().unwrap();
+    if let Some(as_of) = params.as_of {
+        return match ledger_balance_as_of(&ledger.events, &id, as_of).await {
+            Ok(Some(balance)) => (StatusCode::OK, Json(balance)).into_response(),
+            Ok(None) => AppError::NotFound("Resource Not Found".to_string()).into_response(),
+            Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+        };
+    }
     let view = match ledger.query.load(&id).await {
         Ok(view) => view,
         Err(err) => {
@@ -103,42 +263,46 @@ pub async fn ledger_query_handler(
     }
 }
 
-pub async fn house_account_query_handler(
-    Extension(_tenant_id): Extension<i32>,
-    State(state): State<SharedState>,
+pub async fn house_account_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
     Query(params): Query<HouseAccountParams>,
 ) -> Response {
     let client = Arc::clone(&state.database);
-    match client.get_house_accounts(params.currency.into()).await {
+    match client.get_house_accounts(tenant_id, params.currency).await {
         Ok(accounts) => (StatusCode::OK, Json(json!({ "entries": accounts }))).into_response(),
         Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
     }
 }
 
-pub async fn house_account_create_handler(
-    Extension(_tenant_id): Extension<i32>,
-    State(state): State<SharedState>,
-    HouseAccountExtractor(_metadata, mut house_account): HouseAccountExtractor,
+pub async fn house_account_create_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    HouseAccountExtractor(metadata, mut house_account): HouseAccountExtractor,
 ) -> Response {
+    if let Err(err) = house_account.currency.ensure_enabled(tenant_id) {
+        return AppError::from(DomainError::from(err)).into_response();
+    }
     let client = &state.database.clone();
     let ledger_id = Uuid::new_v4();
     let ledger = &state.ledger.clone().unwrap();
-    if let Err(err) = ledger
-        .cqrs
-        .execute(
-            &ledger_id.to_string(),
-            LedgerCommand::Init {
-                id: ledger_id,
-                account_id: house_account.id,
-                amount: Money::new(Decimal::ZERO, house_account.currency),
-            },
-        )
-        .await
-    {
-        return AppError::BadRequest(err.to_string()).into_response();
+    let id = ledger_id.to_string();
+    let execute = ledger.cqrs.execute_with_metadata(
+        &id,
+        LedgerCommand::Init {
+            id: ledger_id,
+            account_id: house_account.id,
+            amount: Money::new(Decimal::ZERO, house_account.currency),
+            tenant_id,
+        },
+        metadata,
+    );
+    if let Err(err) = timed_execute(AGGREGATE_LEDGER, execute).await {
+        return AppError::from(DomainError::from(err)).into_response();
     }
 
     house_account.ledger_id = ledger_id.to_string();
+    house_account.tenant_id = tenant_id;
     let house_account_id = house_account.id.to_string();
     if let Err(err) = client.create_house_account(house_account).await {
         return AppError::BadRequest(err.to_string()).into_response();
@@ -147,25 +311,2367 @@ pub async fn house_account_create_handler(
     (StatusCode::CREATED, Json(json!({ "id": house_account_id}))).into_response()
 }
 
-pub async fn transaction_query_handler(
-    Extension(_tenant_id): Extension<i32>,
-    State(state): State<SharedState>,
+// Currencies the tenant can use: those enabled in this deployment with their
+// ISO 4217 details, then its custom assets.
+pub async fn currency_query_handler(Extension(tenant_id): Extension<i32>) -> Response {
+    let currencies: Vec<Value> = Currency::enabled(tenant_id)
+        .into_iter()
+        .map(|currency| {
+            json!({
+                "code": currency.code(),
+                "numeric_code": currency.numeric_code(),
+                "minor_units": currency.precision(),
+                "custom": currency.is_custom(),
+            })
+        })
+        .collect();
+    (StatusCode::OK, Json(json!({ "entries": currencies }))).into_response()
+}
+
+// Registers a custom asset of the tenant. It can be used at once on this
+// server under the returned `currency` code, the others pick it up on their
+// next asset refresh.
+pub async fn asset_create_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    Json(mut asset): Json<Asset>,
+) -> Response {
+    if let Err(err) = asset.validate() {
+        return AppError::BadRequest(err).into_response();
+    }
+    asset.tenant_id = tenant_id;
+    let code = asset.code.clone();
+    match state.database.create_asset(asset).await {
+        Ok(asset) => {
+            let currency = asset.register();
+            let body = json!({
+                "code": asset.code,
+                "currency": currency,
+                "name": asset.name,
+                "minor_units": asset.minor_units,
+                "created_at": asset.created_at,
+            });
+            (StatusCode::CREATED, Json(body)).into_response()
+        }
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            AppError::Conflict(format!("Asset {} already exists", code)).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn transaction_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
     Query(params): Query<TransactionParams>,
 ) -> Response {
     let client = &state.database.clone();
+    let owner = client
+        .get_bank_account_tenant(params.bank_account_id.clone())
+        .await;
+    if let Err(err) = ensure_owner(owner, tenant_id) {
+        return err.into_response();
+    }
     match client
-        .get_transactions(params.bank_account_id, params.offset, params.limit)
+        .get_transactions(
+            tenant_id,
+            params.bank_account_id,
+            params.offset,
+            params.limit,
+        )
         .await
     {
         Ok(transactions) => {
             // convert transaction using into_transaction_with_money and insert
             // into Vec again to make sure the amount precision
-            let transactions: Vec<TransactionWithMoney> = transactions
+            let transactions: Result<Vec<TransactionWithMoney>, _> = transactions
                 .into_iter()
                 .map(|t| t.into_transaction_with_money())
                 .collect();
-            (StatusCode::OK, Json(json!({ "entries": transactions }))).into_response()
+            match transactions {
+                Ok(transactions) => {
+                    (StatusCode::OK, Json(json!({ "entries": transactions }))).into_response()
+                }
+                Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+            }
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn ach_file_create_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match generate_ach_file(&state, tenant_id).await {
+        Ok(Some(file)) => (StatusCode::CREATED, Json(file)).into_response(),
+        Ok(None) => AppError::NotFound("No withdrawals pending payout".to_string()).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn ach_file_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let id = match Uuid::parse_str(&id) {
+        Ok(id) => id,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state.database.get_ach_file(tenant_id, id).await {
+        Ok(file) => (StatusCode::OK, Json(file)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Accepts the raw NACHA return file as the request body.
+pub async fn ach_return_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    RequestMetadata(metadata): RequestMetadata,
+    body: String,
+) -> Response {
+    match process_ach_returns(&state, tenant_id, &body, &metadata).await {
+        Ok(entries) => (StatusCode::OK, Json(json!({ "entries": entries }))).into_response(),
+        Err(err) if err.downcast_ref::<NachaError>().is_some() => {
+            AppError::BadRequest(err.to_string()).into_response()
+        }
+        Err(err) if matches!(err.downcast_ref(), Some(sqlx::Error::RowNotFound)) => {
+            AppError::NotFound(format!("{:#}", err)).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Registers a webhook endpoint for the tenant. The signing secret is only
+// returned here, receivers must keep it to verify `X-Bankie-Signature`.
+pub async fn webhook_create_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    Json(mut endpoint): Json<WebhookEndpoint>,
+) -> Response {
+    if let Err(err) = endpoint.validate() {
+        return AppError::BadRequest(err).into_response();
+    }
+    if let Err(err) = check_endpoint_host(&endpoint.url).await {
+        return AppError::BadRequest(err).into_response();
+    }
+    endpoint.id = Uuid::new_v4();
+    endpoint.tenant_id = tenant_id;
+    endpoint.secret = generate_secret_key(32);
+    endpoint.status = "active".to_string();
+
+    let secret = endpoint.secret.clone();
+    match state.database.create_webhook_endpoint(endpoint).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "id": id, "secret": secret })),
+        )
+            .into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn webhook_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match state.database.get_webhook_endpoints(tenant_id).await {
+        Ok(endpoints) => (StatusCode::OK, Json(json!({ "entries": endpoints }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn webhook_delete_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let id = match Uuid::parse_str(&id) {
+        Ok(id) => id,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state.database.disable_webhook_endpoint(tenant_id, id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn webhook_delivery_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    Query(params): Query<WebhookDeliveryParams>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let id = match Uuid::parse_str(&id) {
+        Ok(id) => id,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state
+        .database
+        .get_webhook_deliveries(tenant_id, id, params.status)
+        .await
+    {
+        Ok(deliveries) => (StatusCode::OK, Json(json!({ "entries": deliveries }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Puts a delivery, usually a dead-lettered one, back in the queue with a
+// fresh retry budget.
+pub async fn webhook_delivery_replay_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let id = match Uuid::parse_str(&id) {
+        Ok(id) => id,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state.database.replay_webhook_delivery(tenant_id, id).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Outbox commands of the tenant's transactions, the dead-lettered ones unless
+// `status` asks for another state.
+pub async fn outbox_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Query(params): Query<OutboxParams>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let status = params.status.unwrap_or_else(|| OUTBOX_DEAD.to_string());
+    match state.database.get_outbox_entries(tenant_id, status).await {
+        Ok(entries) => (StatusCode::OK, Json(json!({ "entries": entries }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Retries a dead-lettered command with a fresh retry budget, the transaction
+// is processing again.
+pub async fn outbox_retry_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    RequestMetadata(metadata): RequestMetadata,
+) -> Response {
+    let entry = match dead_letter(&state, tenant_id, id).await {
+        Ok(entry) => entry,
+        Err(err) => return err.into_response(),
+    };
+    match retry_entry(&state, tenant_id, &entry, metadata).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => AppError::from(err).into_response(),
+    }
+}
+
+// Acknowledges a dead-lettered command, it is never retried and its
+// transaction stays failed.
+pub async fn outbox_skip_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Err(err) = dead_letter(&state, tenant_id, id).await {
+        return err.into_response();
+    }
+    match state.database.skip_outbox(tenant_id, id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            AppError::from(DomainError::ConcurrentModification).into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+async fn dead_letter<C: DatabaseClient + Send + Sync + 'static>(
+    state: &ApplicationState<C>,
+    tenant_id: i32,
+    id: i32,
+) -> Result<OutboxEntry, AppError> {
+    match state.database.get_outbox_entry(tenant_id, id).await {
+        Ok(entry) if entry.status == OUTBOX_DEAD => Ok(entry),
+        Ok(entry) => Err(AppError::Conflict(format!(
+            "Outbox entry {} is {}, not dead-lettered",
+            id, entry.status
+        ))),
+        Err(sqlx::Error::RowNotFound) => Err(AppError::NotFound("Resource Not Found".to_string())),
+        Err(err) => Err(AppError::InternalServerError(err.to_string())),
+    }
+}
+
+// Streams balance and account status changes as Server-Sent Events. Clients
+// reconnecting with `Last-Event-ID` (or `?since=`) get the buffered events
+// they missed first.
+pub async fn stream_sse_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    headers: HeaderMap,
+    Query(mut filter): Query<StreamFilter>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Some(last_event_id) = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+    {
+        filter.since = Some(last_event_id);
+    }
+    let stream = state.stream.clone().unwrap();
+    Sse::new(sse_events(stream.subscribe(tenant_id, filter)))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// WebSocket equivalent of `stream_sse_handler`, every event is sent as a JSON
+// text frame.
+pub async fn stream_ws_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Query(filter): Query<StreamFilter>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let stream = state.stream.clone().unwrap();
+    let items = stream.subscribe(tenant_id, filter);
+    ws.on_upgrade(move |socket| forward_to_socket(socket, items))
+}
+
+// Ordered event history of a bank account, `after` is the cursor returned as
+// `next_cursor` by the previous page.
+pub async fn bank_account_events_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    Query(params): Query<EventHistoryParams>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let owner = state.database.get_bank_account_tenant(id.clone()).await;
+    if let Err(err) = ensure_owner(owner, tenant_id) {
+        return err.into_response();
+    }
+    let bank_account = &state.bank_account.clone().unwrap();
+    match event_history(
+        &bank_account.history,
+        &bank_account_upcasters(),
+        &id,
+        params.after,
+        params.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+    .await
+    {
+        Ok(page) if page.entries.is_empty() && params.after == 0 => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Ok(page) => (StatusCode::OK, Json(page)).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn ledger_events_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(id): Path<String>,
+    Query(params): Query<EventHistoryParams>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let owner = state.database.get_ledger_tenant(id.clone()).await;
+    if let Err(err) = ensure_owner(owner, tenant_id) {
+        return err.into_response();
+    }
+    let ledger = &state.ledger.clone().unwrap();
+    match event_history(
+        &ledger.history,
+        &ledger_upcasters(),
+        &id,
+        params.after,
+        params.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+    .await
+    {
+        Ok(page) if page.entries.is_empty() && params.after == 0 => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Ok(page) => (StatusCode::OK, Json(page)).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// OAuth2 client-credentials grant (RFC 6749 section 4.4), exchanging the
+// tenant's client secret for a short-lived access token. Errors use the
+// `error` codes of section 5.2 instead of `AppError`.
+pub async fn oauth_token_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+    Form(request): Form<ClientCredentialsRequest>,
+) -> Response {
+    if request.grant_type != "client_credentials" {
+        return oauth_error(StatusCode::BAD_REQUEST, "unsupported_grant_type");
+    }
+    let Ok(tenant_id) = request.client_id.parse::<i32>() else {
+        return oauth_error(StatusCode::UNAUTHORIZED, "invalid_client");
+    };
+    match state
+        .database
+        .get_tenant_client_secret_hash(tenant_id)
+        .await
+    {
+        Ok(hash) if hash == hash_secret(&request.client_secret) => {}
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            return oauth_error(StatusCode::UNAUTHORIZED, "invalid_client");
+        }
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    }
+    let tenant = match state.database.get_tenant_profile(tenant_id).await {
+        Ok(tenant) => tenant,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+
+    let granted = tenant.scope.unwrap_or_default();
+    let granted: Vec<&str> = granted.split_whitespace().collect();
+    let scopes: Vec<String> = match &request.scope {
+        Some(scope) => scope.split_whitespace().map(str::to_string).collect(),
+        None => granted.iter().map(|s| s.to_string()).collect(),
+    };
+    if scopes.iter().any(|s| !granted.contains(&s.as_str())) {
+        return oauth_error(StatusCode::BAD_REQUEST, "invalid_scope");
+    }
+
+    let keys = match key_ring(&state, false).await {
+        Ok(keys) => keys,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+    let ttl = Duration::seconds(SETTINGS.auth.access_token_ttl_secs);
+    match issue_token(
+        &state.database,
+        &keys,
+        tenant_id,
+        &tenant.name,
+        scopes,
+        TOKEN_KIND_ACCESS,
+        ttl,
+    )
+    .await
+    {
+        Ok((token, record)) => (
+            StatusCode::OK,
+            [(CACHE_CONTROL, "no-store")],
+            Json(json!({
+                "access_token": token,
+                "token_type": "Bearer",
+                "expires_in": ttl.num_seconds(),
+                "scope": record.scope,
+            })),
+        )
+            .into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+fn oauth_error(status: StatusCode, error: &str) -> Response {
+    (status, Json(json!({ "error": error }))).into_response()
+}
+
+// Public keys of the active signing keys, for other services to verify the
+// tokens bankie issues.
+pub async fn jwks_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match key_ring(&state, false).await {
+        Ok(keys) => (
+            StatusCode::OK,
+            [(
+                CACHE_CONTROL,
+                format!("public, max-age={}", SETTINGS.auth.key_refresh_secs),
+            )],
+            Json(keys.jwks()),
+        )
+            .into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Liveness, answers as long as the server handles requests.
+pub async fn healthz_handler() -> Response {
+    (StatusCode::OK, Json(json!({ "status": "ok" }))).into_response()
+}
+
+// Readiness, `503` while the server should not receive traffic.
+pub async fn readyz_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let database = Check::probed(timeout(PROBE_TIMEOUT, state.database.ping()).await);
+    let redis = match &state.cache {
+        Some(cache) => Check::probed(timeout(PROBE_TIMEOUT, ping(cache)).await),
+        None => Check::Disabled,
+    };
+    let readiness = Readiness::new(
+        database,
+        redis,
+        state.health.scheduler_running(),
+        state.health.queued_commands(),
+        SETTINGS.health.max_queued_commands,
+    );
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(readiness)).into_response()
+}
+
+// Backlogs of the background work across every tenant.
+pub async fn diagnostics_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let stuck_after = SETTINGS.health.stuck_transaction_secs;
+    match state.database.get_diagnostics(stuck_after).await {
+        Ok(diagnostics) => (
+            StatusCode::OK,
+            Json(json!({
+                "outbox": diagnostics.outbox,
+                "stuck_transactions": diagnostics.stuck_transactions,
+                "projections": diagnostics.projections,
+                "queued_commands": state.health.queued_commands(),
+                "scheduler_running": state.health.scheduler_running(),
+            })),
+        )
+            .into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Prometheus metrics in the text exposition format. Outbox backlog and ledger
+// balances are read at scrape time, across every tenant.
+pub async fn metrics_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match state.database.get_outbox_backlog().await {
+        Ok(backlog) => METRICS.set_outbox_backlog(&backlog),
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    }
+    match state.database.get_ledger_totals().await {
+        Ok(totals) => METRICS.set_ledger_totals(&totals),
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    }
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        METRICS.encode(),
+    )
+        .into_response()
+}
+
+// Issues a new service token with the caller's scopes. The calling token keeps
+// working for `auth.rotation_grace_secs`, so clients can roll out the new one.
+pub async fn token_rotate_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Extension(claims): Extension<Claims>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Some(jti) = claims.jti {
+        match state.database.get_tenant_token(jti).await {
+            Ok(token) if token.kind == TOKEN_KIND_ACCESS => {
+                return AppError::BadRequest(
+                    "Access tokens cannot be rotated, request a new one".to_string(),
+                )
+                .into_response();
+            }
+            // A rotated token still works during its grace period, rotating it
+            // again would keep it alive for good.
+            Ok(token) if token.revoked_at.is_some() => {
+                return AppError::Conflict("Token was already rotated or revoked".to_string())
+                    .into_response();
+            }
+            Ok(_) => {}
+            Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+        }
+    }
+    let keys = match key_ring(&state, false).await {
+        Ok(keys) => keys,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+
+    let ttl = Duration::days(SETTINGS.auth.service_token_ttl_days);
+    let (token, record) = match issue_token(
+        &state.database,
+        &keys,
+        tenant_id,
+        &claims.sub,
+        claims.scopes,
+        TOKEN_KIND_SERVICE,
+        ttl,
+    )
+    .await
+    {
+        Ok(issued) => issued,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+    let grace_until = Utc::now().naive_utc() + Duration::seconds(SETTINGS.auth.rotation_grace_secs);
+    let previous_revoked_at = match claims.jti {
+        Some(jti) => match state
+            .database
+            .revoke_tenant_token(tenant_id, jti, grace_until)
+            .await
+        {
+            Ok(previous) => {
+                cache_token(&state, &previous).await;
+                previous.revoked_at
+            }
+            Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+        },
+        // Tokens without `jti` stop working once they are no longer the
+        // tenant's current token.
+        None => Some(Utc::now().naive_utc()),
+    };
+    if let Err(err) = state
+        .database
+        .update_tenant_profile(tenant_id, &token)
+        .await
+    {
+        return AppError::InternalServerError(err.to_string()).into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        [(CACHE_CONTROL, "no-store")],
+        Json(json!({
+            "token": token,
+            "jti": record.jti,
+            "expires_at": record.expires_at,
+            "previous_revoked_at": previous_revoked_at,
+        })),
+    )
+        .into_response()
+}
+
+pub async fn token_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match state.database.get_tenant_tokens(tenant_id).await {
+        Ok(tokens) => (StatusCode::OK, Json(json!({ "entries": tokens }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Revokes a token of the tenant at once, including one in its rotation grace
+// period.
+pub async fn token_revoke_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(tenant_id): Extension<i32>,
+    Path(jti): Path<String>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let jti = match Uuid::parse_str(&jti) {
+        Ok(jti) => jti,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state
+        .database
+        .revoke_tenant_token(tenant_id, jti, Utc::now().naive_utc())
+        .await
+    {
+        Ok(token) => {
+            cache_token(&state, &token).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            AppError::NotFound("Resource Not Found".to_string()).into_response()
         }
         Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
     }
 }
+
+// Audit log entry of a change the caller made to `tenant_id`.
+fn tenant_audit_entry(
+    claims: &Claims,
+    tenant_id: i32,
+    action: &str,
+    detail: serde_json::Value,
+) -> TenantAuditEntry {
+    TenantAuditEntry {
+        id: 0,
+        tenant_id,
+        action: action.to_string(),
+        actor_tenant_id: claims.tenant_id,
+        actor: claims.sub.clone(),
+        detail,
+        created_at: Utc::now().naive_utc(),
+    }
+}
+
+// Records a change the caller made to `tenant_id` in the tenant audit log.
+async fn audit_tenant<C: DatabaseClient + Send + Sync + 'static>(
+    state: &ApplicationState<C>,
+    claims: &Claims,
+    tenant_id: i32,
+    action: &str,
+    detail: serde_json::Value,
+) -> Result<(), AppError> {
+    let entry = tenant_audit_entry(claims, tenant_id, action, detail);
+    state
+        .database
+        .create_tenant_audit(entry)
+        .await
+        .map_err(|err| AppError::InternalServerError(err.to_string()))
+}
+
+async fn find_tenant<C: DatabaseClient + Send + Sync + 'static>(
+    state: &ApplicationState<C>,
+    tenant_id: i32,
+) -> Result<TenantSummary, AppError> {
+    match state.database.get_tenant(tenant_id).await {
+        Ok(tenant) => Ok(tenant),
+        Err(sqlx::Error::RowNotFound) => Err(AppError::NotFound("Resource Not Found".to_string())),
+        Err(err) => Err(AppError::InternalServerError(err.to_string())),
+    }
+}
+
+fn credentials_response(
+    tenant: &TenantSummary,
+    credentials: TenantCredentials,
+) -> Json<serde_json::Value> {
+    Json(json!({
+        "tenant": tenant,
+        "token": credentials.jwt,
+        "client_id": credentials.tenant_id,
+        "client_secret": credentials.client_secret,
+    }))
+}
+
+pub async fn tenant_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match state.database.get_tenants().await {
+        Ok(tenants) => (StatusCode::OK, Json(json!({ "entries": tenants }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn tenant_get_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match find_tenant(&state, id).await {
+        Ok(tenant) => (StatusCode::OK, Json(tenant)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Creates an active tenant. The response holds its service token and client
+// secret, they are not retrievable afterwards.
+pub async fn tenant_create_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    Json(request): Json<TenantCreateRequest>,
+) -> Response {
+    if request.name.trim().is_empty() {
+        return AppError::BadRequest("Tenant name must not be empty".to_string()).into_response();
+    }
+    let scopes = match parse_scopes(&request.scope) {
+        Ok(scopes) => scopes,
+        Err(err) => return AppError::BadRequest(err).into_response(),
+    };
+    let keys = match key_ring(&state, false).await {
+        Ok(keys) => keys,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+    // The id of the tenant is filled in as it is created.
+    let detail = json!({ "name": request.name, "scope": scopes.join(" ") });
+    let audit = tenant_audit_entry(&claims, 0, "created", detail);
+    let credentials =
+        match create_tenant(&state.database, &keys, &request.name, scopes, Some(audit)).await {
+            Ok(credentials) => credentials,
+            Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+        };
+    let tenant = match find_tenant(&state, credentials.tenant_id).await {
+        Ok(tenant) => tenant,
+        Err(err) => return err.into_response(),
+    };
+
+    (
+        StatusCode::CREATED,
+        credentials_response(&tenant, credentials),
+    )
+        .into_response()
+}
+
+// Moves the tenant to `status` when it is currently in one of `from`, and
+// records `action` with it. Callers cannot change the status of their own tenant.
+async fn change_tenant_status<C: DatabaseClient + Send + Sync + 'static>(
+    state: &ApplicationState<C>,
+    claims: &Claims,
+    id: i32,
+    from: &[&str],
+    status: &str,
+    action: &str,
+) -> Result<TenantSummary, AppError> {
+    if id == claims.tenant_id {
+        return Err(AppError::BadRequest(format!(
+            "The calling tenant cannot be {}",
+            action
+        )));
+    }
+    let tenant = find_tenant(state, id).await?;
+    let from = from.iter().map(|s| s.to_string()).collect();
+    let audit = tenant_audit_entry(claims, id, action, json!({ "from": tenant.status }));
+    let updated = match state
+        .database
+        .update_tenant_status(id, from, status, audit)
+        .await
+    {
+        Ok(updated) => updated,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(AppError::Conflict(format!(
+                "Tenant {} is {}",
+                id, tenant.status
+            )))
+        }
+        Err(err) => return Err(AppError::InternalServerError(err.to_string())),
+    };
+    Ok(updated)
+}
+
+// Tokens of a suspended tenant are rejected, they work again once the tenant
+// is reactivated.
+pub async fn tenant_suspend_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match change_tenant_status(
+        &state,
+        &claims,
+        id,
+        &[TENANT_ACTIVE],
+        TENANT_SUSPENDED,
+        "suspended",
+    )
+    .await
+    {
+        Ok(tenant) => (StatusCode::OK, Json(tenant)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+pub async fn tenant_reactivate_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    match change_tenant_status(
+        &state,
+        &claims,
+        id,
+        &[TENANT_SUSPENDED],
+        TENANT_ACTIVE,
+        "reactivated",
+    )
+    .await
+    {
+        Ok(tenant) => (StatusCode::OK, Json(tenant)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+// Deletes the tenant and revokes its tokens. Its data is kept.
+pub async fn tenant_delete_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Err(err) = change_tenant_status(
+        &state,
+        &claims,
+        id,
+        &[TENANT_ACTIVE, TENANT_SUSPENDED, TENANT_INACTIVE],
+        TENANT_DELETED,
+        "deleted",
+    )
+    .await
+    {
+        return err.into_response();
+    }
+    match state
+        .database
+        .revoke_tenant_tokens(id, Utc::now().naive_utc())
+        .await
+    {
+        Ok(tokens) => {
+            for token in &tokens {
+                cache_token(&state, token).await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Replaces the tenant's scopes. Removed scopes are withdrawn from its tokens at
+// once, added ones only come with tokens issued afterwards.
+pub async fn tenant_scope_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    Json(request): Json<TenantScopeRequest>,
+) -> Response {
+    if request.scope.is_empty() {
+        return AppError::BadRequest("Scope must not be empty".to_string()).into_response();
+    }
+    let scopes = match parse_scopes(&request.scope) {
+        Ok(scopes) => scopes,
+        Err(err) => return AppError::BadRequest(err).into_response(),
+    };
+    let tenant = match find_tenant(&state, id).await {
+        Ok(tenant) => tenant,
+        Err(err) => return err.into_response(),
+    };
+    let scope = scopes.join(" ");
+    let detail = json!({ "from": tenant.scope, "to": scope });
+    let audit = tenant_audit_entry(&claims, id, "scope_updated", detail);
+    let updated = match state.database.update_tenant_scope(id, &scope, audit).await {
+        Ok(updated) => updated,
+        Err(sqlx::Error::RowNotFound) => {
+            return AppError::Conflict(format!("Tenant {} is {}", id, tenant.status))
+                .into_response()
+        }
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+
+    (StatusCode::OK, Json(updated)).into_response()
+}
+
+// Replaces the tenant's rate limit overrides, route classes left out use the
+// `rate_limit` settings.
+pub async fn tenant_rate_limit_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+    Json(request): Json<TenantRateLimits>,
+) -> Response {
+    if let Err(err) = request.validate() {
+        return AppError::BadRequest(err).into_response();
+    }
+    let tenant = match find_tenant(&state, id).await {
+        Ok(tenant) => tenant,
+        Err(err) => return err.into_response(),
+    };
+    let rate_limits = match serde_json::to_value(&request) {
+        Ok(rate_limits) => rate_limits,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+    let detail = json!({ "from": tenant.rate_limits, "to": rate_limits });
+    let audit = tenant_audit_entry(&claims, id, "rate_limit_updated", detail);
+    let updated = match state
+        .database
+        .update_tenant_rate_limits(id, rate_limits, audit)
+        .await
+    {
+        Ok(updated) => updated,
+        Err(sqlx::Error::RowNotFound) => {
+            return AppError::Conflict(format!("Tenant {} is {}", id, tenant.status))
+                .into_response()
+        }
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+
+    (StatusCode::OK, Json(updated)).into_response()
+}
+
+// Issues a new service token and client secret for an active tenant. The
+// previous client secret stops working, its tokens stay valid until revoked.
+pub async fn tenant_credentials_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let tenant = match find_tenant(&state, id).await {
+        Ok(tenant) => tenant,
+        Err(err) => return err.into_response(),
+    };
+    if tenant.status != TENANT_ACTIVE {
+        return AppError::Conflict(format!("Tenant {} is {}", id, tenant.status)).into_response();
+    }
+    let keys = match key_ring(&state, false).await {
+        Ok(keys) => keys,
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    };
+    let scopes = tenant
+        .scope
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let credentials =
+        match issue_credentials(&state.database, &keys, id, &tenant.name, scopes).await {
+            Ok(credentials) => credentials,
+            Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+        };
+    if let Err(err) = audit_tenant(&state, &claims, id, "credentials_issued", json!({})).await {
+        return err.into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        credentials_response(&tenant, credentials),
+    )
+        .into_response()
+}
+
+pub async fn tenant_token_query_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Err(err) = find_tenant(&state, id).await {
+        return err.into_response();
+    }
+    match state.database.get_tenant_tokens(id).await {
+        Ok(tokens) => (StatusCode::OK, Json(json!({ "entries": tokens }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+pub async fn tenant_token_revoke_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Extension(claims): Extension<Claims>,
+    Path((id, jti)): Path<(i32, String)>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    let jti = match Uuid::parse_str(&jti) {
+        Ok(jti) => jti,
+        Err(err) => return AppError::BadRequest(err.to_string()).into_response(),
+    };
+    match state
+        .database
+        .revoke_tenant_token(id, jti, Utc::now().naive_utc())
+        .await
+    {
+        Ok(token) => cache_token(&state, &token).await,
+        Err(sqlx::Error::RowNotFound) => {
+            return AppError::NotFound("Resource Not Found".to_string()).into_response()
+        }
+        Err(err) => return AppError::InternalServerError(err.to_string()).into_response(),
+    }
+    match audit_tenant(&state, &claims, id, "token_revoked", json!({ "jti": jti })).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+pub async fn tenant_audit_handler<C: DatabaseClient + Send + Sync + 'static>(
+    Path(id): Path<i32>,
+    State(state): State<Arc<ApplicationState<C>>>,
+) -> Response {
+    if let Err(err) = find_tenant(&state, id).await {
+        return err.into_response();
+    }
+    match state.database.get_tenant_audit(id).await {
+        Ok(entries) => (StatusCode::OK, Json(json!({ "entries": entries }))).into_response(),
+        Err(err) => AppError::InternalServerError(err.to_string()).into_response(),
+    }
+}
+
+// Configure the Axum routes and services.
+// For this example a single logical endpoint is used and the HTTP method
+// distinguishes whether the call is a command or a query.
+pub fn router<C: DatabaseClient + Send + Sync + 'static>(
+    state: Arc<ApplicationState<C>>,
+) -> Router {
+    let comression_layer: CompressionLayer = CompressionLayer::new();
+    Router::new()
+        .route(
+            "/v1/bank_account/:id",
+            scoped(BANK_ACCOUNT_READ, get(bank_account_query_handler)),
+        )
+        .route(
+            "/v1/bank_account/:id/events",
+            scoped(BANK_ACCOUNT_READ, get(bank_account_events_handler)),
+        )
+        .route(
+            "/v1/bank_account",
+            scoped(BANK_ACCOUNT_WRITE, post(bank_account_command_handler)),
+        )
+        .route(
+            "/v1/ledger/:id",
+            scoped(LEDGER_READ, get(ledger_query_handler)),
+        )
+        .route(
+            "/v1/ledger/:id/events",
+            scoped(LEDGER_READ, get(ledger_events_handler)),
+        )
+        .route(
+            "/v1/house_account",
+            scoped(LEDGER_READ, get(house_account_query_handler)).merge(scoped(
+                HOUSE_ACCOUNT_ADMIN,
+                post(house_account_create_handler),
+            )),
+        )
+        .route("/v1/currency", get(currency_query_handler))
+        .route("/v1/asset", scoped(ASSET_ADMIN, post(asset_create_handler)))
+        .route(
+            "/v1/user/:id",
+            scoped(BANK_ACCOUNT_READ, get(user_query_handler)),
+        )
+        .route(
+            "/v1/transaction",
+            scoped(BANK_ACCOUNT_READ, get(transaction_query_handler)),
+        )
+        .route(
+            "/v1/ach_file",
+            scoped(ACH_ADMIN, post(ach_file_create_handler)),
+        )
+        .route(
+            "/v1/ach_file/:id",
+            scoped(ACH_ADMIN, get(ach_file_query_handler)),
+        )
+        .route(
+            "/v1/ach_return",
+            scoped(ACH_ADMIN, post(ach_return_handler)),
+        )
+        .route(
+            "/v1/stream",
+            scoped(BANK_ACCOUNT_READ, get(stream_sse_handler)),
+        )
+        .route(
+            "/v1/stream/ws",
+            scoped(BANK_ACCOUNT_READ, get(stream_ws_handler)),
+        )
+        .route(
+            "/v1/webhook",
+            scoped(
+                WEBHOOK_ADMIN,
+                get(webhook_query_handler).post(webhook_create_handler),
+            ),
+        )
+        .route(
+            "/v1/webhook/:id",
+            scoped(WEBHOOK_ADMIN, delete(webhook_delete_handler)),
+        )
+        .route(
+            "/v1/webhook/:id/deliveries",
+            scoped(WEBHOOK_ADMIN, get(webhook_delivery_query_handler)),
+        )
+        .route(
+            "/v1/webhook_delivery/:id/replay",
+            scoped(WEBHOOK_ADMIN, post(webhook_delivery_replay_handler)),
+        )
+        .route(
+            "/v1/outbox",
+            scoped(OUTBOX_ADMIN, get(outbox_query_handler)),
+        )
+        .route(
+            "/v1/outbox/:id/retry",
+            scoped(OUTBOX_ADMIN, post(outbox_retry_handler)),
+        )
+        .route(
+            "/v1/outbox/:id/skip",
+            scoped(OUTBOX_ADMIN, post(outbox_skip_handler)),
+        )
+        .route("/v1/token/rotate", post(token_rotate_handler))
+        .route("/v1/token", scoped(TOKEN_ADMIN, get(token_query_handler)))
+        .route(
+            "/v1/token/:jti",
+            scoped(TOKEN_ADMIN, delete(token_revoke_handler)),
+        )
+        .route(
+            "/v1/tenant",
+            scoped(
+                TENANT_ADMIN,
+                get(tenant_query_handler).post(tenant_create_handler),
+            ),
+        )
+        .route(
+            "/v1/tenant/:id",
+            scoped(
+                TENANT_ADMIN,
+                get(tenant_get_handler).delete(tenant_delete_handler),
+            ),
+        )
+        .route(
+            "/v1/tenant/:id/suspend",
+            scoped(TENANT_ADMIN, post(tenant_suspend_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/reactivate",
+            scoped(TENANT_ADMIN, post(tenant_reactivate_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/scope",
+            scoped(TENANT_ADMIN, put(tenant_scope_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/rate_limit",
+            scoped(TENANT_ADMIN, put(tenant_rate_limit_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/credentials",
+            scoped(TENANT_ADMIN, post(tenant_credentials_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/token",
+            scoped(TENANT_ADMIN, get(tenant_token_query_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/token/:jti",
+            scoped(TENANT_ADMIN, delete(tenant_token_revoke_handler)),
+        )
+        .route(
+            "/v1/tenant/:id/audit",
+            scoped(TENANT_ADMIN, get(tenant_audit_handler)),
+        )
+        .route(
+            "/v1/admin/diagnostics",
+            scoped(TENANT_ADMIN, get(diagnostics_handler)),
+        )
+        .route("/metrics", scoped(METRICS_READ, get(metrics_handler)))
+        // Runs after `authorize`, which resolves the tenant.
+        .layer(middleware::from_fn(rate_limit::<C>))
+        .layer(middleware::from_fn(authorize::<C>))
+        // Routes added below are reachable without a token.
+        .route("/v1/oauth/token", post(oauth_token_handler))
+        .route("/.well-known/jwks.json", get(jwks_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .layer(AddExtensionLayer::new(state.clone()))
+        .layer(middleware::from_fn(track_requests))
+        .layer(comression_layer)
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::keys::{generate_signing_key, KeyRing, EDDSA};
+    use crate::auth::scope::{DEFAULT_SCOPES, SCOPES};
+    use crate::domain::diagnostics::{Diagnostics, OutboxBacklog};
+    use crate::domain::finance::{OUTBOX_PENDING, OUTBOX_PROCESSED};
+    use crate::domain::signing_key::SigningKey;
+    use crate::domain::tenant::{Tenant, TenantToken};
+    use crate::event_sourcing::metadata::Metadata;
+    use crate::repository::adapter::{Adapter, MockDatabaseClient};
+    use crate::state::CommandMessage;
+    use crate::stream::EventStream;
+    use axum::body::{to_bytes, Body};
+    use axum::extract::Request;
+    use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+    use futures::StreamExt;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+
+    const TENANT_ID: i32 = 1;
+    const OTHER_TENANT_ID: i32 = 2;
+
+    // A database whose only tenant is `TENANT_ID`, every resource looked up
+    // by id belongs to `OTHER_TENANT_ID`.
+    fn database() -> MockDatabaseClient {
+        database_with_token(|jti| tenant_token(jti, TOKEN_KIND_SERVICE))
+    }
+
+    // Like `database`, with `token` as the record of the caller's token.
+    fn database_with_token(
+        token: impl Fn(Uuid) -> TenantToken + Send + 'static,
+    ) -> MockDatabaseClient {
+        database_with_keys(token, vec![])
+    }
+
+    // Like `database_with_token`, with `keys` as the active signing keys.
+    fn database_with_keys(
+        token: impl Fn(Uuid) -> TenantToken + Send + 'static,
+        keys: Vec<SigningKey>,
+    ) -> MockDatabaseClient {
+        let mut db = MockDatabaseClient::new();
+        db.expect_get_tenant_profile().returning(|id| {
+            Ok(Tenant {
+                id,
+                name: "test_service".to_string(),
+                jwt: String::new(),
+                scope: Some(SCOPES.join(" ")),
+                status: "active".to_string(),
+                rate_limits: Default::default(),
+            })
+        });
+        db.expect_get_tenant_token()
+            .returning(move |jti| Ok(token(jti)));
+        db.expect_get_signing_keys()
+            .returning(move || Ok(keys.clone()));
+        db.expect_get_bank_account_tenant()
+            .returning(|_| Ok(OTHER_TENANT_ID));
+        db.expect_get_ledger_tenant()
+            .returning(|_| Ok(OTHER_TENANT_ID));
+        db
+    }
+
+    fn tenant_token(jti: Uuid, kind: &str) -> TenantToken {
+        TenantToken {
+            jti,
+            tenant_id: TENANT_ID,
+            kind: kind.to_string(),
+            scope: SCOPES.join(" "),
+            expires_at: (Utc::now() + Duration::hours(1)).naive_utc(),
+            revoked_at: None,
+        }
+    }
+
+    fn token() -> String {
+        token_with_scopes(&SCOPES)
+    }
+
+    fn token_with_scopes(scopes: &[&str]) -> String {
+        std::env::set_var("JWT_SECRET", "your_secret_key");
+        let claims = Claims {
+            sub: "test_service".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            iat: Utc::now().timestamp() as usize,
+            iss: "bankie".to_owned(),
+            aud: "service".to_owned(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            tenant_id: TENANT_ID,
+            jti: Some(Uuid::new_v4()),
+        };
+        let encoding_key = EncodingKey::from_secret("your_secret_key".as_bytes());
+        encode(&Header::default(), &claims, &encoding_key).unwrap()
+    }
+
+    // Stands in for the command processor: every command is answered with
+    // `outcome` and handed to the returned receiver.
+    fn command_channel(
+        outcome: Result<(), DomainError>,
+    ) -> (
+        mpsc::UnboundedSender<CommandMessage>,
+        mpsc::UnboundedReceiver<(BankAccountCommand, Metadata)>,
+    ) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<CommandMessage>();
+        let (forward, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some((command, metadata, reply)) = rx.recv().await {
+                forward.send((command, metadata)).unwrap();
+                reply.send(outcome.clone()).unwrap();
+            }
+        });
+        (tx, received)
+    }
+
+    async fn send(
+        state: ApplicationState<MockDatabaseClient>,
+        method: &str,
+        uri: &str,
+        body: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", token()))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router(Arc::new(state)).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_other_tenant_bank_account_is_not_found() {
+        for uri in [
+            "/v1/bank_account/account1",
+            "/v1/bank_account/account1/events",
+            "/v1/transaction?bank_account_id=account1&offset=0&limit=10",
+        ] {
+            let state = ApplicationState::new(Adapter::new(database()));
+            let (status, _) = send(state, "GET", uri, "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_other_tenant_ledger_is_not_found() {
+        for uri in [
+            "/v1/ledger/ledger1",
+            "/v1/ledger/ledger1?as_of=2024-01-01T00:00:00Z",
+            "/v1/ledger/ledger1/events",
+        ] {
+            let state = ApplicationState::new(Adapter::new(database()));
+            let (status, _) = send(state, "GET", uri, "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_on_other_tenant_account_is_not_sent() {
+        let (tx, mut rx) = command_channel(Ok(()));
+        let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+        let body = r#"{"Deposit": {"id": "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f", "amount": {"amount": "10.00", "currency": "USD"}}}"#;
+
+        let (status, _) = send(state, "POST", "/v1/bank_account", body).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_account_belongs_to_caller() {
+        let (tx, mut rx) = command_channel(Ok(()));
+        let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+        let body = r#"{"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": "USD", "user_id": "user1", "tenant_id": 2}}"#;
+
+        let (status, _) = send(state, "POST", "/v1/bank_account", body).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        match rx.try_recv().unwrap().0 {
+            BankAccountCommand::OpenAccount { tenant_id, .. } => assert_eq!(tenant_id, TENANT_ID),
+            command => panic!("Unexpected command: {:?}", command),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_is_refused_on_shutdown() {
+        let (tx, mut rx) = command_channel(Ok(()));
+        let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+        state.health.set_shutting_down();
+        let body = r#"{"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": "USD", "user_id": "user1", "tenant_id": 2}}"#;
+
+        let (status, body) = send(state, "POST", "/v1/bank_account", body).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "SHUTTING_DOWN");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_command_returns_problem() {
+        for (outcome, status, code) in [
+            (
+                DomainError::InsufficientFunds,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "INSUFFICIENT_FUNDS",
+            ),
+            (
+                DomainError::DuplicateAccount,
+                StatusCode::CONFLICT,
+                "DUPLICATE_ACCOUNT",
+            ),
+            (
+                DomainError::Internal("ledger write failed".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL_ERROR",
+            ),
+        ] {
+            let (tx, _rx) = command_channel(Err(outcome.clone()));
+            let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+            let body = r#"{"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": "USD", "user_id": "user1"}}"#;
+
+            let (actual, body) = send(state, "POST", "/v1/bank_account", body).await;
+
+            assert_eq!(actual, status);
+            assert_eq!(body["status"], status.as_u16());
+            assert_eq!(body["code"], code);
+            assert_eq!(body["detail"], outcome.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_currency_must_be_enabled() {
+        for (body, status) in [
+            (
+                r#"{"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": "KWD", "user_id": "user1"}}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"Deposit": {"id": "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f", "amount": {"amount": "10.000", "currency": "KWD"}}}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"Withdrawal": {"id": "5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f", "amount": {"amount": "10", "currency": "ABC"}}}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let (tx, mut rx) = command_channel(Ok(()));
+            let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+
+            let (actual, _) = send(state, "POST", "/v1/bank_account", body).await;
+
+            assert_eq!(actual, status, "{}", body);
+            assert!(rx.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_asset_create() {
+        let mut db = database();
+        db.expect_create_asset()
+            .withf(|asset| asset.tenant_id == TENANT_ID && asset.code == "ROUTEPTS")
+            .returning(Ok);
+        let body = r#"{"code": "ROUTEPTS", "name": "Reward points", "minor_units": 0}"#;
+
+        let (status, asset) = send(
+            ApplicationState::new(Adapter::new(db)),
+            "POST",
+            "/v1/asset",
+            body,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(asset["code"], "ROUTEPTS");
+        assert_eq!(asset["currency"], format!("{}:ROUTEPTS", TENANT_ID));
+        let (tx, mut rx) = command_channel(Ok(()));
+        let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+        let body = json!({"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": asset["currency"], "user_id": "user1"}});
+        let (status, _) = send(state, "POST", "/v1/bank_account", &body.to_string()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        match rx.try_recv().unwrap().0 {
+            BankAccountCommand::OpenAccount { currency, .. } => {
+                assert_eq!(currency.code(), format!("{}:ROUTEPTS", TENANT_ID));
+                assert_eq!(currency.precision(), 0);
+            }
+            command => panic!("Unexpected command: {:?}", command),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_asset_of_other_tenant_is_not_enabled() {
+        let other = Currency::register("OTHERPTS", 2, TENANT_ID + 1);
+        Currency::register("OTHERPTS", 2, TENANT_ID);
+        let (tx, mut rx) = command_channel(Ok(()));
+        let state = ApplicationState::new(Adapter::new(database())).with_command_sender(tx);
+        let body = json!({"OpenAccount": {"account_type": "Retail", "kind": "Interest", "currency": other, "user_id": "user1"}});
+
+        let (status, body) = send(state, "POST", "/v1/bank_account", &body.to_string()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["detail"],
+            format!("currency not enabled: {}:OTHERPTS", TENANT_ID + 1)
+        );
+        assert_eq!(body["code"], "CURRENCY_NOT_ENABLED");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_house_account_in_asset_of_other_tenant() {
+        let other = Currency::register("HOUSEPTS", 0, TENANT_ID + 1);
+        let state = ApplicationState::new(Adapter::new(database()));
+        let body = json!({"status": "active", "account_name": "Points", "account_type": "Asset", "currency": other});
+
+        let (status, body) = send(state, "POST", "/v1/house_account", &body.to_string()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "CURRENCY_NOT_ENABLED");
+    }
+
+    #[tokio::test]
+    async fn test_asset_create_rejects_invalid_code() {
+        let state = ApplicationState::new(Adapter::new(database()));
+        let body = r#"{"code": "EUR", "name": "Not an asset", "minor_units": 2}"#;
+
+        let (status, _) = send(state, "POST", "/v1/asset", body).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_house_account_unknown_currency() {
+        let state = ApplicationState::new(Adapter::new(database()));
+
+        let (status, _) = send(state, "GET", "/v1/house_account?currency=usd", "").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_currency_list() {
+        let state = ApplicationState::new(Adapter::new(database()));
+
+        let (status, body) = send(state, "GET", "/v1/currency", "").await;
+
+        assert_eq!(status, StatusCode::OK);
+        let jpy = body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["code"] == "JPY")
+            .unwrap();
+        assert_eq!(jpy["numeric_code"], 392);
+        assert_eq!(jpy["minor_units"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_lists_are_scoped_to_caller() {
+        let mut db = database();
+        db.expect_get_user_bank_accounts()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Ok(vec![]));
+        db.expect_get_house_accounts()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Ok(vec![]));
+        db.expect_get_webhook_endpoints()
+            .withf(|tenant_id| *tenant_id == TENANT_ID)
+            .returning(|_| Ok(vec![]));
+        db.expect_get_webhook_deliveries()
+            .withf(|tenant_id, _, _| *tenant_id == TENANT_ID)
+            .returning(|_, _, _| Ok(vec![]));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+
+        for uri in [
+            "/v1/user/user1",
+            "/v1/house_account?currency=USD",
+            "/v1/webhook",
+            "/v1/webhook/5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f/deliveries",
+        ] {
+            let request = Request::builder()
+                .uri(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", token()))
+                .body(Body::empty())
+                .unwrap();
+            let response = router(state.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ach_files_are_scoped_to_caller() {
+        let mut db = database();
+        db.expect_get_pending_ach_withdrawals()
+            .withf(|tenant_id| *tenant_id == TENANT_ID)
+            .returning(|_| Ok(vec![]));
+        db.expect_get_ach_file()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        db.expect_get_house_account()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+
+        for (method, uri) in [
+            ("POST", "/v1/ach_file"),
+            ("GET", "/v1/ach_file/5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f"),
+            ("POST", "/v1/ach_return"),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", token()))
+                .body(Body::empty())
+                .unwrap();
+            let response = router(state.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    fn outbox_entry(status: &str) -> OutboxEntry {
+        OutboxEntry {
+            id: 42,
+            transaction_id: Uuid::new_v4(),
+            event_type: "LedgerCommand::Credit".to_string(),
+            payload: json!({}),
+            status: status.to_string(),
+            attempts: 6,
+            next_attempt_at: Utc::now().naive_utc(),
+            last_error: Some("Failed to write ledger".to_string()),
+            hold_released: false,
+            created_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outbox_is_scoped_to_caller() {
+        let mut db = database();
+        db.expect_get_outbox_entries()
+            .withf(|tenant_id, status| *tenant_id == TENANT_ID && status == OUTBOX_DEAD)
+            .returning(|_, _| Ok(vec![outbox_entry(OUTBOX_DEAD)]));
+        db.expect_get_outbox_entry()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+
+        let (status, body) = send(state.as_ref().clone(), "GET", "/v1/outbox", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["entries"][0]["id"], 42);
+
+        for uri in ["/v1/outbox/42/retry", "/v1/outbox/42/skip"] {
+            let (status, _) = send(state.as_ref().clone(), "POST", uri, "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outbox_only_acts_on_dead_letters() {
+        for (entry_status, uri, status) in [
+            (OUTBOX_PENDING, "/v1/outbox/42/retry", StatusCode::CONFLICT),
+            (OUTBOX_PROCESSED, "/v1/outbox/42/skip", StatusCode::CONFLICT),
+            (OUTBOX_DEAD, "/v1/outbox/42/retry", StatusCode::ACCEPTED),
+            (OUTBOX_DEAD, "/v1/outbox/42/skip", StatusCode::NO_CONTENT),
+        ] {
+            let mut db = database();
+            db.expect_get_outbox_entry()
+                .returning(move |_, _| Ok(outbox_entry(entry_status)));
+            db.expect_retry_outbox()
+                .withf(|tenant_id, id| *tenant_id == TENANT_ID && *id == 42)
+                .returning(|_, _| Ok(()));
+            db.expect_skip_outbox()
+                .withf(|tenant_id, id| *tenant_id == TENANT_ID && *id == 42)
+                .returning(|_, _| Ok(()));
+
+            let (actual, _) = send(ApplicationState::new(Adapter::new(db)), "POST", uri, "").await;
+
+            assert_eq!(actual, status, "{} {}", entry_status, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_other_tenant_webhooks_are_not_found() {
+        let mut db = database();
+        db.expect_disable_webhook_endpoint()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        db.expect_replay_webhook_delivery()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|_, _| Err(sqlx::Error::RowNotFound));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+
+        for (method, uri) in [
+            ("DELETE", "/v1/webhook/5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f"),
+            (
+                "POST",
+                "/v1/webhook_delivery/5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f/replay",
+            ),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", token()))
+                .body(Body::empty())
+                .unwrap();
+            let response = router(state.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_belongs_to_caller() {
+        let mut db = database();
+        db.expect_create_webhook_endpoint()
+            .withf(|endpoint| endpoint.tenant_id == TENANT_ID)
+            .returning(|endpoint| Ok(endpoint.id));
+        let state = ApplicationState::new(Adapter::new(db));
+        let body = r#"{"url": "https://example.com/hook", "event_types": ["ledger.updated"], "tenant_id": 2}"#;
+
+        let (status, _) = send(state, "POST", "/v1/webhook", body).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_stream_skips_other_tenant_events() {
+        let stream = Arc::new(EventStream::new(10, 10));
+        stream.publish(OTHER_TENANT_ID, "ledger.updated", "a".into(), json!({}));
+        stream.publish(TENANT_ID, "ledger.updated", "b".into(), json!({}));
+        let state = ApplicationState::new(Adapter::new(database())).with_stream(stream);
+        let request = Request::builder()
+            .uri("/v1/stream?since=0")
+            .header(AUTHORIZATION, format!("Bearer {}", token()))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router(Arc::new(state)).oneshot(request).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+        let frame = body.next().await.unwrap().unwrap();
+
+        let frame = String::from_utf8(frame.to_vec()).unwrap();
+        assert!(frame.contains("id: 2"), "{}", frame);
+        assert!(!frame.contains("id: 1\n"), "{}", frame);
+    }
+
+    async fn probe(state: ApplicationState<MockDatabaseClient>, uri: &str) -> (StatusCode, Value) {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = router(Arc::new(state)).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_probes_need_no_token() {
+        let mut db = MockDatabaseClient::new();
+        db.expect_ping().returning(|| Ok(()));
+        let state = ApplicationState::new(Adapter::new(db));
+        state.health.set_scheduler_running(true);
+
+        let (status, body) = probe(state.clone(), "/healthz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+
+        let (status, body) = probe(state, "/readyz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["database"], "ok");
+        assert_eq!(body["redis"], "disabled");
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_without_database() {
+        let mut db = MockDatabaseClient::new();
+        db.expect_ping()
+            .returning(|| Err(sqlx::Error::PoolTimedOut));
+        let state = ApplicationState::new(Adapter::new(db));
+        state.health.set_scheduler_running(true);
+
+        let (status, body) = probe(state, "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["database"], "failed");
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_requires_tenant_admin() {
+        let mut db = database();
+        db.expect_get_diagnostics()
+            .withf(|secs| *secs == SETTINGS.health.stuck_transaction_secs)
+            .returning(|_| {
+                Ok(Diagnostics {
+                    stuck_transactions: 2,
+                    ..Default::default()
+                })
+            });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(state.clone(), "GET", "/v1/admin/diagnostics", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["stuck_transactions"], 2);
+        assert_eq!(body["queued_commands"], 0);
+
+        let request = Request::builder()
+            .uri("/v1/admin/diagnostics")
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", token_with_scopes(&DEFAULT_SCOPES)),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = router(Arc::new(state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let mut db = database();
+        db.expect_get_outbox_backlog().returning(|| {
+            Ok(OutboxBacklog {
+                pending: 3,
+                dead: 1,
+                oldest_pending_secs: Some(90),
+            })
+        });
+        db.expect_get_ledger_totals().returning(|| Ok(vec![]));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+        let (status, _) = send(state.as_ref().clone(), "GET", "/v1/ledger/ledger1", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let request = Request::builder()
+            .uri("/metrics")
+            .header(AUTHORIZATION, format!("Bearer {}", token()))
+            .body(Body::empty())
+            .unwrap();
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains("bankie_outbox_pending 3"));
+        assert!(text.contains("bankie_outbox_lag_seconds 90"));
+        // Requests are counted by route, the id in the path is not a label.
+        assert!(text.contains(r#"route="/v1/ledger/:id",status="404""#));
+
+        let request = Request::builder()
+            .uri("/metrics")
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", token_with_scopes(&DEFAULT_SCOPES)),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_house_account_create_requires_admin_scope() {
+        let state = Arc::new(ApplicationState::new(Adapter::new(database())));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/house_account")
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", token_with_scopes(&DEFAULT_SCOPES)),
+            )
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+
+        let response = router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["detail"], "Missing scope: house-account:admin");
+    }
+
+    async fn send_form(
+        state: ApplicationState<MockDatabaseClient>,
+        body: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/oauth/token")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router(Arc::new(state)).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_revoked_token_is_rejected() {
+        let db = database_with_token(|jti| {
+            let mut token = tenant_token(jti, TOKEN_KIND_SERVICE);
+            token.revoked_at = Some(Utc::now().naive_utc() - Duration::seconds(1));
+            token
+        });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, _) = send(state, "GET", "/v1/webhook", "").await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_token_signed_with_published_key() {
+        let key = generate_signing_key(EDDSA).unwrap();
+        let keys = KeyRing::new(vec![key.clone()], None).unwrap();
+        let mut db = database_with_keys(|jti| tenant_token(jti, TOKEN_KIND_SERVICE), vec![key]);
+        db.expect_get_webhook_endpoints().returning(|_| Ok(vec![]));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+
+        let request = Request::builder()
+            .uri("/.well-known/jwks.json")
+            .body(Body::empty())
+            .unwrap();
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let jwks: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(jwks, serde_json::to_value(keys.jwks()).unwrap());
+        assert_eq!(jwks["keys"][0]["alg"], "EdDSA");
+        assert!(jwks["keys"][0].get("d").is_none());
+
+        let claims = Claims {
+            sub: "test_service".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            iat: Utc::now().timestamp() as usize,
+            iss: "bankie".to_owned(),
+            aud: "service".to_owned(),
+            scopes: vec![WEBHOOK_ADMIN.to_string()],
+            tenant_id: TENANT_ID,
+            jti: Some(Uuid::new_v4()),
+        };
+        let request = Request::builder()
+            .uri("/v1/webhook")
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", keys.encode(&claims).unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_grant() {
+        let mut db = database();
+        db.expect_get_tenant_client_secret_hash()
+            .withf(|tenant_id| *tenant_id == TENANT_ID)
+            .returning(|_| Ok(hash_secret("secret1")));
+        db.expect_create_tenant_token()
+            .withf(|token| token.kind == TOKEN_KIND_ACCESS && token.scope == "ledger:read")
+            .returning(|_| Ok(()));
+        let state = ApplicationState::new(Adapter::new(db));
+        std::env::set_var("JWT_SECRET", "your_secret_key");
+
+        let (status, body) = send_form(
+            state,
+            "grant_type=client_credentials&client_id=1&client_secret=secret1&scope=ledger%3Aread",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["token_type"], "Bearer");
+        assert_eq!(body["expires_in"], SETTINGS.auth.access_token_ttl_secs);
+        let claims = KeyRing::new(vec![], Some("your_secret_key".to_string()))
+            .unwrap()
+            .decode(body["access_token"].as_str().unwrap())
+            .unwrap()
+            .claims;
+        assert_eq!(claims.scopes, vec![LEDGER_READ]);
+        assert!(claims.jti.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_grant_errors() {
+        for (body, status, error) in [
+            (
+                "grant_type=password&client_id=1&client_secret=secret1",
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+            ),
+            (
+                "grant_type=client_credentials&client_id=1&client_secret=wrong",
+                StatusCode::UNAUTHORIZED,
+                "invalid_client",
+            ),
+            (
+                "grant_type=client_credentials&client_id=1&client_secret=secret1&scope=root",
+                StatusCode::BAD_REQUEST,
+                "invalid_scope",
+            ),
+        ] {
+            let mut db = database();
+            db.expect_get_tenant_client_secret_hash()
+                .returning(|_| Ok(hash_secret("secret1")));
+            let state = ApplicationState::new(Adapter::new(db));
+
+            let (actual, response) = send_form(state, body).await;
+
+            assert_eq!(actual, status, "{}", body);
+            assert_eq!(response["error"], error, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_rotation_keeps_grace_period() {
+        let mut db = database();
+        db.expect_create_tenant_token()
+            .withf(|token| token.kind == TOKEN_KIND_SERVICE)
+            .returning(|_| Ok(()));
+        db.expect_revoke_tenant_token()
+            .withf(|tenant_id, _, revoked_at| {
+                *tenant_id == TENANT_ID && *revoked_at > Utc::now().naive_utc()
+            })
+            .returning(|_, jti, revoked_at| {
+                let mut token = tenant_token(jti, TOKEN_KIND_SERVICE);
+                token.revoked_at = Some(revoked_at);
+                Ok(token)
+            });
+        db.expect_update_tenant_profile()
+            .withf(|tenant_id, _| *tenant_id == TENANT_ID)
+            .returning(|tenant_id, _| Ok(tenant_id));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(state, "POST", "/v1/token/rotate", "").await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(body["token"].is_string());
+        assert!(body["previous_revoked_at"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_rotated_token_cannot_be_rotated_again() {
+        // The caller's token record, revoked by the first rotation.
+        let revoked_at = Arc::new(std::sync::Mutex::new(None));
+        let record = revoked_at.clone();
+        let mut db = database_with_token(move |jti| {
+            let mut token = tenant_token(jti, TOKEN_KIND_SERVICE);
+            token.revoked_at = *record.lock().unwrap();
+            token
+        });
+        db.expect_create_tenant_token()
+            .times(1)
+            .returning(|_| Ok(()));
+        let revoked = revoked_at.clone();
+        db.expect_revoke_tenant_token()
+            .returning(move |_, jti, at| {
+                *revoked.lock().unwrap() = Some(at);
+                let mut token = tenant_token(jti, TOKEN_KIND_SERVICE);
+                token.revoked_at = Some(at);
+                Ok(token)
+            });
+        db.expect_update_tenant_profile()
+            .returning(|tenant_id, _| Ok(tenant_id));
+        let state = Arc::new(ApplicationState::new(Adapter::new(db)));
+        let token = token();
+
+        let mut statuses = vec![];
+        for _ in 0..2 {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/v1/token/rotate")
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap();
+            let response = router(state.clone()).oneshot(request).await.unwrap();
+            statuses.push(response.status());
+        }
+
+        assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+    }
+
+    #[tokio::test]
+    async fn test_access_token_cannot_be_rotated() {
+        let db = database_with_token(|jti| tenant_token(jti, TOKEN_KIND_ACCESS));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, _) = send(state, "POST", "/v1/token/rotate", "").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_other_tenant_token_revocation_is_not_found() {
+        let mut db = database();
+        db.expect_revoke_tenant_token()
+            .withf(|tenant_id, _, _| *tenant_id == TENANT_ID)
+            .returning(|_, _, _| Err(sqlx::Error::RowNotFound));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, _) = send(
+            state,
+            "DELETE",
+            "/v1/token/5f8b1a4e-1d7c-4c64-9b0e-8f7c1c2d3e4f",
+            "",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    fn tenant_summary(id: i32, status: &str) -> TenantSummary {
+        TenantSummary {
+            id,
+            name: "acme".to_string(),
+            status: status.to_string(),
+            scope: LEDGER_READ.to_string(),
+            rate_limits: json!({}),
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tenant_admin_requires_scope() {
+        let state = Arc::new(ApplicationState::new(Adapter::new(database())));
+        let request = Request::builder()
+            .uri("/v1/tenant")
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", token_with_scopes(&DEFAULT_SCOPES)),
+            )
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_create() {
+        let mut db = database();
+        db.expect_create_tenant_profile()
+            .withf(|name, scope, audit| {
+                name == "acme"
+                    && scope == LEDGER_READ
+                    && audit.as_ref().is_some_and(|entry| {
+                        entry.action == "created"
+                            && entry.actor_tenant_id == TENANT_ID
+                            && entry.actor == "test_service"
+                    })
+            })
+            .returning(|_, _, _| Ok(7));
+        db.expect_create_tenant_token()
+            .withf(|token| token.tenant_id == 7 && token.kind == TOKEN_KIND_SERVICE)
+            .returning(|_| Ok(()));
+        db.expect_set_tenant_client_secret_hash()
+            .returning(|_, _| Ok(()));
+        db.expect_update_tenant_profile().returning(|id, _| Ok(id));
+        db.expect_get_tenant()
+            .returning(|id| Ok(tenant_summary(id, TENANT_ACTIVE)));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(
+            state,
+            "POST",
+            "/v1/tenant",
+            r#"{"name":"acme","scope":["ledger:read"]}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["tenant"]["id"], 7);
+        assert_eq!(body["client_id"], 7);
+        assert!(body["token"].as_str().is_some_and(|t| !t.is_empty()));
+        assert_eq!(body["client_secret"].as_str().unwrap().len(), 48);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_create_unknown_scope() {
+        let state = ApplicationState::new(Adapter::new(database()));
+
+        let (status, body) = send(
+            state,
+            "POST",
+            "/v1/tenant",
+            r#"{"name":"acme","scope":["root"]}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["detail"], "Unknown scope: root");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_cannot_suspend_itself() {
+        let state = ApplicationState::new(Adapter::new(database()));
+
+        let (status, _) = send(
+            state,
+            "POST",
+            &format!("/v1/tenant/{}/suspend", TENANT_ID),
+            "",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_suspend_requires_active() {
+        let mut db = database();
+        db.expect_get_tenant()
+            .returning(|id| Ok(tenant_summary(id, TENANT_DELETED)));
+        db.expect_update_tenant_status()
+            .withf(|_, from, status, _| from == &[TENANT_ACTIVE] && status == TENANT_SUSPENDED)
+            .returning(|_, _, _, _| Err(sqlx::Error::RowNotFound));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(state, "POST", "/v1/tenant/7/suspend", "").await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["detail"], "Tenant 7 is deleted");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_delete_revokes_tokens() {
+        let mut db = database();
+        db.expect_get_tenant()
+            .returning(|id| Ok(tenant_summary(id, TENANT_SUSPENDED)));
+        db.expect_update_tenant_status()
+            .withf(|id, _, status, audit| {
+                *id == 7
+                    && status == TENANT_DELETED
+                    && audit.action == "deleted"
+                    && audit.detail["from"] == "suspended"
+            })
+            .times(1)
+            .returning(|id, _, _, _| Ok(tenant_summary(id, TENANT_DELETED)));
+        db.expect_revoke_tenant_tokens()
+            .withf(|id, _| *id == 7)
+            .times(1)
+            .returning(|_, _| Ok(vec![]));
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, _) = send(state, "DELETE", "/v1/tenant/7", "").await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_scope_update_is_audited() {
+        let mut db = database();
+        db.expect_get_tenant()
+            .returning(|id| Ok(tenant_summary(id, TENANT_ACTIVE)));
+        db.expect_update_tenant_scope()
+            .withf(|id, scope, audit| {
+                *id == 7
+                    && scope == "ledger:read ach:admin"
+                    && audit.action == "scope_updated"
+                    && audit.detail
+                        == json!({ "from": "ledger:read", "to": "ledger:read ach:admin" })
+            })
+            .returning(|id, scope, _| {
+                let mut tenant = tenant_summary(id, TENANT_ACTIVE);
+                tenant.scope = scope.to_string();
+                Ok(tenant)
+            });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(
+            state,
+            "PUT",
+            "/v1/tenant/7/scope",
+            r#"{"scope":["ledger:read","ach:admin"]}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["scope"], "ledger:read ach:admin");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_rate_limit_update() {
+        let mut db = database();
+        db.expect_get_tenant()
+            .returning(|id| Ok(tenant_summary(id, TENANT_ACTIVE)));
+        db.expect_update_tenant_rate_limits()
+            .withf(|id, rate_limits, audit| {
+                *id == 7
+                    && rate_limits["money"]["burst"] == 5
+                    && rate_limits.get("read").is_none()
+                    && audit.action == "rate_limit_updated"
+                    && audit.detail["from"] == json!({})
+            })
+            .returning(|id, rate_limits, _| {
+                let mut tenant = tenant_summary(id, TENANT_ACTIVE);
+                tenant.rate_limits = rate_limits;
+                Ok(tenant)
+            });
+        let state = ApplicationState::new(Adapter::new(db));
+
+        let (status, body) = send(
+            state,
+            "PUT",
+            "/v1/tenant/7/rate_limit",
+            r#"{"money":{"rate":1.5,"burst":5,"daily_quota":1000}}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["rate_limits"]["money"]["daily_quota"], 1000);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_rate_limit_rejects_invalid_limits() {
+        let state = ApplicationState::new(Adapter::new(database()));
+
+        let (status, body) = send(
+            state,
+            "PUT",
+            "/v1/tenant/7/rate_limit",
+            r#"{"read":{"rate":0,"burst":5,"daily_quota":0}}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["detail"], "read: rate must be greater than 0");
+    }
+}
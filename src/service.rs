@@ -13,7 +13,8 @@ use crate::{
         finance::{JournalEntry, JournalLine, Transaction},
         models::{BankAccountKind, BankAccountStatus, BankAccountView, HouseAccount, LedgerAction},
     },
-    event_sourcing::command::LedgerCommand,
+    event_sourcing::{command::LedgerCommand, error::DomainError, metadata},
+    metrics::{timed_execute, AGGREGATE_LEDGER},
     repository::adapter::Adapter,
     state::{BankAccountLoader, LedgerLoaderSaver},
 };
@@ -33,7 +34,11 @@ impl BankAccountServices {
 // External services must be called during the processing of the command.
 #[async_trait]
 pub trait BankAccountApi: Sync + Send {
-    async fn get_house_account(&self, currency: Currency) -> Result<HouseAccount, anyhow::Error>;
+    async fn get_house_account(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<HouseAccount, anyhow::Error>;
     async fn note_ledger(&self, id: String, command: LedgerCommand) -> Result<(), anyhow::Error>;
     async fn create_transaction_with_journal(
         &self,
@@ -51,6 +56,7 @@ pub trait BankAccountApi: Sync + Send {
     async fn validate_account_creation(
         &self,
         account_id: Uuid,
+        tenant_id: i32,
         user_id: String,
         currency: Currency,
         kind: BankAccountKind,
@@ -75,11 +81,13 @@ pub struct BankAccountLogic {
 impl BankAccountApi for BankAccountLogic {
     async fn note_ledger(&self, id: String, command: LedgerCommand) -> Result<(), anyhow::Error> {
         // Should call ledger commange to write the transaction.
-        self.ledger
+        let execute = self
+            .ledger
             .cqrs
-            .execute(&id, command)
+            .execute_with_metadata(&id, command, metadata::current());
+        timed_execute(AGGREGATE_LEDGER, execute)
             .await
-            .map_err(|e| anyhow!("Failed to write ledger: {}", e))
+            .map_err(|e| DomainError::from(e).into())
     }
 
     async fn create_transaction_with_journal(
@@ -90,7 +98,13 @@ impl BankAccountApi for BankAccountLogic {
         journal_lines: Vec<JournalLine>,
     ) -> Result<Uuid, anyhow::Error> {
         self.database
-            .create_transaction_with_journal(transaction, ledger_id, journal_entry, journal_lines)
+            .create_transaction_with_journal(
+                transaction,
+                ledger_id,
+                journal_entry,
+                journal_lines,
+                metadata::current(),
+            )
             .await
             .map_err(|e| anyhow!("Failed to write transaction: {}", e))
     }
@@ -103,13 +117,19 @@ impl BankAccountApi for BankAccountLogic {
     ) -> Result<(), anyhow::Error> {
         match self.bank_account.query.load(&account_id.to_string()).await {
             Ok(view) => match view {
-                None => error!("Account not found"),
+                None => return Err(DomainError::AccountNotFound.into()),
                 Some(account_view) => {
-                    if account_view.status != BankAccountStatus::Approved {
-                        return Err(anyhow!("Account is not active"));
+                    match account_view.status {
+                        BankAccountStatus::Approved => {}
+                        BankAccountStatus::Freeze => return Err(DomainError::AccountFrozen.into()),
+                        _ => return Err(DomainError::AccountNotActive.into()),
                     }
                     if account_view.currency != amount.currency {
-                        return Err(anyhow!("Currency invalid"));
+                        return Err(DomainError::CurrencyMismatch(
+                            account_view.currency,
+                            amount.currency,
+                        )
+                        .into());
                     }
 
                     match self.ledger.query.load(&account_view.ledger_id).await {
@@ -117,9 +137,14 @@ impl BankAccountApi for BankAccountLogic {
                             None => error!("Ledger not found"),
                             Some(ledger_view) => {
                                 if action == LedgerAction::Withdraw
-                                    && ledger_view.available < amount
+                                    && ledger_view
+                                        .available
+                                        .try_sub(amount)
+                                        .map_err(DomainError::from)?
+                                        .amount
+                                        .is_sign_negative()
                                 {
-                                    return Err(anyhow!("Insufficient funds"));
+                                    return Err(DomainError::InsufficientFunds.into());
                                 }
                             }
                         },
@@ -137,16 +162,24 @@ impl BankAccountApi for BankAccountLogic {
         Ok(())
     }
 
-    async fn get_house_account(&self, currency: Currency) -> Result<HouseAccount, anyhow::Error> {
+    async fn get_house_account(
+        &self,
+        tenant_id: i32,
+        currency: Currency,
+    ) -> Result<HouseAccount, anyhow::Error> {
         self.database
-            .get_house_account(currency)
+            .get_house_account(tenant_id, currency)
             .await
-            .map_err(|e| anyhow!("Failed to get house account: {}", e))
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => DomainError::HouseAccountNotFound.into(),
+                e => anyhow!("Failed to get house account: {}", e),
+            })
     }
 
     async fn validate_account_creation(
         &self,
         account_id: Uuid,
+        tenant_id: i32,
         user_id: String,
         currency: Currency,
         kind: BankAccountKind,
@@ -158,15 +191,15 @@ impl BankAccountApi for BankAccountLogic {
             .await?)
             .is_some()
         {
-            return Err(anyhow!("Account duplicated"));
+            return Err(DomainError::DuplicateAccount.into());
         }
 
         let valid = self
             .database
-            .validate_bank_account_exists(user_id, currency, kind)
+            .validate_bank_account_exists(tenant_id, user_id, currency, kind)
             .await?;
         if !valid {
-            return Err(anyhow!("Account duplicated"));
+            return Err(DomainError::DuplicateAccount.into());
         }
 
         Ok(true)
@@ -175,7 +208,7 @@ impl BankAccountApi for BankAccountLogic {
     async fn get_bank_account(&self, account_id: Uuid) -> Result<BankAccountView, anyhow::Error> {
         match self.bank_account.query.load(&account_id.to_string()).await {
             Ok(view) => match view {
-                None => Err(anyhow!("Account not found")),
+                None => Err(DomainError::AccountNotFound.into()),
                 Some(account_view) => Ok(account_view),
             },
             Err(err) => Err(err.into()),
@@ -195,9 +228,14 @@ impl BankAccountApi for BankAccountLogic {
             transaction_id,
             amount,
         };
-        match self.ledger.cqrs.execute(&ledger_id.to_string(), cmd).await {
+        let id = ledger_id.to_string();
+        let execute = self
+            .ledger
+            .cqrs
+            .execute_with_metadata(&id, cmd, metadata::current());
+        match timed_execute(AGGREGATE_LEDGER, execute).await {
             Ok(_) => Ok(()),
-            Err(err) => Err(anyhow!("Failed to debit hold: {}", err)),
+            Err(err) => Err(DomainError::from(err).into()),
         }
     }
 }
@@ -1,23 +1,42 @@
 use std::sync::Arc;
 
-use postgres_es::{default_postgress_pool, PostgresCqrs, PostgresViewRepository};
+use postgres_es::{
+    default_postgress_pool, PostgresCqrs, PostgresEventRepository, PostgresViewRepository,
+};
 use sqlx::PgPool;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
 
+use crate::auth::keys::KeyCache;
 use crate::configs::settings::SETTINGS;
 use crate::domain::models::{BankAccount, BankAccountView, Ledger, LedgerView};
 use crate::event_sourcing::command::BankAccountCommand;
+use crate::event_sourcing::error::DomainError;
+use crate::event_sourcing::history::EventHistory;
+use crate::event_sourcing::metadata::Metadata;
+use crate::health::Health;
 use crate::repository::adapter::{Adapter, DatabaseClient};
 use crate::repository::configs::{configure_bank_account, configure_ledger};
+use crate::stream::EventStream;
 use crate::SharedState;
 
+// A bank account command with the sender its outcome is reported back on.
+pub type CommandMessage = (
+    BankAccountCommand,
+    Metadata,
+    oneshot::Sender<Result<(), DomainError>>,
+);
+
 #[derive(Clone)]
 pub struct ApplicationState<C: DatabaseClient + Send + Sync> {
     pub bank_account: Option<BankAccountLoaderSaver>,
     pub ledger: Option<LedgerLoaderSaver>,
     pub database: Arc<Adapter<C>>,
     pub cache: Option<Arc<redis::Client>>,
-    pub command_sender: Option<Arc<UnboundedSender<BankAccountCommand>>>,
+    pub command_sender: Option<Arc<UnboundedSender<CommandMessage>>>,
+    pub stream: Option<Arc<EventStream>>,
+    pub keys: Arc<KeyCache>,
+    pub health: Arc<Health>,
 }
 
 impl<C: DatabaseClient + Send + Sync> ApplicationState<C> {
@@ -28,6 +47,9 @@ impl<C: DatabaseClient + Send + Sync> ApplicationState<C> {
             database: Arc::new(database),
             cache: None,
             command_sender: None,
+            stream: None,
+            keys: Arc::new(KeyCache::default()),
+            health: Arc::new(Health::default()),
         }
     }
 
@@ -46,16 +68,22 @@ impl<C: DatabaseClient + Send + Sync> ApplicationState<C> {
         self
     }
 
-    pub fn with_command_sender(mut self, sender: UnboundedSender<BankAccountCommand>) -> Self {
+    pub fn with_command_sender(mut self, sender: UnboundedSender<CommandMessage>) -> Self {
         self.command_sender = Some(Arc::new(sender));
         self
     }
+
+    pub fn with_stream(mut self, stream: Arc<EventStream>) -> Self {
+        self.stream = Some(stream);
+        self
+    }
 }
 
 #[derive(Clone)]
 pub struct BankAccountLoaderSaver {
     pub cqrs: Arc<PostgresCqrs<BankAccount>>,
     pub query: Arc<PostgresViewRepository<BankAccountView, BankAccount>>,
+    pub history: Arc<EventHistory>,
 }
 
 #[derive(Clone)]
@@ -67,30 +95,28 @@ pub struct BankAccountLoader {
 pub struct LedgerLoaderSaver {
     pub cqrs: Arc<PostgresCqrs<Ledger>>,
     pub query: Arc<PostgresViewRepository<LedgerView, Ledger>>,
+    pub events: Arc<PostgresEventRepository>,
+    pub history: Arc<EventHistory>,
 }
 
-pub async fn new_application_state(tx: UnboundedSender<BankAccountCommand>) -> SharedState {
+pub async fn new_application_state(tx: UnboundedSender<CommandMessage>) -> SharedState {
     // Configure the CQRS framework, backed by a Postgres database, along with two queries:
     // - a simply-query prints events to stdout as they are published
     // - `query` stores the current state of the account in a ViewRepository that we can access
     let pool: PgPool = default_postgress_pool(&SETTINGS.database.connection_string()).await;
-    let (ledger_cqrs, ledger_query) = configure_ledger(pool.clone());
-    let ledger_loader_saver = LedgerLoaderSaver {
-        cqrs: ledger_cqrs,
-        query: ledger_query,
-    };
-    let (bc_cqrs, bc_query) = configure_bank_account(pool.clone(), ledger_loader_saver.clone());
+    let stream = Arc::new(EventStream::default());
+    let ledger_loader_saver = configure_ledger(pool.clone(), stream.clone());
+    let bank_account_loader_saver =
+        configure_bank_account(pool.clone(), ledger_loader_saver.clone(), stream.clone());
 
     let cache = redis::Client::open(SETTINGS.redis.connection_string()).unwrap();
 
     Arc::new(
         ApplicationState::<PgPool>::new(Adapter::new(pool))
             .with_cache(cache)
-            .with_bank_account(BankAccountLoaderSaver {
-                cqrs: bc_cqrs,
-                query: bc_query,
-            })
+            .with_bank_account(bank_account_loader_saver)
             .with_ledger(ledger_loader_saver)
-            .with_command_sender(tx),
+            .with_command_sender(tx)
+            .with_stream(stream),
     )
 }
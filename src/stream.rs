@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use axum::extract::ws::{Message, WebSocket};
+use axum::response::sse::Event;
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use crate::configs::settings::SETTINGS;
+
+pub const BALANCE_UPDATED: &str = "balance";
+pub const ACCOUNT_STATUS: &str = "account_status";
+const LAGGED: &str = "lagged";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEvent {
+    // Position in the stream, used by clients to resume after a reconnect.
+    pub id: u64,
+    #[serde(skip)]
+    pub tenant_id: i32,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub account_id: String,
+    pub data: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamFilter {
+    pub account_id: Option<String>,
+    // Last event id seen by the client, older buffered events are skipped.
+    pub since: Option<u64>,
+}
+
+impl StreamFilter {
+    fn matches(&self, tenant_id: i32, event: &StreamEvent) -> bool {
+        tenant_id == event.tenant_id
+            && self
+                .account_id
+                .as_ref()
+                .is_none_or(|id| *id == event.account_id)
+    }
+}
+
+#[derive(Debug)]
+pub enum StreamItem {
+    Event(StreamEvent),
+    // The subscriber fell behind and missed this many events, it should
+    // reload the current state from the query endpoints.
+    Lagged(u64),
+}
+
+impl StreamItem {
+    pub fn to_sse(&self) -> Event {
+        match self {
+            StreamItem::Event(event) => Event::default()
+                .id(event.id.to_string())
+                .event(&event.event_type)
+                .json_data(event)
+                .unwrap_or_default(),
+            StreamItem::Lagged(skipped) => Event::default()
+                .event(LAGGED)
+                .data(json!({ "skipped": skipped }).to_string()),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            StreamItem::Event(event) => json!(event),
+            StreamItem::Lagged(skipped) => json!({ "type": LAGGED, "skipped": skipped }),
+        }
+    }
+}
+
+struct History {
+    next_id: u64,
+    events: VecDeque<StreamEvent>,
+}
+
+/// Fan-out of committed account and ledger changes to connected clients. The
+/// most recent events are kept so a client can resume from the last id it saw.
+pub struct EventStream {
+    sender: broadcast::Sender<StreamEvent>,
+    history: Mutex<History>,
+    history_size: usize,
+}
+
+impl EventStream {
+    pub fn new(history_size: usize, channel_capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(channel_capacity);
+        Self {
+            sender,
+            history: Mutex::new(History {
+                next_id: 1,
+                events: VecDeque::with_capacity(history_size),
+            }),
+            history_size,
+        }
+    }
+
+    pub fn publish(&self, tenant_id: i32, event_type: &str, account_id: String, data: Value) {
+        // Ids are assigned and sent under the lock, so a subscriber never sees
+        // an event both in its backlog and on its receiver.
+        let mut history = self.history.lock().unwrap();
+        let event = StreamEvent {
+            id: history.next_id,
+            tenant_id,
+            event_type: event_type.to_string(),
+            account_id,
+            data,
+        };
+        history.next_id += 1;
+        if history.events.len() == self.history_size {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+
+        // An error only means nobody is listening right now.
+        let _ = self.sender.send(event);
+    }
+
+    /// Events of `tenant_id` matching `filter`, the tenant must be the
+    /// authenticated one.
+    pub fn subscribe(
+        &self,
+        tenant_id: i32,
+        filter: StreamFilter,
+    ) -> impl Stream<Item = StreamItem> + Send {
+        let history = self.history.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let backlog: Vec<StreamEvent> = match filter.since {
+            Some(since) => history
+                .events
+                .iter()
+                .filter(|e| e.id > since && filter.matches(tenant_id, e))
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        drop(history);
+
+        let live = BroadcastStream::new(receiver).filter_map(move |result| {
+            let item = match result {
+                Ok(event) if filter.matches(tenant_id, &event) => Some(StreamItem::Event(event)),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(StreamItem::Lagged(skipped)),
+            };
+            async move { item }
+        });
+        stream::iter(backlog.into_iter().map(StreamItem::Event)).chain(live)
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new(
+            SETTINGS.stream.history_size,
+            SETTINGS.stream.channel_capacity,
+        )
+    }
+}
+
+pub fn sse_events(
+    items: impl Stream<Item = StreamItem> + Send,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send {
+    items.map(|item| Ok(item.to_sse()))
+}
+
+// Forwards stream items as JSON text frames until either side goes away.
+pub async fn forward_to_socket(mut socket: WebSocket, items: impl Stream<Item = StreamItem>) {
+    let mut items = std::pin::pin!(items);
+    loop {
+        tokio::select! {
+            item = items.next() => {
+                let Some(item) = item else { break };
+                let text = item.to_json().to_string();
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_event(item: Option<StreamItem>) -> StreamEvent {
+        match item {
+            Some(StreamItem::Event(event)) => event,
+            other => panic!("unexpected item: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_published_events() {
+        let events = EventStream::new(10, 10);
+        let mut stream = Box::pin(events.subscribe(1, StreamFilter::default()));
+
+        events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        let event = next_event(stream.next().await);
+        assert_eq!(event.id, 1);
+        assert_eq!(event.event_type, BALANCE_UPDATED);
+        assert_eq!(event.account_id, "account1");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_filters_by_account() {
+        let events = EventStream::new(10, 10);
+        let filter = StreamFilter {
+            account_id: Some("account2".to_string()),
+            since: None,
+        };
+        let mut stream = Box::pin(events.subscribe(1, filter));
+
+        events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        events.publish(1, ACCOUNT_STATUS, "account2".to_string(), json!({}));
+        let event = next_event(stream.next().await);
+        assert_eq!(event.id, 2);
+        assert_eq!(event.account_id, "account2");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_filters_by_tenant() {
+        let events = EventStream::new(10, 10);
+        events.publish(2, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        let filter = StreamFilter {
+            account_id: None,
+            since: Some(0),
+        };
+        let mut stream = Box::pin(events.subscribe(1, filter));
+
+        // Neither buffered nor live events of another tenant are delivered.
+        events.publish(2, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        events.publish(1, BALANCE_UPDATED, "account2".to_string(), json!({}));
+        let event = next_event(stream.next().await);
+        assert_eq!(event.id, 3);
+        assert_eq!(event.tenant_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_resumes_from_since() {
+        let events = EventStream::new(2, 10);
+        for _ in 0..3 {
+            events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        }
+
+        // Only the last two events are buffered.
+        let filter = StreamFilter {
+            account_id: None,
+            since: Some(0),
+        };
+        let mut stream = Box::pin(events.subscribe(1, filter));
+        assert_eq!(next_event(stream.next().await).id, 2);
+        assert_eq!(next_event(stream.next().await).id, 3);
+
+        events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        assert_eq!(next_event(stream.next().await).id, 4);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_reports_lag() {
+        let events = EventStream::new(10, 1);
+        let mut stream = Box::pin(events.subscribe(1, StreamFilter::default()));
+        events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+        events.publish(1, BALANCE_UPDATED, "account1".to_string(), json!({}));
+
+        assert!(matches!(stream.next().await, Some(StreamItem::Lagged(1))));
+        assert_eq!(next_event(stream.next().await).id, 2);
+    }
+}
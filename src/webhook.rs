@@ -0,0 +1,243 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use sha2::Sha256;
+use tokio::net::lookup_host;
+
+use crate::{
+    common::backoff,
+    configs::settings::SETTINGS,
+    domain::webhook::{validate_url, PendingWebhookDelivery},
+};
+
+pub const SIGNATURE_HDR: &str = "X-Bankie-Signature";
+pub const EVENT_HDR: &str = "X-Bankie-Event";
+pub const DELIVERY_HDR: &str = "X-Bankie-Delivery";
+
+/// Sign `{timestamp}.{body}` with the endpoint secret, so receivers can verify
+/// both the payload and its freshness. The header value looks like
+/// `t=1724300000,v1=5257a869e7...`.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Time of the next attempt after `attempts` failed deliveries, doubling the
+/// wait each time, or `None` once the delivery should be dead-lettered.
+pub fn next_attempt_at(attempts: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let settings = &SETTINGS.webhook;
+    backoff::next_attempt_at(
+        attempts,
+        settings.max_attempts,
+        settings.backoff_base_secs,
+        now,
+    )
+}
+
+/// Whether `ip` is reachable from the internet. Webhooks are never sent to
+/// loopback, private, link-local (cloud metadata) or other reserved addresses.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 0.0.0.0/8, shared address space 100.64.0.0/10, 240.0.0.0/4
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// Addresses `host` resolves to, an error when any of them is not public.
+pub async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>, anyhow::Error> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, 0)).await?.collect();
+    if addrs.is_empty() {
+        return Err(anyhow!("{} has no address", host));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(anyhow!("{} resolves to {}", host, addr.ip()));
+    }
+    Ok(addrs)
+}
+
+/// Refuses an endpoint whose host resolves into a non-public network. A host
+/// that does not resolve yet is accepted, every delivery checks it again.
+pub async fn check_endpoint_host(url: &str) -> Result<(), String> {
+    let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    else {
+        return Err("url is invalid".to_string());
+    };
+    let Ok(mut addrs) = lookup_host((host.as_str(), 0)).await else {
+        return Ok(());
+    };
+    match addrs.find(|addr| !is_public(addr.ip())) {
+        Some(addr) => Err(format!(
+            "url resolves to a non-public address: {}",
+            addr.ip()
+        )),
+        None => Ok(()),
+    }
+}
+
+// Resolves delivery hosts at connect time, so a name pointed at an internal
+// address after it was registered is still refused.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+// Redirects are not followed, an endpoint could bounce the request inward.
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(SETTINGS.webhook.timeout_secs))
+        .redirect(Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("Failed to build webhook client")
+}
+
+pub async fn deliver(
+    client: &reqwest::Client,
+    delivery: &PendingWebhookDelivery,
+) -> Result<(), anyhow::Error> {
+    // Endpoints registered before URLs were checked may name an IP address,
+    // which the resolver never sees.
+    validate_url(&delivery.url).map_err(|e| anyhow!(e))?;
+    let body = delivery.payload.to_string();
+    let signature = sign_payload(&delivery.secret, Utc::now().timestamp(), &body);
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HDR, signature)
+        .header(EVENT_HDR, &delivery.event_type)
+        .header(DELIVERY_HDR, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Endpoint responded with {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("secret", 1724300000, r#"{"type":"ledger.updated"}"#);
+        assert!(signature.starts_with("t=1724300000,v1="));
+
+        // Same input gives the same signature, any change in body does not.
+        assert_eq!(
+            signature,
+            sign_payload("secret", 1724300000, r#"{"type":"ledger.updated"}"#)
+        );
+        assert_ne!(
+            signature,
+            sign_payload("secret", 1724300000, r#"{"type":"ledger.updatex"}"#)
+        );
+        assert_ne!(
+            signature,
+            sign_payload("other", 1724300000, r#"{"type":"ledger.updated"}"#)
+        );
+    }
+
+    #[test]
+    fn test_next_attempt_at_backoff() {
+        let now = NaiveDate::from_ymd_opt(2024, 8, 22)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let base = SETTINGS.webhook.backoff_base_secs;
+
+        assert_eq!(
+            next_attempt_at(1, now),
+            Some(now + chrono::Duration::seconds(base))
+        );
+        assert_eq!(
+            next_attempt_at(3, now),
+            Some(now + chrono::Duration::seconds(base * 4))
+        );
+    }
+
+    #[test]
+    fn test_next_attempt_at_dead_letter() {
+        let now = Utc::now().naive_utc();
+        let max_attempts = SETTINGS.webhook.max_attempts;
+
+        assert!(next_attempt_at(max_attempts - 1, now).is_some());
+        assert!(next_attempt_at(max_attempts, now).is_none());
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_internal_hosts_are_refused() {
+        assert!(resolve_public("localhost").await.is_err());
+        assert!(check_endpoint_host("https://localhost/hooks")
+            .await
+            .is_err());
+    }
+}